@@ -1,61 +1,696 @@
 //! Add inputs to the library.
 
-use crate::{scrape, types, util, Config};
+use crate::util::PromptOption::{No, Yes};
+use crate::{scrape, types, util, Config, TapewormError};
+use audiotags::Tag;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::thread;
 use url::Url;
 
+/// The maximum number of pages (each in its own headless browser) to scrape at once.
+const MAX_CONCURRENT_SCRAPES: usize = 4;
+
+/// Marks the URL that the inputs below it were scraped from, see `refresh`.
+pub const SOURCE_PREFIX: &str = "# source: ";
+
+/// How similar (see `util::similarity`) a query must be to a file in the library to be considered
+/// a likely duplicate, see `config.check_duplicates`.
+const DUPLICATE_SIMILARITY: f64 = 0.6;
+
+pub type Scraper = fn(&str, &Config) -> types::VecStringResult;
+
+/// The format of an input list, see `parse_structured`.
+#[derive(Debug, Default, PartialEq)]
+pub enum InputFormat {
+    /// One URL or query per line
+    #[default]
+    Lines,
+    /// `artist,title` rows
+    Csv,
+    /// An array of strings (URLs or queries) and/or objects with a `title` and optional `artist`
+    Json,
+}
+
+impl InputFormat {
+    pub fn from(s: &str) -> Result<Self, TapewormError> {
+        match s.to_lowercase().as_str() {
+            "lines" => Ok(Self::Lines),
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Invalid input format: '{}'. See 'help'", s).into()),
+        }
+    }
+}
+
 /// Attempts to append all terms to the input file.
 /// The input file is created if it does not exist.
-pub fn run(config: &Config) -> types::UnitResult {
-    util::append(
+///
+/// The terms in `config.term_files` follow the terms given. With a structured `config.input_format`,
+/// the terms are files to read the inputs from instead.
+/// Inputs that contain a term of the blocklist are skipped, as are inputs that are in the input
+/// file already, unless `config.allow_duplicates`.
+pub fn run<R: BufRead>(config: &Config, reader: R) -> types::UnitResult {
+    let mut terms = config.terms.clone().unwrap();
+    let inputs = if config.input_format == InputFormat::Lines {
+        for file in &config.term_files {
+            terms.extend(terms_in(&util::read_text_file(file, config.encoding)?));
+        }
+        parse(&terms, config, reader)
+    } else {
+        terms.extend(config.term_files.iter().cloned());
+        let mut inputs = Vec::new();
+        for file in &terms {
+            inputs.extend(parse_structured(
+                &util::read_text_file(file, config.encoding)?,
+                config,
+            )?);
+        }
+        inputs.join("\n")
+    };
+    let inputs = without_blocked(&inputs, config);
+    let inputs = if config.allow_duplicates {
+        inputs
+    } else {
+        let input_path = config.input_path.as_ref().unwrap();
+        let existing = util::read_text_file(input_path, config.encoding).unwrap_or_default();
+        without_duplicates(&inputs, &existing)
+    };
+    if inputs.is_empty() {
+        return Ok(());
+    }
+    util::append_text_file(
         config.input_path.as_ref().unwrap(),
-        format!("{}\n", parse(config.terms.as_ref().unwrap())), // \n needed for next append
+        format!("{}\n", inputs), // \n needed for next append
+        config.encoding,
     )
 }
 
-fn parse(terms: &Vec<String>) -> String {
-    let mut inputs: Vec<String> = Vec::new();
-    for term in terms {
+/// # Returns
+/// The lines of `inputs` that do not contain a term of `config.blocklist`. Comments are kept.
+fn without_blocked(inputs: &str, config: &Config) -> String {
+    inputs
+        .lines()
+        .filter(|input| match util::blocked(config, input) {
+            Some(term) if !input.starts_with('#') => {
+                println!("Skipping blocked {} (matches '{}')", input, term);
+                false
+            }
+            _ => true,
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// # Returns
+/// The terms in the `contents` of a terms file: each line that is neither blank nor a comment.
+fn terms_in(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// # Returns
+/// The lines of `inputs` that are neither in the `existing` inputs, nor earlier in `inputs`. Search
+/// queries are compared case-insensitively, URLs exactly. Blank lines are dropped, and comments are
+/// kept, except for a `# source:` line of which none of the inputs below it are kept, so re-adding
+/// a playlist that is queued already adds nothing.
+fn without_duplicates(inputs: &str, existing: &str) -> String {
+    let key = |input: &str| {
+        if input.starts_with("ytsearch") {
+            input.to_lowercase()
+        } else {
+            String::from(input)
+        }
+    };
+    let mut seen = existing
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.starts_with('#'))
+        .map(key)
+        .collect::<HashSet<String>>();
+
+    let mut kept = Vec::new();
+    // The source line and the comments after it, until an input below it is kept
+    let mut pending: Vec<&str> = Vec::new();
+    for input in inputs.lines().map(|line| line.trim()) {
+        if input.is_empty() {
+            continue;
+        }
+        if input.starts_with(SOURCE_PREFIX) {
+            kept.extend(pending.drain(..).skip(1));
+            pending.push(input);
+        } else if input.starts_with('#') {
+            if pending.is_empty() {
+                kept.push(input);
+            } else {
+                pending.push(input);
+            }
+        } else if seen.insert(key(input)) {
+            kept.append(&mut pending);
+            kept.push(input);
+        } else {
+            println!("Skipping duplicate {}", input);
+        }
+    }
+    kept.extend(pending.drain(..).skip(1));
+    kept.join("\n")
+}
+
+/// Convert the terms into inputs, preserving their order. Scrapeable URLs are scraped
+/// concurrently, at most `MAX_CONCURRENT_SCRAPES` at a time. The results of a scrape are preceded
+/// by a `SOURCE_PREFIX` comment line, so that the source can be refreshed later.
+///
+/// When a scrape yields more than `config.scrape_warn_threshold` results, they are only added upon
+/// confirmation (unless `config.auto_add` is set). With `config.check_duplicates`, the same goes for
+/// queries that look like a file in the library already.
+fn parse<R: BufRead>(terms: &[String], config: &Config, mut reader: R) -> String {
+    let mut inputs: Vec<Vec<String>> = vec![Vec::new(); terms.len()];
+    let mut to_scrape = Vec::new();
+    let library = if config.check_duplicates {
+        library_songs(config)
+    } else {
+        Vec::new()
+    };
+    for (i, term) in terms.iter().enumerate() {
         if let Ok(url) = Url::parse(term) {
-            inputs.extend(scrape(url));
+            if let Some(scraper) = scraper_for(&url) {
+                to_scrape.push((i, url, scraper));
+            } else {
+                inputs[i].push(bare_video_url(&url).to_string());
+            }
+        } else if confirm_unique(term, &library, config, &mut reader) {
+            inputs[i].push(format!("{}{}", search_prefix(config), term));
         } else {
-            inputs.push(format!("ytsearch:{}", term));
+            println!("Skipping {}", term);
+        }
+    }
+
+    for batch in to_scrape.chunks(MAX_CONCURRENT_SCRAPES) {
+        thread::scope(|s| {
+            let handles = batch
+                .iter()
+                .map(|(i, url, scraper)| (*i, url, s.spawn(move || scrape(url, *scraper, config))))
+                .collect::<Vec<_>>();
+            for (i, url, handle) in handles {
+                match handle.join() {
+                    Ok(results) if !results.is_empty() => {
+                        if !confirm_results(url, results.len(), config, &mut reader) {
+                            println!("Skipping {}", url.as_str());
+                            continue;
+                        }
+                        inputs[i].push(format!("{}{}", SOURCE_PREFIX, url));
+                        inputs[i].extend(results);
+                    }
+                    // yt-dlp can still download the playlist as a whole
+                    Ok(_) if is_youtube_playlist(url) => inputs[i].push(url.to_string()),
+                    Ok(_) => {}
+                    Err(_) => println!("Error scraping {}\nSkipping...", url.as_str()),
+                }
+            }
+        });
+    }
+
+    inputs.concat().join("\n")
+}
+
+/// Convert the `contents` of a structured input list (see `config.input_format`) into inputs.
+/// Each `artist,title` CSV row, or JSON object with a `title` (and optional `artist`), becomes a
+/// search query. JSON strings are used as is if they are URLs, or else searched for. A CSV header
+/// row is skipped, and malformed rows or entries are reported and skipped.
+///
+/// # Errors
+/// - If the JSON is invalid, or not an array
+pub fn parse_structured(contents: &str, config: &Config) -> Result<Vec<String>, TapewormError> {
+    let query = |artist: &str, title: &str| {
+        let term = format!("{} {}", artist.trim(), title.trim());
+        format!("{}{}", search_prefix(config), term.trim())
+    };
+
+    let mut inputs = Vec::new();
+    match config.input_format {
+        InputFormat::Lines => inputs.extend(contents.lines().map(String::from)),
+        InputFormat::Csv => {
+            for (i, line) in contents.lines().enumerate() {
+                if line.trim().is_empty() || (i == 0 && is_csv_header(line)) {
+                    continue;
+                }
+                match csv_fields(line).as_deref() {
+                    Some([artist, title]) if !title.trim().is_empty() => {
+                        inputs.push(query(artist, title))
+                    }
+                    _ => println!(
+                        "! Skipping row {}, expected 'artist,title': {}",
+                        i + 1,
+                        line
+                    ),
+                }
+            }
+        }
+        InputFormat::Json => {
+            let entries = match serde_json::from_str(contents)? {
+                Value::Array(entries) => entries,
+                _ => return Err("Expected a JSON array of inputs".into()),
+            };
+            for (i, entry) in entries.iter().enumerate() {
+                let title = entry.get("title").and_then(Value::as_str);
+                let artist = entry.get("artist").and_then(Value::as_str);
+                match (entry.as_str(), title) {
+                    (Some(term), _) if !term.trim().is_empty() => match Url::parse(term.trim()) {
+                        Ok(url) => inputs.push(bare_video_url(&url).to_string()),
+                        Err(_) => inputs.push(query("", term)),
+                    },
+                    (None, Some(title)) if !title.trim().is_empty() => {
+                        inputs.push(query(artist.unwrap_or_default(), title))
+                    }
+                    _ => println!(
+                        "! Skipping entry {}, expected a string or title: {}",
+                        i + 1,
+                        entry
+                    ),
+                }
+            }
         }
     }
-    inputs.join("\n")
+    Ok(inputs)
+}
+
+fn is_csv_header(line: &str) -> bool {
+    csv_fields(line).is_some_and(|fields| {
+        let fields: Vec<String> = fields.iter().map(|f| f.trim().to_lowercase()).collect();
+        fields == ["artist", "title"]
+    })
 }
 
-/// If `url` is scrapeable, return a list of scraped queries from that page.
-/// Otherwise, return `url` as a single item in the list.
-fn scrape(url: Url) -> Vec<String> {
-    let mut results = Vec::new();
+/// Split a CSV `line` into its fields. Fields may be quoted, e.g. `"Artist, The",Song`, with `""`
+/// for a quote inside a quoted field.
+///
+/// # Returns
+/// `None` if a quoted field is not terminated
+fn csv_fields(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields)
+}
+
+/// Ask whether to add the `count` results scraped from `url`, if there are more than
+/// `config.scrape_warn_threshold` (0 means no limit). With `config.auto_add`, the results are
+/// always added, but the count is still reported.
+fn confirm_results<R: BufRead>(url: &Url, count: usize, config: &Config, reader: R) -> bool {
+    let threshold = config.scrape_warn_threshold;
+    if threshold == 0 || count <= threshold {
+        return true;
+    }
+    if config.auto_add {
+        println!("Adding {} results from {}", count, url.as_str());
+        return true;
+    }
+
+    let prompt = format!(
+        "! {} yielded {} results (more than {})\nAdd them all?",
+        url.as_str(),
+        count,
+        threshold
+    );
+    matches!(util::select(&prompt, vec![Yes, No], No, reader), Ok(Yes))
+}
+
+/// Ask whether to add the `query` if it is similar to one of the `library` songs (see
+/// `library_songs`). With `config.auto_add`, the query is always added, but the likely duplicate
+/// is still reported.
+fn confirm_unique<R: BufRead>(
+    query: &str,
+    library: &[(PathBuf, String)],
+    config: &Config,
+    reader: R,
+) -> bool {
+    let closest = library
+        .iter()
+        .map(|(file, song)| (file, util::similarity(query, song)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+    let (file, similarity) = match closest {
+        Some((file, similarity)) if similarity >= DUPLICATE_SIMILARITY => (file, similarity),
+        _ => return true,
+    };
+
+    let warning = format!(
+        "! '{}' looks like {} ({:.0}% similar)",
+        query,
+        file.display(),
+        similarity * 100.0
+    );
+    if config.auto_add {
+        println!("{}", warning);
+        return true;
+    }
+    let prompt = format!("{}\nAdd it anyway?", warning);
+    matches!(util::select(&prompt, vec![Yes, No], No, reader), Ok(Yes))
+}
+
+/// # Returns
+/// Each audio file in the target directory (and its subfolders, except `.tapeworm`) that has a
+/// 'title' tag, along with its `ARTIST - TITLE`. On failure, the error is printed and an empty list
+/// is returned.
+fn library_songs(config: &Config) -> Vec<(PathBuf, String)> {
+    let target_dir = config.target_dir.as_ref().unwrap();
+    let files = match util::filepaths_in_recursive(target_dir, &config.lib_dir) {
+        Ok(files) => files,
+        Err(e) => {
+            println!("Error checking the library for duplicates: {}", e);
+            return Vec::new();
+        }
+    };
+    files
+        .into_iter()
+        .filter_map(|file| {
+            let ftag = Tag::new().read_from_path(&file).ok()?;
+            let title = ftag.title()?;
+            let song = format!("{} - {}", ftag.artist().unwrap_or_default(), title);
+            Some((file, song))
+        })
+        .collect()
+}
+
+/// # Returns
+/// - `Some(fn)`: the scraper to use if `url` is scrapeable
+/// - `None`: if `url` should be added as is
+pub fn scraper_for(url: &Url) -> Option<Scraper> {
     match url.host_str() {
         Some("open.spotify.com") if url.path().starts_with("/playlist") => {
-            match scrape::spotify_playlist(url.as_str()) {
-                Ok(list) => list.iter().for_each(|query| {
-                    results.push(format!("ytsearch:{}", query));
-                }),
-                Err(e) => println!("Error scraping {}: {}\nSkipping...", url.as_str(), e),
-            }
+            Some(scrape::spotify_playlist)
+        }
+        Some("soundcloud.com" | "www.soundcloud.com") if url.path().contains("/sets/") => {
+            Some(scrape::soundcloud_set)
+        }
+        _ if is_youtube_playlist(url) => Some(scrape::youtube_playlist),
+        _ => None,
+    }
+}
+
+fn is_youtube(url: &Url) -> bool {
+    matches!(
+        url.host_str(),
+        Some("youtube.com" | "www.youtube.com" | "m.youtube.com" | "music.youtube.com")
+    )
+}
+
+fn is_youtube_playlist(url: &Url) -> bool {
+    is_youtube(url) && url.path() == "/playlist"
+}
+
+/// Strip the playlist parameters (e.g. `list` and `index`) from a YouTube video `url`, so that
+/// yt-dlp only downloads the video. Other URLs are returned as is.
+pub fn bare_video_url(url: &Url) -> Url {
+    if !is_youtube(url) || url.path() != "/watch" || !url.query_pairs().any(|(k, _)| k == "list") {
+        return url.clone();
+    }
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key == "v" || key == "t")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    let mut bare = url.clone();
+    bare.query_pairs_mut().clear().extend_pairs(kept);
+    bare
+}
+
+/// The ID of the YouTube video at `url`, e.g. `https://youtu.be/ID`, `https://www.youtube.com/watch?v=ID`
+/// or `https://www.youtube.com/shorts/ID`.
+pub fn youtube_video_id(url: &Url) -> Option<String> {
+    if url.host_str() == Some("youtu.be") {
+        return url.path_segments()?.next().map(String::from);
+    }
+    if !is_youtube(url) {
+        return None;
+    }
+    match url.path() {
+        "/watch" => url
+            .query_pairs()
+            .find(|(key, _)| key == "v")
+            .map(|(_, value)| value.into_owned()),
+        path => path.strip_prefix("/shorts/").map(String::from),
+    }
+    .filter(|id| !id.is_empty())
+}
+
+/// The yt-dlp search prefix for `config.search_results`, e.g. `ytsearch3:` to download the top 3
+/// results. 0 means all results (`ytsearchall:`), 1 (the default) only the first (`ytsearch:`).
+pub fn search_prefix(config: &Config) -> String {
+    match config.search_results {
+        0 => String::from("ytsearchall:"),
+        1 => String::from("ytsearch:"),
+        n => format!("ytsearch{}:", n),
+    }
+}
+
+/// Scrape the page at `url`, and return a list of the scraped queries, with the search prefix of
+/// `config.search_results`. On failure, the error is printed and an empty list is returned.
+pub fn scrape(url: &Url, scraper: Scraper, config: &Config) -> Vec<String> {
+    match scraper(url.as_str(), config) {
+        Ok(list) => list
+            .iter()
+            .map(|query| match Url::parse(query) {
+                Ok(_) => query.clone(), // E.g. the videos of a YouTube playlist
+                Err(_) => format!("{}{}", search_prefix(config), query),
+            })
+            .collect(),
+        Err(e) => {
+            println!("Error scraping {}: {}\nSkipping...", url.as_str(), e);
+            Vec::new()
         }
-        _ => results.push(url.to_string()),
     }
-    results
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io;
+
+    #[test]
+    fn skips_duplicate_inputs() {
+        let existing = "ytsearch:Darude Sandstorm\nhttps://youtu.be/abc\n";
+        let inputs = "ytsearch:darude sandstorm\nhttps://youtu.be/ABC\nhttps://youtu.be/abc\nytsearch:New\nytsearch:NEW";
+        assert_eq!(
+            without_duplicates(inputs, existing),
+            "https://youtu.be/ABC\nytsearch:New"
+        );
+        assert_eq!(without_duplicates(existing, ""), existing.trim_end());
+
+        let source = "# source: https://open.spotify.com/playlist/123";
+        let existing = format!("{}\nytsearch:A\n", source);
+        let inputs = format!("{}\nytsearch:A\nytsearch:B", source);
+        assert_eq!(
+            without_duplicates(&inputs, &existing),
+            format!("{}\nytsearch:B", source)
+        );
+        // Without any new inputs, the source line is left out as well
+        let inputs = format!(
+            "# note\n{}\n# all queued\nytsearch:a\n{}\nytsearch:C",
+            source, source
+        );
+        assert_eq!(
+            without_duplicates(&inputs, &existing),
+            format!("# note\n# all queued\n{}\nytsearch:C", source)
+        );
+        assert_eq!(
+            without_duplicates(&format!("{}\nytsearch:A", source), &existing),
+            ""
+        );
+    }
+
+    #[test]
+    fn confirms_large_scrapes() {
+        let url = Url::parse("https://open.spotify.com/playlist/123").unwrap();
+        let mut config = Config::default();
+        config.scrape_warn_threshold = 2;
+
+        assert!(confirm_results(&url, 2, &config, io::empty()));
+        assert!(!confirm_results(&url, 3, &config, io::empty())); // Defaults to 'no'
+        assert!(!confirm_results(&url, 3, &config, "n\n".as_bytes()));
+        assert!(confirm_results(&url, 3, &config, "y\n".as_bytes()));
+
+        config.auto_add = true;
+        assert!(confirm_results(&url, 3, &config, io::empty()));
+
+        config.auto_add = false;
+        config.scrape_warn_threshold = 0;
+        assert!(confirm_results(&url, 1000, &config, io::empty()));
+    }
+
+    #[test]
+    fn confirms_likely_duplicates() {
+        let library = vec![(PathBuf::from("a.mp3"), String::from("Artist - Song"))];
+        let mut config = Config::default();
+
+        assert!(confirm_unique(
+            "Other - Tune",
+            &library,
+            &config,
+            io::empty()
+        ));
+        assert!(!confirm_unique(
+            "artist song",
+            &library,
+            &config,
+            io::empty()
+        )); // Defaults to 'no'
+        assert!(confirm_unique(
+            "artist song",
+            &library,
+            &config,
+            "y\n".as_bytes()
+        ));
+
+        config.auto_add = true;
+        assert!(confirm_unique(
+            "artist song",
+            &library,
+            &config,
+            io::empty()
+        ));
+    }
+
+    #[test]
+    fn parses_csv_inputs() {
+        let mut config = Config::default();
+        config.input_format = InputFormat::Csv;
+        let csv = "artist,title\nBand,Song\n\"Artist, The\",\"A \"\"Quoted\"\" Song\"\nonly one\n,Untitled\n";
+        assert_eq!(
+            parse_structured(csv, &config).unwrap(),
+            vec![
+                "ytsearch:Band Song",
+                "ytsearch:Artist, The A \"Quoted\" Song",
+                "ytsearch:Untitled"
+            ]
+        );
+        assert_eq!(csv_fields("a,\"b"), None);
+    }
+
+    #[test]
+    fn parses_json_inputs() {
+        let mut config = Config::default();
+        config.input_format = InputFormat::Json;
+        let json = r#"[
+            "Darude Sandstorm",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PL123",
+            { "artist": "Band", "title": "Song" },
+            { "title": "Untitled" },
+            { "artist": "Band" },
+            42
+        ]"#;
+        assert_eq!(
+            parse_structured(json, &config).unwrap(),
+            vec![
+                "ytsearch:Darude Sandstorm",
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+                "ytsearch:Band Song",
+                "ytsearch:Untitled"
+            ]
+        );
+        assert!(parse_structured(r#"{ "title": "Song" }"#, &config).is_err());
+        assert!(parse_structured("[", &config).is_err());
+    }
+
+    #[test]
+    fn finds_scrapers() {
+        let scrapes = |url: &str| scraper_for(&Url::parse(url).unwrap()).is_some();
+        assert!(scrapes("https://open.spotify.com/playlist/123"));
+        assert!(scrapes("https://soundcloud.com/user/sets/some-set"));
+        assert!(!scrapes("https://soundcloud.com/user/some-track"));
+        assert!(!scrapes("https://www.youtube.com/watch?v=dQw4w9WgXcQ"));
+        assert!(scrapes("https://www.youtube.com/playlist?list=PL123"));
+    }
+
+    #[test]
+    fn prefixes_scraped_queries() {
+        fn scraper(_: &str, _: &Config) -> types::VecStringResult {
+            Ok(vec![String::from("Band - Song")])
+        }
+        let url = Url::parse("https://open.spotify.com/playlist/123").unwrap();
+        let mut config = Config::default();
+        config.search_results = 3;
+        assert_eq!(
+            scrape(&url, scraper, &config),
+            vec!["ytsearch3:Band - Song"]
+        );
+    }
+
+    #[test]
+    fn strips_playlist_from_video_urls() {
+        let bare = |url: &str| bare_video_url(&Url::parse(url).unwrap()).to_string();
+        assert_eq!(
+            bare("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PL123&index=2"),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        );
+        assert_eq!(
+            bare("https://youtube.com/watch?list=PL123&v=dQw4w9WgXcQ&t=42"),
+            "https://youtube.com/watch?v=dQw4w9WgXcQ&t=42"
+        );
+        let untouched = "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42";
+        assert_eq!(bare(untouched), untouched);
+        let untouched = "https://example.com/watch?v=1&list=2";
+        assert_eq!(bare(untouched), untouched);
+    }
 
     #[test]
     fn parses_terms() {
         let terms = vec![String::from("Darude"), String::from("Sandstorm")];
         assert_eq!(
-            parse(&terms),
+            parse(&terms, &Config::default(), io::empty()),
             String::from("ytsearch:Darude\nytsearch:Sandstorm")
         );
 
         let terms = vec![String::from("Darude Sandstorm")];
-        assert_eq!(parse(&terms), String::from("ytsearch:Darude Sandstorm"));
+        assert_eq!(
+            parse(&terms, &Config::default(), io::empty()),
+            String::from("ytsearch:Darude Sandstorm")
+        );
+    }
+
+    #[test]
+    fn parses_terms_with_search_results() {
+        let terms = vec![
+            String::from("Darude"),
+            String::from("https://www.youtube.com/watch?v=y6120QOlsfU"),
+        ];
+        let mut config = Config::default();
+        config.search_results = 3;
+        assert_eq!(
+            parse(&terms, &config, io::empty()),
+            String::from("ytsearch3:Darude\nhttps://www.youtube.com/watch?v=y6120QOlsfU")
+        );
+
+        config.search_results = 0;
+        assert_eq!(
+            parse(&terms[..1], &config, io::empty()),
+            String::from("ytsearchall:Darude")
+        );
     }
 
     #[test]
@@ -65,7 +700,7 @@ mod tests {
             String::from("https://www.youtube.com/watch?v=y6120QOlsfU"),
         ];
         assert_eq!(
-            parse(&terms),
+            parse(&terms, &Config::default(), io::empty()),
             String::from(
                 "\
 https://www.youtube.com/watch?v=dQw4w9WgXcQ
@@ -83,7 +718,7 @@ https://www.youtube.com/watch?v=y6120QOlsfU"
             String::from("https://www.youtube.com/watch?v=y6120QOlsfU"),
         ];
         assert_eq!(
-            parse(&terms),
+            parse(&terms, &Config::default(), io::empty()),
             String::from(
                 "\
 https://www.youtube.com/watch?v=dQw4w9WgXcQ
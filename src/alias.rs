@@ -9,6 +9,7 @@ pub fn run(config: &Config) -> types::UnitResult {
     }
 
     let mut new_aliases = config.aliases.clone();
+    let mut commands = config.alias_commands.clone();
     let remove_or_alias = config.terms.as_ref().unwrap().get(0).unwrap();
     if remove_or_alias == "-r" {
         // When invoking `tapeworm ALIAS alias -r`, remove just that ALIAS
@@ -19,10 +20,56 @@ pub fn run(config: &Config) -> types::UnitResult {
     } else {
         // When invoking `tapeworm ALIAS_OR_PATH alias ALIAS`
         let alias = remove_or_alias.to_owned();
+        if crate::GENERAL_OPTIONS.contains(&alias.to_lowercase().as_str()) {
+            return Err(format!("Alias cannot be named '{}', as it is an option", alias).into());
+        }
         let path = config.lib_path.clone().unwrap();
+        // A renamed alias keeps its command
+        if let Some(command) = config.lib_alias.as_ref().and_then(|a| commands.remove(a)) {
+            commands.insert(alias.clone(), command);
+        }
         add_alias(&mut new_aliases, &config.lib_alias, alias, path);
     }
-    write(new_aliases, &config.general_conf)
+
+    let changes = diff(&config.aliases, &new_aliases);
+    if changes.is_empty() {
+        println!("No changes to aliases");
+        return Ok(());
+    }
+    changes.iter().for_each(|change| println!("{}", change));
+    if config.dry_run {
+        return Ok(());
+    }
+    write(
+        &config.general_options,
+        new_aliases,
+        &commands,
+        &config.general_conf,
+    )
+}
+
+/// # Returns
+/// `Vec<String>`: a line per added (`+`), removed (`-`), or changed (`~`) alias
+fn diff(old: &BTreeMap<String, PathBuf>, new: &BTreeMap<String, PathBuf>) -> Vec<String> {
+    let mut changes = Vec::new();
+    for (alias, path) in old {
+        match new.get(alias) {
+            None => changes.push(format!("- {}={}", alias, path.display())),
+            Some(new_path) if new_path != path => changes.push(format!(
+                "~ {}={} -> {}",
+                alias,
+                path.display(),
+                new_path.display()
+            )),
+            _ => {}
+        }
+    }
+    for (alias, path) in new {
+        if !old.contains_key(alias) {
+            changes.push(format!("+ {}={}", alias, path.display()));
+        }
+    }
+    changes
 }
 
 fn show_aliases(config: &Config) {
@@ -39,9 +86,23 @@ fn show_aliases(config: &Config) {
     }
 }
 
-fn write(aliases: BTreeMap<String, PathBuf>, path: &PathBuf) -> types::UnitResult {
-    let content = aliases.iter().fold(String::new(), |acc, (alias, path)| {
-        format!("{}{}={}\n", acc, alias, path.to_str().unwrap())
+/// Write the `aliases` (along with their `commands`, if any) to the general config at `path`,
+/// keeping its `options`.
+fn write(
+    options: &[(String, String)],
+    aliases: BTreeMap<String, PathBuf>,
+    commands: &BTreeMap<String, String>,
+    path: &PathBuf,
+) -> types::UnitResult {
+    let content = options.iter().fold(String::new(), |acc, (key, value)| {
+        format!("{}{}={}\n", acc, key, value)
+    });
+    let content = aliases.iter().fold(content, |acc, (alias, path)| {
+        let path = path.to_str().unwrap();
+        match commands.get(alias) {
+            Some(command) => format!("{}{}={};{}\n", acc, alias, path, command),
+            None => format!("{}{}={}\n", acc, alias, path),
+        }
     });
     util::write(path, content)
 }
@@ -103,6 +164,29 @@ mod tests {
         assert_eq!(aliases.get("test2"), Some(&PathBuf::from("test/library")));
     }
 
+    #[test]
+    fn diffs_aliases() {
+        let mut old = BTreeMap::new();
+        old.insert(String::from("keep"), PathBuf::from("keep/library"));
+        old.insert(String::from("move"), PathBuf::from("old/library"));
+        old.insert(String::from("remove"), PathBuf::from("remove/library"));
+
+        let mut new = old.clone();
+        new.insert(String::from("add"), PathBuf::from("add/library"));
+        new.insert(String::from("move"), PathBuf::from("new/library"));
+        new.remove("remove");
+
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                "~ move=old/library -> new/library",
+                "- remove=remove/library",
+                "+ add=add/library",
+            ]
+        );
+        assert!(diff(&old, &old).is_empty());
+    }
+
     #[test]
     fn removes_aliases_for_path() {
         let mut aliases = BTreeMap::new();
@@ -1,40 +1,93 @@
-use crate::{types, Config};
+use crate::{history, types, util, Config, TapewormError};
 use std::fs::{self, DirEntry};
 use std::path::PathBuf;
 
+/// Remove empty folders from the target directory. With `config.dry_run` (`--dry-run`), only
+/// print the folders that would be removed. With `config.backup` (`--backup`), the folders are first
+/// recreated in the backup folder, see `util::backup`.
 pub fn run(config: &Config) -> types::UnitResult {
-    remove_empty_folders(config.target_dir.as_ref().unwrap(), 0, config.verbose)
+    let target_dir = config.target_dir.as_ref().unwrap();
+    let mut removed = Vec::new();
+    if config.backup && !config.dry_run {
+        remove_empty_folders(target_dir, 0, &config.lib_dir, false, true, &mut removed)?;
+        if !removed.is_empty() {
+            let backup_dir = util::backup(&removed, config)?;
+            println!(
+                "Backed up {} folders to {}",
+                removed.len(),
+                backup_dir.display()
+            );
+            removed.clear();
+        }
+    }
+    remove_empty_folders(
+        target_dir,
+        0,
+        &config.lib_dir,
+        config.verbose,
+        config.dry_run,
+        &mut removed,
+    )?;
+    if config.dry_run {
+        removed
+            .iter()
+            .for_each(|f| println!("Would remove: {}", f.display()));
+        println!("Would remove {} empty folders", removed.len());
+    } else {
+        let summary = format!("{} empty folders removed", removed.len());
+        history::record(config, "clean", &summary);
+    }
+    Ok(())
 }
 
-/// Remove empty folders, except for ".tapeworm".
+/// Remove empty folders, except for the library config folder. A folder that only contains
+/// folders that are removed, is removed as well, except for the initial `root`.
 ///
 /// # Parameters
 /// - `root`: The folder to start from
 /// - `depth`: The current depth in the folder tree, must start at 0
+/// - `lib_dir`: The name of the library config folder (e.g. ".tapeworm"), which is kept
 /// - `verbose`: Whether to print removed directories
-fn remove_empty_folders(root: &PathBuf, depth: i8, verbose: bool) -> types::UnitResult {
+/// - `dry_run`: Whether to only collect the folders that would be removed, without removing them
+/// - `removed`: The (would-be) removed folders
+///
+/// # Returns
+/// Whether `root` was (or would be) removed
+fn remove_empty_folders(
+    root: &PathBuf,
+    depth: i8,
+    lib_dir: &str,
+    verbose: bool,
+    dry_run: bool,
+    removed: &mut Vec<PathBuf>,
+) -> Result<bool, TapewormError> {
     let entries = fs::read_dir(root)?
         .filter_map(|e| e.ok())
         .collect::<Vec<DirEntry>>();
-    if entries.is_empty() {
-        if verbose {
-            println!("Removing empty folder: {}", root.display());
+
+    // Only folders of which all entries are removed become empty
+    let mut empty = true;
+    for entry in &entries {
+        if entry.file_name() == lib_dir || !entry.file_type()?.is_dir() {
+            empty = false;
+            continue;
         }
-        fs::remove_dir(root)?;
-        if depth > 1 {
-            // Go back up (if not at the initial root) to check if the parent has now become empty
-            remove_empty_folders(&root.parent().unwrap().to_path_buf(), depth - 1, verbose)?;
+        let path = entry.path();
+        if !remove_empty_folders(&path, depth + 1, lib_dir, verbose, dry_run, removed)? {
+            empty = false;
         }
-        return Ok(());
     }
 
-    for entry in entries {
-        if entry.file_name() == ".tapeworm" {
-            continue;
-        }
-        if entry.file_type().unwrap().is_dir() {
-            remove_empty_folders(&entry.path(), depth + 1, verbose)?;
+    // The initial root is only removed when it was empty to begin with
+    if !empty || (depth == 0 && !entries.is_empty()) {
+        return Ok(false);
+    }
+    if !dry_run {
+        if verbose {
+            println!("Removing empty folder: {}", root.display());
         }
+        fs::remove_dir(root)?;
     }
-    Ok(())
+    removed.push(root.clone());
+    Ok(true)
 }
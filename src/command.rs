@@ -1,17 +1,29 @@
-use crate::types;
+use crate::{types, TapewormError};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Command {
     Help,
     List,
+    CheckAll,
     Alias,
     Show,
     Clean,
     Add,
+    Refresh,
+    Remove,
+    DedupeInputs,
+    PruneInputs,
     Download,
     Tag,
     Deposit,
     Process,
+    Rename,
+    Export,
+    Stats,
+    Undo,
+    History,
+    Verify,
+    TemplateTest,
 }
 
 impl Command {
@@ -19,27 +31,50 @@ impl Command {
         match s {
             "help" | "h" | "-h" | "--help" => Ok(Self::Help),
             "list" | "ls" | "l" => Ok(Self::List),
+            "check-all" => Ok(Self::CheckAll),
             "alias" => Ok(Self::Alias),
             "show" => Ok(Self::Show),
             "clean" => Ok(Self::Clean),
             "add" => Ok(Self::Add),
+            "refresh" => Ok(Self::Refresh),
+            "remove" => Ok(Self::Remove),
+            "dedupe-inputs" => Ok(Self::DedupeInputs),
+            "prune-inputs" => Ok(Self::PruneInputs),
             "download" => Ok(Self::Download),
             "tag" => Ok(Self::Tag),
             "deposit" => Ok(Self::Deposit),
             "process" => Ok(Self::Process),
-            _ => Err(format!("Unrecognized command: {}. See 'help'", s).into()),
+            "rename" => Ok(Self::Rename),
+            "export" => Ok(Self::Export),
+            "stats" => Ok(Self::Stats),
+            "history" => Ok(Self::History),
+            "undo" => Ok(Self::Undo),
+            "verify" => Ok(Self::Verify),
+            "template-test" => Ok(Self::TemplateTest),
+            _ => Err(TapewormError::UnknownCommand(String::from(s))),
         }
     }
 
     pub fn uses_lib_conf(&self) -> bool {
         match self {
+            Self::Add => true,
             Self::Alias => true,
             Self::Clean => true,
+            Self::DedupeInputs => true,
             Self::Deposit => true,
             Self::Download => true,
+            Self::Export => true,
+            Self::History => true,
             Self::Process => true,
+            Self::PruneInputs => true,
+            Self::Refresh => true,
+            Self::Remove => true,
+            Self::Rename => true,
             Self::Show => true,
+            Self::Stats => true,
             Self::Tag => true,
+            Self::Undo => true,
+            Self::Verify => true,
             _ => false,
         }
     }
@@ -50,6 +85,8 @@ impl Command {
             Self::Deposit => true,
             Self::Download => true,
             Self::Process => true,
+            Self::PruneInputs => true,
+            Self::Rename => true,
             Self::Tag => true,
             _ => false,
         }
@@ -0,0 +1,54 @@
+//! Remove duplicate inputs from the input file.
+
+use crate::{types, util, Config};
+use std::collections::HashSet;
+
+/// Rewrite the input file without duplicate inputs, keeping the first occurrence of each. Blank
+/// lines and comments (such as `# source: URL` lines) are always kept, in their original order.
+pub fn run(config: &Config) -> types::UnitResult {
+    let input_path = config.input_path.as_ref().unwrap();
+    let contents = util::read_text_file(input_path, config.encoding).unwrap_or_default();
+
+    let (deduped, removed) = dedupe(&contents);
+    if removed == 0 {
+        println!("No duplicate inputs");
+        return Ok(());
+    }
+
+    util::write_text_file(input_path, deduped, config.encoding)?;
+    println!("Removed {} duplicate inputs", removed);
+    Ok(())
+}
+
+/// # Returns
+/// The `contents` without duplicate (trimmed) inputs, and the number of lines removed.
+fn dedupe(contents: &str) -> (String, usize) {
+    let mut seen = HashSet::new();
+    let mut deduped = String::new();
+    let mut removed = 0;
+
+    for line in contents.lines() {
+        let input = line.trim();
+        if !input.is_empty() && !input.starts_with('#') && !seen.insert(input) {
+            removed += 1;
+            continue;
+        }
+        deduped.push_str(line);
+        deduped.push('\n');
+    }
+
+    (deduped, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_duplicates() {
+        let contents = "a\n# source: x\nb\na\n\n# source: x\nb \nc\n";
+        let expected = "a\n# source: x\nb\n\n# source: x\nc\n";
+        assert_eq!(dedupe(contents), (String::from(expected), 2));
+        assert_eq!(dedupe(expected), (String::from(expected), 0));
+    }
+}
@@ -1,21 +1,42 @@
 //! Move (downloaded and/or tagged) files to a target directory.
 
+use crate::report::Outcome;
+use crate::state::State;
+use crate::tag::{self, TagExtractor};
 use crate::util::PromptOption::{No, Yes};
-use crate::{types, util, Config};
+use crate::{history, rename, types, util, Config, TapewormError};
 use audiotags::Tag;
-use chrono::{DateTime, Datelike, Utc};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use regex::Regex;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::fs;
 use std::io::BufRead;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+
+/// The overwrite decisions remembered in the state, keyed by target path, see `overwrite`.
+const OVERWRITES_KEY: &str = "overwrites";
+
+/// The moves of the last deposit, in `.tapeworm`, see `undo`.
+const JOURNAL_FILE: &str = "last_deposit.json";
 
 #[derive(Debug, PartialEq)]
 pub enum DepositMode {
     /// Sort files into `A-Z/ARTIST?/ALBUM?` subfolders
     AZ,
+    /// Sort files into `ARTIST?/ALBUM?` subfolders
+    Artist,
     /// Sort files into `YYYY/MM` subfolders
     Date,
+    /// Sort files into `YYYY/MM` subfolders by their date tag, see `tag_date_for`
+    TagDate,
+    /// Sort files into `GENRE` subfolders, or `Unknown` for files without a genre tag
+    Genre,
     /// Drop files directly in `target_dir`
     Drop,
+    /// Sort files into subfolders built from a path template, e.g. `{genre}/{artist}`
+    Template(String),
 }
 
 impl Default for DepositMode {
@@ -25,166 +46,666 @@ impl Default for DepositMode {
 }
 
 impl DepositMode {
-    pub fn from(s: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn from(s: &str) -> Result<Self, TapewormError> {
         match s {
             "A-Z" => Ok(Self::AZ),
+            "ARTIST" => Ok(Self::Artist),
             "DATE" => Ok(Self::Date),
+            "TAG-DATE" => Ok(Self::TagDate),
+            "GENRE" => Ok(Self::Genre),
             "DROP" => Ok(Self::Drop),
             _ => Err(format!("Invalid organization mode: '{}'. See 'help'", s).into()),
         }
     }
 
-    fn func(&self) -> fn(&PathBuf, &PathBuf) -> types::PathBufResult {
+    /// The path template that this mode expands to.
+    fn template(&self) -> &str {
         match self {
-            Self::AZ => alphabetical,
-            Self::Date => chronological,
-            Self::Drop => drop,
+            Self::AZ => "{letter}/{artist}/{album}",
+            Self::Artist => "{artist}/{album}",
+            Self::Date => "{created}",
+            Self::TagDate => "{date_year}/{date_month}",
+            Self::Genre => "{genre}",
+            Self::Drop => "",
+            Self::Template(template) => template,
+        }
+    }
+}
+
+/// What to do when a file with the same name already exists in the target folder. When not set,
+/// the user is asked whether to overwrite it (see `config.auto_overwrite`).
+#[derive(Debug, PartialEq)]
+pub enum ConflictMode {
+    /// Number the file, e.g. `Song (2).mp3`, see `rename::free_path`
+    Rename,
+    Overwrite,
+    Skip,
+}
+
+impl ConflictMode {
+    pub fn from(s: &str) -> Result<Self, TapewormError> {
+        match s.to_lowercase().as_str() {
+            "rename" => Ok(Self::Rename),
+            "overwrite" => Ok(Self::Overwrite),
+            "skip" => Ok(Self::Skip),
+            _ => Err(format!("Invalid conflict mode: '{}'. See 'help'", s).into()),
         }
     }
 }
 
-/// Attempt to move all (downloaded and processed) files (not directories) in `INPUT_DIR` to
+/// Where the `DATE` mode takes the date to sort a file into from.
+#[derive(Debug, Default, PartialEq)]
+pub enum DateSource {
+    /// The file creation date
+    #[default]
+    Created,
+    /// The release date, from the DATE or YEAR tag, see `release_dir`
+    Tag,
+}
+
+impl DateSource {
+    pub fn from(s: &str) -> Result<Self, TapewormError> {
+        match s.to_lowercase().as_str() {
+            "ctime" => Ok(Self::Created),
+            "tag" => Ok(Self::Tag),
+            _ => Err(format!("Invalid date source: '{}'. See 'help'", s).into()),
+        }
+    }
+}
+
+/// Attempt to move all (downloaded and processed) files (not directories) in each `INPUT_DIR` to
 /// `TARGET_DIR`. If the target folder does not exist, it is created. If a file already exists in
-/// the target folder, it will be overwritten upon user confirmation.
-pub fn run<R: BufRead>(config: &Config, reader: R) -> types::UnitResult {
-    let downloads = util::filepaths_in(config.input_dir.as_ref().unwrap())?;
+/// the target folder, it will be overwritten upon user confirmation, unless `config.on_conflict`
+/// says otherwise. With `config.keep_originals`, the files are copied instead.
+///
+/// With `config.remember_overwrite`, overwrite decisions are remembered across runs (see
+/// `overwrite`), until forgotten with `config.forget_overwrites`.
+///
+/// With `config.dry_run` (`--dry-run`), only the moves are printed. Nothing is backed up, tagged
+/// (see `config.tag_first`) or created, and there are no prompts.
+///
+/// # Returns
+/// The files that were moved, for the `--report`
+pub fn run<R: BufRead>(config: &Config, reader: R) -> types::OutcomeResult {
+    // Overwrite decisions from previous runs, see `overwrite`
+    let mut state = (config.remember_overwrite || config.forget_overwrites)
+        .then(|| State::load(config.state_path.as_ref().unwrap()));
+    if let Some(state) = state.as_mut().filter(|_| config.forget_overwrites) {
+        let forgotten = state.get(OVERWRITES_KEY).and_then(Value::as_object);
+        println!(
+            "Forgot {} overwrite decisions",
+            forgotten.map_or(0, |f| f.len())
+        );
+        state.remove(OVERWRITES_KEY);
+        state.save()?;
+    }
+
+    let mut downloads = if config.preserve_subdir {
+        let mut files = Vec::new();
+        for input_dir in &config.input_dirs {
+            files.extend(util::filepaths_in_recursive(input_dir, &config.lib_dir)?);
+        }
+        files
+    } else {
+        util::filepaths_in_all(&config.input_dirs)?
+    };
     if downloads.is_empty() {
-        return Ok(());
+        if !config.dry_run {
+            write_journal(config, &[])?; // Nothing to undo
+        }
+        return Ok(Outcome::Deposited(Vec::new()));
+    }
+    util::limit_files(config, &mut downloads);
+    let auto_overwrite = match config.on_conflict {
+        _ if config.dry_run => Some(true), // Existing targets are only annotated
+        Some(_) => Some(true),             // Never prompts
+        None => util::auto_for(config, config.auto_overwrite, downloads.len()),
+    };
+    let Some(auto_overwrite) = auto_overwrite else {
+        write_journal(config, &[])?; // Nothing to undo
+        return Ok(Outcome::Deposited(Vec::new()));
+    };
+    if config.backup && !config.dry_run {
+        let backup_dir = util::backup(&downloads, config)?;
+        println!(
+            "Backed up {} files to {}",
+            downloads.len(),
+            backup_dir.display()
+        );
     }
-    let target_dir = util::guarantee_dir_path(config.target_dir.clone().unwrap())?;
+    let target_dir = if config.dry_run {
+        config.target_dir.clone().unwrap()
+    } else {
+        util::guarantee_dir_path(config.target_dir.clone().unwrap())?
+    };
+
+    let remembered = state.as_mut().filter(|_| config.remember_overwrite);
 
-    if let Some(errors) = deposit(config, target_dir, downloads, reader) {
-        Err(format!(
+    let (moved, errors) = deposit(
+        config,
+        target_dir,
+        downloads,
+        auto_overwrite,
+        remembered,
+        reader,
+    );
+    if let Some(state) = state.filter(|_| config.remember_overwrite) {
+        state.save()?;
+    }
+    let verb = if config.keep_originals {
+        "copied"
+    } else {
+        "moved"
+    };
+    if !config.dry_run {
+        // Copies leave the originals in place, so there is nothing to move back
+        let moves = if config.keep_originals {
+            &[][..]
+        } else {
+            &moved[..]
+        };
+        write_journal(config, moves)?;
+
+        let target_dir = config.target_dir.as_ref().unwrap().display();
+        let mut summary = format!("{} files {} to {}", moved.len(), verb, target_dir);
+        if !errors.is_empty() {
+            summary.push_str(&format!(", {} failed", errors.len()));
+        }
+        history::record(config, "deposit", &summary);
+    }
+    if !errors.is_empty() {
+        Err(TapewormError::Partial(format!(
             "Could not move {} files to target directory:{}",
             errors.len(),
             errors.iter().fold(String::new(), |a, b| a + "\n" + &b)
-        )
-        .into())
+        )))
+    } else if config.dry_run {
+        println!("\n{} files would be {}", moved.len(), verb);
+        Ok(Outcome::Deposited(Vec::new()))
     } else {
         println!();
-        Ok(())
+        Ok(Outcome::Deposited(moved))
     }
 }
 
-/// Sort the `file` into a dated subfolder of `target_dir`:
-/// `target_dir/YYYY/MM/file.ext`, where `YYYY` and `MM` are determined from file creation date.
+/// Move the files of the last deposit (as recorded by `write_journal`) back to where they came
+/// from. Nothing is moved when any of them is no longer in the target directory, or when another
+/// file took its original place. Folders left empty in the target directory are kept, see `clean`.
 ///
-/// Examples:
-/// - `randomfile.jpg` created at 2024-04-29    -> `target_dir/2024/04/randomfile.jpg`
-/// - `Artist - Song.mp3` created at 2024-05-15 -> `target_dir/2024/05/Artist - Song.mp3`
-fn chronological(target_dir: &PathBuf, file: &PathBuf) -> types::PathBufResult {
-    let filename = file.file_name().unwrap().to_owned().into_string().unwrap();
+/// # Errors
+/// - If the last deposit cannot be undone, listing the conflicting files
+pub fn undo(config: &Config) -> types::UnitResult {
+    let Some(journal) = journal_path(config) else {
+        return Err("Not a library. See 'help'".into());
+    };
+    let moves = read_journal(&journal);
+    if moves.is_empty() {
+        println!("Nothing to undo");
+        return Ok(());
+    }
 
-    let target = if let Ok(meta) = fs::metadata(&file) {
-        if let Ok(created) = meta.created() {
-            let created: DateTime<Utc> = created.into();
-            target_dir
-                .join(created.year().to_string())
-                .join(format!("{:02}", created.month()))
-        } else {
-            return Err("! Unsupported platform: can't get file date".into());
-        }
-    } else {
-        return Err(format!("! Invalid path or no permission: {}", filename).into());
+    let conflicts = undo_conflicts(&moves);
+    if !conflicts.is_empty() {
+        return Err(format!(
+            "Cannot undo the last deposit:{}",
+            conflicts.iter().fold(String::new(), |a, b| a + "\n" + b)
+        )
+        .into());
+    }
+
+    for (from, to) in &moves {
+        util::guarantee_dir_path(from.parent().unwrap().to_path_buf())?;
+        fs::rename(to, from)?;
+        println!("  {}\n> {}", to.display(), from.display());
+    }
+    fs::remove_file(&journal)?;
+    println!("\nMoved {} files back", moves.len());
+    history::record(config, "undo", &format!("{} files moved back", moves.len()));
+    Ok(())
+}
+
+fn journal_path(config: &Config) -> Option<PathBuf> {
+    Some(config.lib_conf_path.as_ref()?.parent()?.join(JOURNAL_FILE))
+}
+
+/// Record the `moved` files as `(from, to)`, replacing the previous journal, so `undo` can move
+/// them back. Without moves, the journal is removed, so an older deposit is not undone instead.
+fn write_journal(config: &Config, moved: &[(PathBuf, PathBuf)]) -> types::UnitResult {
+    let Some(journal) = journal_path(config) else {
+        return Ok(());
     };
+    if moved.is_empty() {
+        if fs::metadata(&journal).is_ok() {
+            fs::remove_file(&journal)?;
+        }
+        return Ok(());
+    }
+    let moves = moved
+        .iter()
+        .map(|(from, to)| json!({ "from": from, "to": to }))
+        .collect::<Vec<Value>>();
+    util::write(journal, serde_json::to_string_pretty(&moves)?)
+}
 
-    Ok(util::guarantee_dir_path(target)?.join(filename))
+/// # Returns
+/// The moves recorded in the `journal` as `(from, to)`. A missing or malformed journal has none.
+fn read_journal(journal: &PathBuf) -> Vec<(PathBuf, PathBuf)> {
+    let moves = fs::read_to_string(journal)
+        .ok()
+        .and_then(|s| serde_json::from_str::<Vec<Value>>(&s).ok())
+        .unwrap_or_default();
+    moves
+        .iter()
+        .filter_map(|m| {
+            let from = m.get("from")?.as_str()?;
+            let to = m.get("to")?.as_str()?;
+            Some((PathBuf::from(from), PathBuf::from(to)))
+        })
+        .collect()
+}
+
+/// # Returns
+/// Why each of the `moves` cannot be undone: its target is missing, or its source exists again
+fn undo_conflicts(moves: &[(PathBuf, PathBuf)]) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    for (from, to) in moves {
+        if fs::metadata(to).is_err() {
+            conflicts.push(format!("! Missing: {}", to.display()));
+        } else if fs::metadata(from).is_ok() {
+            conflicts.push(format!("! Already exists: {}", from.display()));
+        }
+    }
+    conflicts
 }
 
-/// Sort the `file` into an alphabetical subfolder of `target_dir`:
-/// `target_dir/A-Z/ARTIST?/ALBUM?/file.ext`, where ARTIST and ALBUM are optional (determined from
-/// file tags). The letter `A-Z` subfolder is based on the ARTIST tag. If the ARTIST tag is not
-/// present, the artist is guessed from the filename (if there is a part to the left of a '-'
-/// separator). If that fails, the first letter of the filename is used.
+/// Check that `format` is a valid chrono format string for `config.date_format`, e.g. `%Y-%m-%d`.
+pub fn parse_date_format(format: &str) -> Result<String, TapewormError> {
+    if format.is_empty() || StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(TapewormError::InvalidConfig(format!(
+            "Invalid date format: '{}'. See 'help'",
+            format
+        )));
+    }
+    Ok(String::from(format))
+}
+
+/// Determine where to sort the `file` into: a subfolder of `target_dir`, as rendered from the
+/// `template` (see `render_path`). The subfolder is not created, see `move_file`.
 ///
-/// Examples:
+/// The following tokens are supported, besides those of the tag format:
+/// - `{letter}`: the first letter of the artist (or filename, if there is no artist), `0-9#` for
+///   anything but A-Z
+/// - `{created}`: the file creation date as formatted by `config.date_format`, which may span
+///   multiple segments, e.g. `YYYY/MM` by default. With `config.date_source` set to `Tag`, the
+///   release date is preferred, see `release_dir`
+/// - `{created_year}`, `{created_month}`: the file creation date as `YYYY` and `MM`
+/// - `{date_year}`, `{date_month}`: the release date as `YYYY` and `MM`, see `tag_date_for`
+/// - `{quality}`: `Lossless` or `Lossy` for audio files, see `quality_for`
+///
+/// The `{artist}` is taken from the ARTIST tag. If not present, the artist is guessed from the
+/// filename (if there is a part to the left of a '-' separator). If that fails,
+/// `config.unknown_artist` is used when set.
+///
+/// Examples for the "A-Z" template, `{letter}/{artist}/{album}`:
 /// - `randomfile.jpg`                         -> `target_dir/R/randomfile.jpg`
 /// - `Song.mp3 with artist tag 'Band'`        -> `target_dir/B/Band/Song.mp3`
 /// - `Song.mp3 without artist tag`            -> `target_dir/S/Song.mp3`
 /// - `Band - Song.mp3 with artist tag 'Band'` -> `target_dir/B/Band/Band - Song.mp3`
 /// - `Band - Song.mp3 without artist tag`     -> `target_dir/B/Band/Band - Song.mp3`
 /// - `Band - Song.mp3 with artist, album tag` -> `target_dir/B/Band/Album/Band - Song.mp3`
-fn alphabetical(target_dir: &PathBuf, file: &PathBuf) -> types::PathBufResult {
+/// - `Song.mp3 without artist tag, with unknown_artist 'Unknown'` -> `target_dir/U/Unknown/Song.mp3`
+///
+/// Examples for the "GENRE" template, `{genre}`:
+/// - `Song.mp3 with genre tag 'Rock'` -> `target_dir/Rock/Song.mp3`
+/// - `Song.mp3 without genre tag`     -> `target_dir/Unknown/Song.mp3`
+///
+/// Examples for the "DATE" template, `{created}`, with the default `%Y/%m` date format:
+/// - `randomfile.jpg` created at 2024-04-29    -> `target_dir/2024/04/randomfile.jpg`
+/// - `Artist - Song.mp3` created at 2024-05-15 -> `target_dir/2024/05/Artist - Song.mp3`
+fn organize(
+    config: &Config,
+    template: &str,
+    target_dir: &PathBuf,
+    file: &PathBuf,
+) -> types::PathBufResult {
+    let mut template = String::from(template);
     let filename = file.file_name().unwrap().to_owned().into_string().unwrap();
-    let tag = Tag::new().read_from_path(&file);
+    let mut tokens = HashMap::new();
+    let mut tag_date = None;
+
+    if let Ok(tag) = Tag::new().read_from_path(&file) {
+        tag_date = tag.date().map(|d| d.to_string());
+        let mut insert = |key: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                tokens.insert(key, value);
+            }
+        };
+        insert("album", tag.album_title().map(String::from));
+        insert("album_artist", tag.album_artist().map(String::from));
+        insert("artist", tag.artist().map(String::from));
+        insert("genre", tag.genre().map(String::from));
+        insert("label", tag.comment().map(String::from));
+        insert("title", tag.title().map(String::from));
+        insert("track", tag.track_number().map(|t| t.to_string()));
+        insert("year", tag.year().map(|y| y.to_string()));
+    }
 
-    let mut target = None;
-    if let Ok(tag) = &tag {
-        // Attempt to get the ARTIST from tag
-        if let Some(artist) = tag.artist() {
-            target = Some(target_dir.join(letter_for(artist)).join(artist));
+    if !tokens.contains_key("artist") {
+        // Attempt to get the ARTIST from filename, or fall back to the configured ARTIST
+        let author = filename.split_once('-').map(|(a, _)| a.trim());
+        if let Some(author) = author.filter(|a| !a.is_empty()) {
+            tokens.insert("artist", String::from(author));
+        } else if let Some(unknown) = &config.unknown_artist {
+            tokens.insert("artist", unknown.clone());
         }
     }
-    if target.is_none() {
-        // Attempt to get the ARTIST from filename
-        if let Some((author, _)) = filename.split_once('-') {
-            let author = author.trim();
-            if !author.is_empty() {
-                target = Some(target_dir.join(letter_for(&author)).join(author));
+    let has_artist = tokens.get("artist").is_some_and(|a| !a.trim().is_empty());
+    if matches!(config.organize, DepositMode::AZ | DepositMode::Artist) && !has_artist {
+        tokens.remove("album"); // The album folder only goes inside an artist folder
+    }
+    let genre = tokens.get("genre").map(|g| g.trim());
+    if config.organize == DepositMode::Genre && genre.is_none_or(str::is_empty) {
+        tokens.insert("genre", String::from("Unknown"));
+    }
+    let letter = letter_for(tokens.get("artist").unwrap_or(&filename));
+    tokens.insert("letter", letter);
+    if let Some(quality) = quality_for(file) {
+        tokens.insert("quality", String::from(quality));
+    }
+
+    if template.contains("{created") {
+        let meta = fs::metadata(&file)
+            .map_err(|_| format!("! Invalid path or no permission: {}", filename))?;
+        let created: DateTime<Utc> = meta
+            .created()
+            .map_err(|_| "! Unsupported platform: can't get file date")?
+            .into();
+        tokens.insert("created_year", created.year().to_string());
+        tokens.insert("created_month", format!("{:02}", created.month()));
+        let release = match config.date_source {
+            DateSource::Tag => {
+                let year = tokens.get("year").and_then(|y| y.parse::<i32>().ok());
+                release_dir(&config.date_format, tag_date.as_deref(), year)
             }
+            DateSource::Created => None,
+        };
+        // Substituted up front, as the formatted date may contain path separators
+        let formatted = release.unwrap_or_else(|| created.format(&config.date_format).to_string());
+        template = template.replace("{created}", &formatted);
+    }
+
+    if template.contains("{date_") {
+        let modified: DateTime<Utc> = fs::metadata(file)
+            .and_then(|m| m.modified())
+            .map_err(|_| format!("! Invalid path or no permission: {}", filename))?
+            .into();
+        let year = tokens.get("year").and_then(|y| y.parse::<i32>().ok());
+        let (year, month) = tag_date_for(tag_date.as_deref(), year, modified);
+        tokens.insert("date_year", year.to_string());
+        if let Some(month) = month {
+            tokens.insert("date_month", format!("{:02}", month));
         }
     }
-    if target.is_some() {
-        // Now that ARTIST is set, try to also set the ALBUM subfolder (from tag)
-        if let Ok(tag) = &tag {
-            if let Some(album) = tag.album_title() {
-                target = Some(target.unwrap().join(album));
+
+    let path = render_path(&template, &tokens);
+    if let (DepositMode::AZ, Some(limit)) = (&config.organize, config.per_artist_limit) {
+        // Route the file into a "Part N" subfolder of its artist folder once that is full
+        if let Some(end) = template.find("{artist}").map(|i| i + "{artist}".len()) {
+            let artist_dir = render_path(&template[..end], &tokens);
+            let album_dir = path.strip_prefix(&artist_dir).unwrap_or(Path::new(""));
+            if let Some(part) = overflow_dir(&target_dir.join(&artist_dir), limit) {
+                return Ok(target_dir
+                    .join(artist_dir)
+                    .join(part)
+                    .join(album_dir)
+                    .join(filename));
             }
         }
-    } else {
-        // No ARTIST, default to 'LETTER/' subfolder only
-        target = Some(target_dir.join(letter_for(&filename)));
     }
 
-    Ok(util::guarantee_dir_path(target.unwrap())?.join(filename))
+    Ok(target_dir.join(path).join(filename))
 }
 
-/// Drop the `file` file directly in `target_dir`.
-fn drop(target_dir: &PathBuf, file: &PathBuf) -> types::PathBufResult {
-    Ok(target_dir.join(file.file_name().unwrap().to_owned().into_string().unwrap()))
+/// Count the files in the `artist_dir` (including its subfolders), to keep at most `limit` files
+/// in each part of it: the artist folder itself, and then `Part 2`, `Part 3`, etc.
+///
+/// # Returns
+/// The `Part N` folder to put the next file in, or `None` when the artist folder has room
+fn overflow_dir(artist_dir: &PathBuf, limit: usize) -> Option<String> {
+    let count = util::filepaths_in_recursive(artist_dir, "").map_or(0, |files| files.len());
+    let part = count / limit + 1;
+    (part > 1).then(|| format!("Part {}", part))
+}
+
+/// Keep the location of the `file` relative to its input directory, instead of organizing it.
+/// E.g. `INPUT_DIR/source/Song.mp3` goes to `target_dir/source/Song.mp3`.
+///
+/// # Errors
+/// - If the file is not in an input directory
+/// - If the relative path is not a plain path, so it could end up outside of the `target_dir`
+fn preserve_subdir(config: &Config, target_dir: &Path, file: &Path) -> types::PathBufResult {
+    // The input directory holding the file, as the input directories may be nested
+    let root = config
+        .input_dirs
+        .iter()
+        .filter(|dir| file.starts_with(dir))
+        .max_by_key(|dir| dir.components().count())
+        .ok_or(format!("! Not in an input directory: {}", file.display()))?;
+    let relative = file.strip_prefix(root).unwrap();
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(format!(
+            "! Would escape the target directory: {}",
+            relative.display()
+        )
+        .into());
+    }
+    Ok(target_dir.join(relative))
+}
+
+/// Determine the release date folder(s) for the `DATE` mode with the `Tag` date source:
+/// 1. The (full) DATE tag, e.g. `2024-05-15`, as formatted by `format`
+/// 2. Only the year of the DATE tag, or else the YEAR tag, so the file goes into `YYYY/`
+///
+/// # Returns
+/// `None` when the tags have no date, so the file creation date should be used
+fn release_dir(format: &str, date: Option<&str>, year: Option<i32>) -> Option<String> {
+    let full = date
+        .and_then(|d| d.trim().get(..10))
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok());
+    if let Some(full) = full {
+        let full = full.and_hms_opt(0, 0, 0)?.and_utc();
+        return Some(full.format(format).to_string());
+    }
+    let year = date.and_then(parse_tag_date).map(|(year, _)| year).or(year);
+    year.map(|year| year.to_string())
+}
+
+/// Determine the release date to sort a file into, from the first of:
+/// 1. The year and month of the (full) DATE tag, e.g. `2024-05-15`
+/// 2. Only the year of the DATE tag, or else the YEAR tag, so the file goes into `YYYY/`
+/// 3. The year and month the file was last modified
+///
+/// # Returns
+/// The year, and the month if known
+fn tag_date_for(
+    date: Option<&str>,
+    year: Option<i32>,
+    modified: DateTime<Utc>,
+) -> (i32, Option<u32>) {
+    if let Some(date) = date.and_then(parse_tag_date) {
+        return date;
+    }
+    match year {
+        Some(year) => (year, None),
+        None => (modified.year(), Some(modified.month())),
+    }
+}
+
+/// Parse a (partial) date tag, e.g. `2024-05-15`, `2024-05-15T10:00`, `2024-05` or `2024`.
+///
+/// # Returns
+/// The year, and the month if present
+fn parse_tag_date(date: &str) -> Option<(i32, Option<u32>)> {
+    let date = date.trim();
+    let full = date.get(..10).map(String::from);
+    let partial = date.get(..7).map(|d| format!("{}-01", d));
+    for date in [full, partial].into_iter().flatten() {
+        if let Ok(date) = NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+            return Some((date.year(), Some(date.month())));
+        }
+    }
+    date.get(..4)
+        .and_then(|y| y.parse::<i32>().ok())
+        .map(|year| (year, None))
 }
 
+/// Render the path `template` by substituting `{token}`s with their value in `tokens`. Each path
+/// segment is sanitized, and segments that end up empty (e.g. because of missing values) are
+/// left out. Unknown or missing tokens are replaced by nothing.
+fn render_path(template: &str, tokens: &HashMap<&str, String>) -> PathBuf {
+    let token = Regex::new(r"\{([a-z_]+)\}").unwrap();
+
+    let mut path = PathBuf::new();
+    for segment in template.split('/') {
+        let segment = token.replace_all(segment, |caps: &regex::Captures| {
+            tokens.get(&caps[1]).cloned().unwrap_or_default()
+        });
+        let segment =
+            util::remove_duplicate_whitespace(util::remove_empty_brackets(segment.into_owned()));
+        let segment = sanitize_filename::sanitize(segment.trim());
+        let segment = segment
+            .trim()
+            .trim_start_matches("- ")
+            .trim_end_matches(" -")
+            .trim();
+        if !segment.is_empty() {
+            path.push(segment);
+        }
+    }
+    path
+}
+
+/// # Returns
+/// The files that were (or with `config.dry_run`, would be) moved as `(from, to)`, and the files
+/// that could not be moved
 fn deposit<R: BufRead>(
     config: &Config,
     target_dir: PathBuf,
     downloads: Vec<PathBuf>,
+    auto_overwrite: bool,
+    mut remembered: Option<&mut State>,
     mut reader: R,
-) -> types::OptionVecString {
-    println!("Moving files to {}...", target_dir.display());
+) -> (Vec<(PathBuf, PathBuf)>, Vec<String>) {
+    if config.dry_run {
+        println!("Previewing deposit to {}...", target_dir.display());
+    } else if config.keep_originals {
+        println!("Copying files to {}...", target_dir.display());
+    } else {
+        println!("Moving files to {}...", target_dir.display());
+    }
 
-    let func = config.organize.func();
+    let template = if config.by_quality {
+        format!("{{quality}}/{}", config.organize.template())
+    } else {
+        String::from(config.organize.template())
+    };
+    let mut moved = Vec::new();
     let mut errors = Vec::new();
+    let extractor = (config.tag_first && !config.dry_run).then(|| TagExtractor::from(config));
 
     for entry in downloads {
         println!();
 
-        let target = func(&target_dir, &entry);
+        let entry = if let Some(extractor) = &extractor {
+            tag::auto_tag_file(config, &entry, extractor)
+        } else {
+            entry
+        };
+
+        if config.min_duration.is_some() || config.max_duration.is_some() {
+            let duration = Tag::new()
+                .read_from_path(&entry)
+                .ok()
+                .and_then(|tag| util::duration_of(&entry, tag.as_ref()));
+            if let Some(reason) = util::check_duration(config, duration) {
+                println!("  Skipping {}: {}", entry.display(), reason);
+                continue;
+            }
+        }
+
+        let target = if config.preserve_subdir {
+            preserve_subdir(config, &target_dir, &entry)
+        } else {
+            organize(config, &template, &target_dir, &entry)
+        };
         if let Err(e) = target {
             errors.push(format!(
-                "! Could not create target dir: {}\n    {}",
+                "! Could not determine target path in: {}\n    {}",
                 target_dir.display(),
                 e
             ));
             continue;
         }
-        let target = target.unwrap();
+        let mut target = target.unwrap();
 
-        if !config.auto_overwrite && !overwrite(&target, &mut reader) {
-            println!("  Skipping {}", entry.display());
-            continue;
+        match config.on_conflict {
+            Some(ConflictMode::Rename) => target = rename::free_path(&entry, &target),
+            Some(ConflictMode::Skip) if fs::metadata(&target).is_ok() => {
+                println!("  Skipping {}, already exists", entry.display());
+                continue;
+            }
+            _ if !auto_overwrite && !overwrite(&target, remembered.as_deref_mut(), &mut reader) => {
+                println!("  Skipping {}", entry.display());
+                continue;
+            }
+            _ => {}
         }
 
-        if fs::rename(&entry, &target).is_ok() {
+        if config.dry_run {
+            let exists = fs::metadata(&target).is_ok();
+            let note = if exists { " (already exists)" } else { "" };
+            println!("  {}\n> {}{}", entry.display(), target.display(), note);
+            moved.push((entry, target));
+        } else if move_file(config, &entry, &target).is_ok() {
             println!("  {}\n> {}", entry.display(), target.display());
+            moved.push((entry, target));
         } else {
             errors.push(format!("! {}\n> {}", entry.display(), target.display()));
         }
     }
 
-    if errors.is_empty() {
-        None
+    (moved, errors)
+}
+
+/// Move `entry` to `target`, only now creating the target folder, so skipped or failed files
+/// don't leave empty folders behind. With `config.keep_originals`, `entry` is copied instead. With
+/// `config.preserve_mtime`, the modification time is kept.
+fn move_file(config: &Config, entry: &PathBuf, target: &Path) -> types::UnitResult {
+    util::guarantee_dir_path(target.parent().unwrap().to_path_buf())?;
+    let mtime = util::snapshot_mtime(config, entry);
+    if config.keep_originals {
+        fs::copy(entry, target)?;
     } else {
-        Some(errors)
+        fs::rename(entry, target)?;
+    }
+    util::restore_mtime(target, mtime)
+}
+
+/// Classify an audio file as lossless or lossy, based on its extension. Note that `.m4a` is
+/// considered lossy, although it may contain lossless ALAC.
+///
+/// # Returns
+/// - `Some("Lossless")` or `Some("Lossy")` for audio files
+/// - `None` for other (or unrecognized) files
+fn quality_for(file: &PathBuf) -> Option<&'static str> {
+    let ext = file.extension()?.to_str()?.to_lowercase();
+    match ext.as_str() {
+        "flac" | "wav" | "aif" | "aiff" | "alac" | "ape" | "wv" => Some("Lossless"),
+        "mp3" | "aac" | "m4a" | "ogg" | "opus" | "wma" => Some("Lossy"),
+        _ => None,
     }
 }
 
@@ -200,21 +721,43 @@ fn letter_for(s: &str) -> String {
 /// Checks if a file already exists at the `target` location,
 /// and asks the user whether to overwrite it.
 ///
+/// With a `remembered` state (`--remember-overwrite`), the answer for `target` is stored, and
+/// applied without asking the next time.
+///
 /// # Returns
 /// - `true` when the file does not exist, or to overwrite it if it does
 /// - `false` when the file exists and the user does not want to overwrite it
-fn overwrite<R: BufRead>(target: &PathBuf, reader: R) -> bool {
+fn overwrite<R: BufRead>(target: &PathBuf, remembered: Option<&mut State>, reader: R) -> bool {
     if fs::metadata(target).is_err() {
         return true;
     }
-    let prompt = format!(
-        "! File already exists: {}\nOverwrite?",
-        target.to_str().unwrap()
-    );
-    match util::select(&prompt, vec![Yes, No], Yes, reader) {
+    let key = target.to_string_lossy();
+    let mut decisions = remembered
+        .as_ref()
+        .and_then(|state| state.get(OVERWRITES_KEY))
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+    if let Some(decision) = decisions.get(key.as_ref()).and_then(Value::as_bool) {
+        let answer = if decision { "yes" } else { "no" };
+        println!(
+            "! File already exists: {}\nOverwrite? {} (remembered)",
+            key, answer
+        );
+        return decision;
+    }
+
+    let prompt = format!("! File already exists: {}\nOverwrite?", key);
+    let decision = match util::select(&prompt, vec![Yes, No], Yes, reader) {
         Ok(Yes) => true,
-        _ => false, // Don't overwrite on Err(_) or Ok(No)
+        Ok(_) => false,
+        Err(_) => return false, // Don't overwrite, nor remember
+    };
+    if let Some(state) = remembered {
+        decisions.insert(String::from(key), json!(decision));
+        state.set(OVERWRITES_KEY, Value::Object(decisions));
     }
+    decision
 }
 
 #[cfg(test)]
@@ -231,6 +774,158 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parses_tag_date() {
+        assert_eq!(parse_tag_date("2024-05-15"), Some((2024, Some(5))));
+        assert_eq!(parse_tag_date("2024-05-15T10:30"), Some((2024, Some(5))));
+        assert_eq!(parse_tag_date("2024-05"), Some((2024, Some(5))));
+        assert_eq!(parse_tag_date("2024"), Some((2024, None)));
+        assert_eq!(parse_tag_date("May"), None);
+    }
+
+    #[test]
+    fn falls_back_from_tag_date() {
+        let modified = DateTime::parse_from_rfc3339("2023-11-02T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        // The full date tag is used when present
+        let date = tag_date_for(Some("2024-05-15"), Some(2020), modified);
+        assert_eq!(date, (2024, Some(5)));
+        // Then the year of the date tag
+        assert_eq!(
+            tag_date_for(Some("2024"), Some(2020), modified),
+            (2024, None)
+        );
+        // Then the year tag
+        assert_eq!(tag_date_for(None, Some(2020), modified), (2020, None));
+        assert_eq!(
+            tag_date_for(Some("unknown"), Some(2020), modified),
+            (2020, None)
+        );
+        // Then the modification date
+        assert_eq!(tag_date_for(None, None, modified), (2023, Some(11)));
+    }
+
+    #[test]
+    fn prefers_release_date() {
+        let dir = |date, year| release_dir("%Y/%m", date, year);
+        assert_eq!(
+            dir(Some("2024-05-15"), Some(2020)),
+            Some(String::from("2024/05"))
+        );
+        assert_eq!(dir(Some("2024-05"), Some(2020)), Some(String::from("2024")));
+        assert_eq!(dir(Some("unknown"), Some(2020)), Some(String::from("2020")));
+        assert_eq!(dir(None, Some(2020)), Some(String::from("2020")));
+        assert_eq!(dir(None, None), None);
+        assert_eq!(
+            release_dir("%Y-%m-%d", Some("2024-05-15"), None),
+            Some(String::from("2024-05-15"))
+        );
+    }
+
+    #[test]
+    fn organizes_without_creating_folders() {
+        let dir = std::env::temp_dir().join(format!("tapeworm-organize-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("Band - Song.txt");
+        fs::write(&file, "").unwrap();
+
+        let config = Config::default();
+        let target = organize(&config, "{letter}/{artist}", &dir, &file).unwrap();
+        assert_eq!(target, dir.join("B").join("Band").join("Band - Song.txt"));
+        assert!(fs::metadata(dir.join("B")).is_err());
+
+        move_file(&config, &file, &target).unwrap();
+        assert!(fs::metadata(&target).is_ok());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn preserves_subdirs() {
+        let mut config = Config::default();
+        config.input_dirs = vec![PathBuf::from("/in"), PathBuf::from("/in/nested")];
+        let target = Path::new("/out");
+        let preserve = |file: &str| preserve_subdir(&config, target, Path::new(file));
+
+        assert_eq!(
+            preserve("/in/source/a.mp3").unwrap(),
+            PathBuf::from("/out/source/a.mp3")
+        );
+        assert_eq!(
+            preserve("/in/nested/b.mp3").unwrap(),
+            PathBuf::from("/out/b.mp3")
+        );
+        assert!(preserve("/in/../etc/passwd").is_err());
+        assert!(preserve("/elsewhere/c.mp3").is_err());
+    }
+
+    #[test]
+    fn validates_date_format() {
+        assert_eq!(parse_date_format("%Y-%m-%d").unwrap(), "%Y-%m-%d");
+        assert!(parse_date_format("%Y/%m/%d").is_ok());
+        assert!(parse_date_format("%Y/%Q").is_err());
+        assert!(parse_date_format("%").is_err());
+        assert!(parse_date_format("").is_err());
+    }
+
+    #[test]
+    fn remembers_overwrite_decisions() {
+        let dir = std::env::temp_dir().join(format!("tapeworm-overwrite-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("Song.mp3");
+        let mut state = State::load(&dir.join("state.json"));
+
+        assert!(overwrite(&target, Some(&mut state), std::io::empty())); // Does not exist
+        fs::write(&target, "").unwrap();
+        assert!(!overwrite(&target, Some(&mut state), "n\n".as_bytes()));
+        // Would default to 'yes' when asked
+        assert!(!overwrite(&target, Some(&mut state), std::io::empty()));
+        assert!(overwrite(&target, None, std::io::empty()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn renders_path_template() {
+        let tokens = HashMap::from([
+            ("artist", String::from("Band")),
+            ("album", String::from("Album")),
+            ("year", String::from("2024")),
+            ("genre", String::from("Rock/Pop")),
+        ]);
+        let inputs = [
+            ("", ""),
+            ("{artist}/{album}", "Band/Album"),
+            (
+                "{genre}/{artist}/{year} - {album}",
+                "RockPop/Band/2024 - Album",
+            ),
+            ("{label}/{artist}", "Band"),
+            ("{artist}/{year} - {title}", "Band/2024"),
+            ("{artist}/{unknown} ({label})", "Band"),
+        ];
+        for (template, expected) in inputs {
+            assert_eq!(render_path(template, &tokens), PathBuf::from(expected));
+        }
+    }
+
+    #[test]
+    fn classifies_quality() {
+        let inputs = [
+            ("song.flac", Some("Lossless")),
+            ("song.WAV", Some("Lossless")),
+            ("song.mp3", Some("Lossy")),
+            ("song.opus", Some("Lossy")),
+            ("picture.jpg", None),
+            ("song", None),
+        ];
+        for (input, expected) in inputs {
+            assert_eq!(quality_for(&PathBuf::from(input)), expected);
+        }
+    }
+
     #[test]
     fn handles_non_letters() {
         for symbol in ["42", "2U", ".band.", "アーティスト", "歌手"] {
@@ -1,10 +1,14 @@
+use crate::add::InputFormat;
 use crate::util::PromptOption::{No, Yes, YesToAll};
-use crate::{types, util, Config};
+use crate::{add, history, prune, types, util, Config, TapewormError};
 use std::collections::HashSet;
 use std::fs;
-use std::io::{BufRead, BufReader, ErrorKind};
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
 
 /// Interface for downloading files.
 pub trait Downloader {
@@ -17,6 +21,7 @@ pub trait Downloader {
 }
 
 /// Wrapper for `yt-dlp`.
+#[derive(Clone)]
 pub struct YtDlp;
 
 impl YtDlp {
@@ -34,82 +39,466 @@ impl YtDlp {
         }
         yt_dlp_conf_path
     }
-}
 
-impl Downloader for YtDlp {
-    fn download<R: BufRead>(
+    /// Download the `inputs` as `Downloader::download` does, but report the progress of each input
+    /// to `on_event` instead of printing the output of yt-dlp, e.g. for a front-end showing a
+    /// progress bar per input.
+    pub fn download_with<R, F>(
         &self,
         config: &Config,
         inputs: HashSet<String>,
         mut reader: R,
-    ) -> types::UnitResult {
+        on_event: F,
+    ) -> types::UnitResult
+    where
+        R: BufRead,
+        F: FnMut(DownloadEvent),
+    {
         let mut command = Command::new("yt-dlp");
         if let Some(conf_path) = YtDlp::get_config(config, &mut reader) {
             command.arg("--config-location").arg(conf_path);
         }
+        if let Some(archive) = &config.download_archive {
+            command.arg("--download-archive").arg(archive);
+        }
         inputs.iter().for_each(|url| {
             command.arg(url);
         });
-        command.stdout(Stdio::piped());
+        run_with_events(&mut command, on_event)
+    }
+}
 
-        let stdout = command.spawn()?.stdout.ok_or_else(|| {
-            std::io::Error::new(ErrorKind::Other, "Could not capture standard output.")
-        })?;
-        BufReader::new(stdout)
-            .lines()
-            .filter_map(|line| line.ok())
-            .for_each(|line| println!("{}", line));
+/// Run the `command`, turning the lines it writes to stdout and stderr (where yt-dlp reports its
+/// errors) into `DownloadEvent`s for `on_event`.
+///
+/// # Errors
+/// - If the `command` could not be run, or exited unsuccessfully
+fn run_with_events<F>(command: &mut Command, mut on_event: F) -> types::UnitResult
+where
+    F: FnMut(DownloadEvent),
+{
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = spawn(command)?;
+    let stdout = child.stdout.take().ok_or_else(|| {
+        std::io::Error::new(ErrorKind::Other, "Could not capture standard output.")
+    })?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| std::io::Error::other("Could not capture standard error."))?;
+
+    // Each stream is read by a thread of its own, as a full pipe would block the command
+    let (sender, lines) = mpsc::channel();
+    thread::scope(|scope| {
+        let error_sender = sender.clone();
+        scope.spawn(move || forward_lines(stdout, sender));
+        scope.spawn(move || forward_lines(stderr, error_sender));
+
+        let mut parser = EventParser::default();
+        for line in lines {
+            parser.parse(&line).into_iter().for_each(&mut on_event);
+        }
+        parser.finish().into_iter().for_each(&mut on_event);
+    });
+
+    let status = child.wait()?;
+    if status.success() {
         Ok(())
+    } else {
+        Err(format!(
+            "{} exited with {}",
+            command.get_program().to_string_lossy(),
+            status
+        )
+        .into())
     }
 }
 
+/// Send each line of the `stream` to the `sender`, until either is closed.
+fn forward_lines<S: Read>(stream: S, sender: Sender<String>) {
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        if sender.send(line).is_err() {
+            break;
+        }
+    }
+}
+
+impl Downloader for YtDlp {
+    fn download<R: BufRead>(
+        &self,
+        config: &Config,
+        inputs: HashSet<String>,
+        reader: R,
+    ) -> types::UnitResult {
+        self.download_with(config, inputs, reader, |event| {
+            if let DownloadEvent::Output(line) = event {
+                println!("{}", line);
+            }
+        })
+    }
+}
+
+/// The progress of a download by yt-dlp, see `YtDlp::download_with`.
+#[derive(Debug, PartialEq)]
+pub enum DownloadEvent {
+    /// A line of yt-dlp output (on stdout or stderr), as is
+    Output(String),
+    /// yt-dlp started on the input `url`. A query or playlist is followed by the `Started` event
+    /// of each of its videos
+    Started { url: String },
+    /// The download of `url` is at `percent`
+    Progress { url: String, percent: f32 },
+    /// `url` was downloaded to `path`, i.e. the final file, after e.g. extracting audio
+    Finished { url: String, path: PathBuf },
+    /// yt-dlp reported an `error` for `url`
+    Failed { url: String, error: String },
+}
+
+/// Turns the output of yt-dlp into `DownloadEvent`s, keeping track of the current input.
+#[derive(Default)]
+struct EventParser {
+    url: Option<String>,
+    path: Option<PathBuf>,
+}
+
+impl EventParser {
+    /// # Returns
+    /// The `Output` event of the `line`, followed by any event it signifies.
+    fn parse(&mut self, line: &str) -> Vec<DownloadEvent> {
+        let mut events = vec![DownloadEvent::Output(String::from(line))];
+        // E.g. `[youtube] Extracting URL: https://youtu.be/...`
+        if let Some((_, url)) = line.split_once("] Extracting URL: ") {
+            events.extend(self.finish());
+            self.url = Some(String::from(url.trim()));
+            events.push(DownloadEvent::Started {
+                url: String::from(url.trim()),
+            });
+            return events;
+        }
+
+        let Some(url) = self.url.clone() else {
+            return events;
+        };
+        if let Some(error) = line.strip_prefix("ERROR: ") {
+            self.url = None;
+            self.path = None;
+            let error = String::from(error);
+            events.push(DownloadEvent::Failed { url, error });
+        } else if let Some(path) = destination_of(line) {
+            self.path = Some(path);
+        } else if let Some(percent) = percent_of(line) {
+            events.push(DownloadEvent::Progress { url, percent });
+        }
+        events
+    }
+
+    /// # Returns
+    /// The `Finished` event of the current input, when it got a destination.
+    fn finish(&mut self) -> Option<DownloadEvent> {
+        let url = self.url.take()?;
+        let path = self.path.take()?;
+        Some(DownloadEvent::Finished { url, path })
+    }
+}
+
+/// The file that a yt-dlp output `line` writes to, e.g. `[download] Destination: FILE` or
+/// `[ExtractAudio] Destination: FILE`.
+fn destination_of(line: &str) -> Option<PathBuf> {
+    let (_, rest) = line.split_once("] ")?;
+    let path = rest
+        .strip_prefix("Destination: ")
+        .or_else(|| rest.strip_suffix(" has already been downloaded"))
+        .or_else(|| {
+            rest.strip_prefix("Merging formats into \"")?
+                .strip_suffix('"')
+        })?;
+    Some(PathBuf::from(path))
+}
+
+/// The percentage of a yt-dlp progress `line`, e.g. `[download]  42.0% of 3.20MiB at ...`.
+fn percent_of(line: &str) -> Option<f32> {
+    let rest = line.strip_prefix("[download]")?.trim_start();
+    let (percent, _) = rest.split_once('%')?;
+    percent.parse::<f32>().ok()
+}
+
+/// Spawn the `command`, with a helpful error if its program is not installed.
+fn spawn(command: &mut Command) -> Result<Child, TapewormError> {
+    command.spawn().map_err(|e| {
+        if e.kind() == ErrorKind::NotFound {
+            format!(
+                "{} not found on PATH; install it from https://github.com/yt-dlp/yt-dlp",
+                command.get_program().to_string_lossy()
+            )
+            .into()
+        } else {
+            e.into()
+        }
+    })
+}
+
+/// Download all inputs. With `config.keep_going` (or `config.retries`), each input is downloaded
+/// separately, so a failing input does not stop the others. The failed inputs are then reported at
+/// the end, and kept in the input file when clearing it. Downloads that match the blocklist are
+/// trashed, also with `config.auto_download`.
 pub fn run<R, D>(config: &Config, mut reader: R, downloader: &D) -> types::UnitResult
 where
     R: BufRead,
     D: Downloader,
 {
-    if let Some(inputs) = get_inputs(config) {
-        downloader.download(config, inputs, &mut reader)?;
-    } else {
+    let Some(inputs) = get_inputs(config)? else {
         if config.verbose {
             println!("Nothing to download. Library is empty.");
         }
         return Ok(());
+    };
+
+    let total = inputs.len();
+    let Some(auto_download) = util::auto_for(config, config.auto_download, total) else {
+        return Ok(()); // The inputs are kept for a later (interactive) run
+    };
+    let mut failed = Vec::new();
+    if config.keep_going || config.retries > 0 {
+        failed = download_each(config, inputs, &mut reader, downloader);
+    } else {
+        downloader
+            .download(config, inputs, &mut reader)
+            .map_err(|e| TapewormError::Download(e.to_string()))?;
     }
 
-    if config.clear_input {
-        fs::write(config.input_path.as_ref().unwrap(), "")?;
+    // Inputs fetched from elsewhere are not in the input file, and structured input files are
+    // left as is
+    if config.clear_input && !external_inputs(config) {
+        let kept = failed.iter().fold(String::new(), |a, b| a + b + "\n");
+        util::write_text_file(config.input_path.as_ref().unwrap(), kept, config.encoding)?;
     }
 
-    if config.auto_download {
-        Ok(())
-    } else {
-        confirm_downloads(config, &mut reader)
+    trash_blocked(config)?;
+    if !auto_download {
+        confirm_downloads(config, &mut reader)?;
     }
+
+    if !failed.is_empty() {
+        println!("\nCould not download {} inputs:", failed.len());
+        failed.iter().for_each(|input| println!("  {}", input));
+    }
+    let summary = format!("{} inputs, {} failed", total, failed.len());
+    history::record(config, "download", &summary);
+    Ok(())
 }
 
-fn get_inputs(config: &Config) -> Option<HashSet<String>> {
-    let input_path = config.input_path.as_ref().unwrap();
-    let inputs = fs::read_to_string(input_path).unwrap_or(String::new());
+/// Download each of the `inputs` separately (see `download_with_retries`), continuing past
+/// failures.
+///
+/// # Returns
+/// The inputs that could not be downloaded, sorted
+fn download_each<R, D>(
+    config: &Config,
+    inputs: HashSet<String>,
+    mut reader: R,
+    downloader: &D,
+) -> Vec<String>
+where
+    R: BufRead,
+    D: Downloader,
+{
+    let mut failed = Vec::new();
+    for input in inputs {
+        let single = HashSet::from([input.clone()]);
+        if let Err(e) = download_with_retries(config, single, &mut reader, downloader) {
+            println!("! Could not download {}: {}", input, e);
+            failed.push(input);
+        }
+    }
+    failed.sort();
+    failed
+}
+
+/// Download the `inputs`, retrying up to `config.retries` times when the download fails, e.g. on a
+/// flaky connection. The delay before a retry starts at `config.retry_delay_ms`, and doubles with
+/// each attempt.
+fn download_with_retries<R, D>(
+    config: &Config,
+    inputs: HashSet<String>,
+    mut reader: R,
+    downloader: &D,
+) -> types::UnitResult
+where
+    R: BufRead,
+    D: Downloader,
+{
+    let mut delay = config.retry_delay_ms;
+    let mut attempt = 0;
+    loop {
+        match downloader.download(config, inputs.clone(), &mut reader) {
+            Err(e) if attempt < config.retries => {
+                attempt += 1;
+                println!(
+                    "! Download failed: {}\nRetrying in {} ms ({} of {})...",
+                    e, delay, attempt, config.retries
+                );
+                thread::sleep(Duration::from_millis(delay));
+                delay = delay.saturating_mul(2);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Read the inputs from `config.input_url` when set, or else from the input file, in the
+/// `config.input_format`. Videos that are in the yt-dlp download archive (see `archive_path`) are
+/// skipped, as yt-dlp would skip them anyway.
+///
+/// # Errors
+/// - `TapewormError::Download`: if the inputs could not be fetched
+/// - `TapewormError::InvalidConfig`: if the input file is not in the `config.encoding`
+/// - If the inputs are not valid JSON, see `add::parse_structured`
+fn get_inputs(config: &Config) -> Result<Option<HashSet<String>>, TapewormError> {
+    let inputs = if let Some(url) = &config.input_url {
+        fetch_inputs(url).map_err(|e| {
+            TapewormError::Download(format!("Could not fetch inputs from {}: {}", url, e))
+        })?
+    } else {
+        let input_path = config.input_path.as_ref().unwrap();
+        match util::read_text_file(input_path, config.encoding) {
+            Ok(inputs) => inputs,
+            Err(_) if !input_path.exists() => String::new(),
+            Err(e) => {
+                let msg = format!(
+                    "Could not read {}: {}. See ENCODING",
+                    input_path.display(),
+                    e
+                );
+                return Err(TapewormError::InvalidConfig(msg));
+            }
+        }
+    };
     if inputs.is_empty() {
-        return None;
+        return Ok(None);
     }
+    let inputs = if config.input_format == InputFormat::Lines {
+        inputs
+    } else {
+        add::parse_structured(&inputs, config)?.join("\n")
+    };
 
-    let inputs: HashSet<String> = inputs.lines().map(|s| s.to_string()).collect();
+    let mut inputs: HashSet<String> = inputs
+        .lines()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty() && !s.starts_with('#')) // Skip comments, e.g. scrape sources
+        .map(|s| s.to_string())
+        .collect();
+    if let Some(archive) = archive_path(config) {
+        let archived = archived_ids(&archive);
+        let total = inputs.len();
+        inputs.retain(|input| !is_archived(input, &archived));
+        if inputs.len() < total {
+            println!(
+                "Skipping {} inputs already in the download archive",
+                total - inputs.len()
+            );
+        }
+    }
+    if inputs.is_empty() {
+        return Ok(None);
+    }
     if config.verbose {
         println!("Downloading {} URLs:", inputs.len());
         inputs.iter().for_each(|s| println!("  {}", s));
         println!();
     }
-    Some(inputs)
+    Ok(Some(inputs))
+}
+
+/// The download archive that yt-dlp records downloaded videos in, as set by
+/// `config.download_archive`, or else by the `--download-archive FILE` option in the yt-dlp config.
+fn archive_path(config: &Config) -> Option<PathBuf> {
+    if config.download_archive.is_some() {
+        return config.download_archive.clone();
+    }
+    let contents = fs::read_to_string(config.yt_dlp_conf_path.as_ref()?).ok()?;
+    let path = contents.lines().map(str::trim).find_map(|line| {
+        let value = line.strip_prefix("--download-archive")?;
+        let value = value.strip_prefix('=').unwrap_or(value).trim();
+        Some(value.trim_matches(|c| c == '"' || c == '\''))
+    })?;
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().map(|home| home.join(rest)),
+        None => Some(PathBuf::from(path)),
+    }
+}
+
+/// The entries of the download archive at `path`, e.g. `youtube dQw4w9WgXcQ`. A missing archive
+/// has no entries.
+fn archived_ids(path: &Path) -> HashSet<String> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Whether the `input` is a video that is in the `archived` entries. Only YouTube videos are
+/// recognized, as the ID of a query is not known until it is searched for.
+fn is_archived(input: &str, archived: &HashSet<String>) -> bool {
+    url::Url::parse(input)
+        .ok()
+        .and_then(|url| add::youtube_video_id(&url))
+        .is_some_and(|id| archived.contains(&format!("youtube {}", id)))
+}
+
+#[cfg(feature = "remote-input")]
+fn fetch_inputs(url: &str) -> types::StringResult {
+    let mut response = ureq::get(url).call()?;
+    Ok(response.body_mut().read_to_string()?)
+}
+
+#[cfg(not(feature = "remote-input"))]
+fn fetch_inputs(_url: &str) -> types::StringResult {
+    Err("tapeworm was built without the 'remote-input' feature".into())
+}
+
+/// Move the downloads whose filename contains a term of `config.blocklist` to the trash, without
+/// asking, as they are never wanted.
+fn trash_blocked(config: &Config) -> types::UnitResult {
+    if config.blocklist.is_empty() {
+        return Ok(());
+    }
+    for download in util::filepaths_in_all(&config.input_dirs)? {
+        let filename = download.file_name().unwrap().to_string_lossy();
+        if let Some(term) = util::blocked(config, &filename) {
+            let trashed = util::trash(&download, config)?;
+            println!(
+                "Moved blocked {} (matches '{}') to {}",
+                download.display(),
+                term,
+                trashed.display()
+            );
+        }
+    }
+    Ok(())
 }
 
 fn confirm_downloads<R: BufRead>(config: &Config, mut reader: R) -> types::UnitResult {
-    let downloads: Vec<PathBuf> = util::filepaths_in(config.input_dir.as_ref().unwrap())?;
+    let downloads: Vec<PathBuf> = util::filepaths_in_all(&config.input_dirs)?;
     if downloads.is_empty() {
         return Ok(());
     }
     let total = downloads.len();
+    // When the inputs were cleared or fetched from elsewhere, there are no inputs to remove
+    let input_path = config
+        .input_path
+        .as_ref()
+        .filter(|_| !external_inputs(config));
+    let mut inputs: Vec<String> =
+        util::read_text_file(input_path.unwrap_or(&PathBuf::new()), config.encoding)
+            .unwrap_or_default()
+            .lines()
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty() && !s.starts_with('#'))
+            .map(String::from)
+            .collect();
 
     println!("\nDownloaded {} files:", total);
     downloads
@@ -121,8 +510,15 @@ fn confirm_downloads<R: BufRead>(config: &Config, mut reader: R) -> types::UnitR
         let choice = util::select("Keep?", vec![Yes, No, YesToAll], YesToAll, &mut reader);
         match choice {
             Ok(No) => {
-                fs::remove_file(entry)?;
-                println!("Deleted {}", entry.to_str().unwrap());
+                let trashed = util::trash(entry, config)?;
+                println!("Moved {} to {}", entry.to_str().unwrap(), trashed.display());
+                if let Some(input) = origin_of(entry, &inputs) {
+                    let prompt = format!("Also remove '{}' from the inputs?", input);
+                    if let Ok(Yes) = util::select(&prompt, vec![Yes, No], No, &mut reader) {
+                        remove_input(config, &input)?;
+                        inputs.retain(|i| *i != input);
+                    }
+                }
             }
             Ok(Yes) => continue,
             _ => break, // Keep all on Err(_) or Ok(YesToAll)
@@ -131,3 +527,164 @@ fn confirm_downloads<R: BufRead>(config: &Config, mut reader: R) -> types::UnitR
 
     Ok(())
 }
+
+/// Whether the inputs are not managed by tapeworm, i.e. fetched from `config.input_url` or read
+/// from a structured input file (see `config.input_format`), so they should not be changed.
+fn external_inputs(config: &Config) -> bool {
+    config.input_url.is_some() || config.input_format != InputFormat::Lines
+}
+
+/// Guess which input a download originated from: the only input, or else the only query whose
+/// words all appear in the filename.
+fn origin_of(entry: &Path, inputs: &[String]) -> Option<String> {
+    if inputs.len() == 1 {
+        return inputs.first().cloned();
+    }
+
+    let filename = util::words(&entry.file_stem()?.to_string_lossy());
+    let mut matches = inputs.iter().filter(|input| {
+        prune::query_from(input).is_some_and(|q| !q.is_empty() && q.is_subset(&filename))
+    });
+    match (matches.next(), matches.next()) {
+        (Some(input), None) => Some(input.clone()),
+        _ => None, // Ambiguous or unknown
+    }
+}
+
+/// Remove all lines holding the `input` from the input file.
+fn remove_input(config: &Config, input: &str) -> types::UnitResult {
+    let input_path = config.input_path.as_ref().unwrap();
+    let contents = util::read_text_file(input_path, config.encoding)?;
+    let kept = contents
+        .lines()
+        .filter(|line| line.trim() != input)
+        .fold(String::new(), |a, b| a + b + "\n");
+    util::write_text_file(input_path, kept, config.encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_download_events() {
+        let output = "\
+[youtube:search] Extracting URL: ytsearch:Band - Song
+[youtube] Extracting URL: https://www.youtube.com/watch?v=abc
+[download] Destination: tmp/Song.webm
+[download]  42.0% of    3.20MiB at    1.00MiB/s ETA 00:02
+[download] 100% of    3.20MiB in 00:00:03
+[ExtractAudio] Destination: tmp/Song.mp3
+[youtube] Extracting URL: https://youtu.be/private
+ERROR: [youtube] private: Private video";
+        let mut parser = EventParser::default();
+        let mut events = output
+            .lines()
+            .flat_map(|line| parser.parse(line))
+            .filter(|event| !matches!(event, DownloadEvent::Output(_)))
+            .collect::<Vec<DownloadEvent>>();
+        events.extend(parser.finish());
+
+        let url = String::from("https://www.youtube.com/watch?v=abc");
+        assert_eq!(
+            events,
+            vec![
+                DownloadEvent::Started {
+                    url: String::from("ytsearch:Band - Song")
+                },
+                DownloadEvent::Started { url: url.clone() },
+                DownloadEvent::Progress {
+                    url: url.clone(),
+                    percent: 42.0
+                },
+                DownloadEvent::Progress {
+                    url: url.clone(),
+                    percent: 100.0
+                },
+                DownloadEvent::Finished {
+                    url,
+                    path: PathBuf::from("tmp/Song.mp3")
+                },
+                DownloadEvent::Started {
+                    url: String::from("https://youtu.be/private")
+                },
+                DownloadEvent::Failed {
+                    url: String::from("https://youtu.be/private"),
+                    error: String::from("[youtube] private: Private video")
+                },
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_errors_on_stderr() {
+        let script = "echo '[youtube] Extracting URL: https://youtu.be/gone'; \
+                      sleep 0.1; echo 'ERROR: [youtube] gone: Video unavailable' >&2; exit 1";
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        let mut events = Vec::new();
+        let result = run_with_events(&mut command, |event| events.push(event));
+
+        assert!(result.is_err());
+        assert!(events.contains(&DownloadEvent::Failed {
+            url: String::from("https://youtu.be/gone"),
+            error: String::from("[youtube] gone: Video unavailable"),
+        }));
+    }
+
+    #[test]
+    fn guesses_origin_of_download() {
+        let inputs = [
+            "ytsearch:Band - Song",
+            "ytsearch:Other Song",
+            "https://youtu.be/abc",
+        ]
+        .map(String::from);
+        let origin = |filename: &str| origin_of(&PathBuf::from(filename), &inputs);
+
+        assert_eq!(
+            origin("Band - Song (Official Video).mp3"),
+            Some(inputs[0].clone())
+        );
+        assert_eq!(origin("Other Song.mp3"), Some(inputs[1].clone()));
+        assert_eq!(origin("Unrelated.mp3"), None);
+        // Ambiguous, as both queries match
+        assert_eq!(origin("Band - Other Song.mp3"), None);
+
+        let single = [String::from("https://youtu.be/abc")];
+        assert_eq!(
+            origin_of(&PathBuf::from("x.mp3"), &single),
+            Some(single[0].clone())
+        );
+    }
+
+    #[test]
+    fn recognizes_archived_videos() {
+        let archived = HashSet::from([String::from("youtube dQw4w9WgXcQ")]);
+        assert!(is_archived(
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            &archived
+        ));
+        assert!(is_archived("https://youtu.be/dQw4w9WgXcQ", &archived));
+        assert!(is_archived(
+            "https://youtube.com/shorts/dQw4w9WgXcQ",
+            &archived
+        ));
+        assert!(!is_archived("https://youtu.be/DQW4W9WGXCQ", &archived)); // IDs are case-sensitive
+        assert!(!is_archived("https://youtu.be/y6120QOlsfU", &archived));
+        assert!(!is_archived("ytsearch:dQw4w9WgXcQ", &archived));
+    }
+
+    #[test]
+    fn reports_program_not_found() {
+        let mut command = Command::new("tapeworm-not-a-program");
+        let error = spawn(&mut command).unwrap_err().to_string();
+        assert!(error.starts_with("tapeworm-not-a-program not found on PATH"));
+
+        let error = run_with_events(&mut command, |_| {}).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("install it from https://github.com/yt-dlp/yt-dlp"));
+    }
+}
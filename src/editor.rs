@@ -2,11 +2,32 @@ use crate::{types, util};
 use std::collections::HashMap;
 use std::io::{BufRead, Write};
 
+/// The (uppercase) names of the tags that can be edited.
+pub const SUPPORTED_TAGS: [&str; 11] = [
+    "ARTIST",
+    "ALBUM",
+    "ALBUM_ARTIST",
+    "COMMENT",
+    "DISC",
+    "GENRE",
+    "GROUPING",
+    "LABEL",
+    "TITLE",
+    "TRACK",
+    "YEAR",
+];
+
+/// # Parameters
+/// - `current`: the current (proposed) value of each tag, by tag name, which `swap` starts from
+///
 /// # Returns
 /// `HashMap<String, Option<String>>`:
 /// - The `String` key is the tag name
 /// - The `Option` is the value: `None` to clear it, `Some(String)` to set/update it
-pub fn edit<R: BufRead>(mut reader: R) -> types::HashMapResult {
+pub fn edit<R: BufRead>(
+    mut reader: R,
+    current: &HashMap<String, Option<String>>,
+) -> types::HashMapResult {
     println!("\n===== Tapeworm Tag Editor =====");
     tag_editor_help();
 
@@ -18,6 +39,13 @@ pub fn edit<R: BufRead>(mut reader: R) -> types::HashMapResult {
         match cmd.as_str() {
             "quit" | "q" => break,
             "help" | "h" => tag_editor_help(),
+            _ if cmd.to_lowercase().starts_with("swap ") => match parse_swap(&cmd) {
+                Some((a, b)) => {
+                    swap(&mut edits, current, &a, &b);
+                    println!("Swapped {} and {}", a, b);
+                }
+                None => println!("Usage: swap TAG TAG, e.g. `swap ARTIST TITLE`"),
+            },
             _ => {
                 if let Some((tag_name, tag_value)) = parse(cmd) {
                     edits.insert(tag_name, tag_value);
@@ -37,12 +65,51 @@ fn parse(cmd: String) -> Option<(String, Option<String>)> {
         (cmd.to_uppercase(), None)
     };
 
-    match tag_name.as_str() {
-        "ARTIST" | "ALBUM" | "ALBUM_ARTIST" | "GENRE" | "TITLE" | "TRACK" | "YEAR" => {
-            Some((tag_name, tag_value))
-        }
-        _ => None,
-    }
+    Some((tag_name, tag_value)).filter(|(name, _)| is_supported(name))
+}
+
+/// Parse a `swap TAG TAG` command.
+///
+/// # Returns
+/// - `None` if the command is malformed or either tag is not supported
+/// - The two uppercase tag names otherwise
+fn parse_swap(cmd: &str) -> Option<(String, String)> {
+    let mut words = cmd.split_whitespace().skip(1).map(|w| w.to_uppercase());
+    let (a, b) = (words.next()?, words.next()?);
+    Some((a, b)).filter(|(a, b)| words.next().is_none() && is_supported(a) && is_supported(b))
+}
+
+/// Stage the exchange of the values of the tags `a` and `b`. Values that were already staged take
+/// precedence over the `current` values.
+fn swap(
+    edits: &mut HashMap<String, Option<String>>,
+    current: &HashMap<String, Option<String>>,
+    a: &str,
+    b: &str,
+) {
+    let value = |tag: &str| match edits.get(tag) {
+        Some(staged) => staged.clone(),
+        None => current.get(tag).cloned().flatten(),
+    };
+    let (a_value, b_value) = (value(a), value(b));
+    edits.insert(String::from(a), b_value);
+    edits.insert(String::from(b), a_value);
+}
+
+/// Parse a `KEY=VALUE` tag assignment, e.g. `album=X`. An empty VALUE clears the tag.
+///
+/// # Returns
+/// - `None` if the assignment is malformed or the tag is not supported
+/// - The uppercase tag name and its value otherwise
+pub fn parse_assignment(s: &str) -> Option<(String, Option<String>)> {
+    let (tag_name, tag_value) = s.split_once('=')?;
+    let tag_name = tag_name.trim().to_uppercase();
+    let tag_value = Some(String::from(tag_value.trim())).filter(|v| !v.is_empty());
+    Some((tag_name, tag_value)).filter(|(name, _)| is_supported(name))
+}
+
+fn is_supported(tag_name: &str) -> bool {
+    SUPPORTED_TAGS.contains(&tag_name)
 }
 
 fn tag_editor_help() {
@@ -52,6 +119,59 @@ Commands:
   help, h         Show this help menu
   TAG             Clear TAG value
   TAG VALUE       Set TAG to VALUE (ARTIST may have multiple with ';'), e.g.: `ARTIST The Band;Singer`, `ARTIST Rapper`
+  swap TAG TAG    Exchange the values of two tags, e.g. `swap ARTIST TITLE` when they were extracted the wrong way around
 Supported tags (lowercase also allowed):
-  ARTIST, ALBUM, ALBUM_ARTIST, GENRE, TITLE, TRACK, YEAR");
+  ARTIST, ALBUM, ALBUM_ARTIST, COMMENT, DISC, GENRE, GROUPING, LABEL, TITLE, TRACK, YEAR
+  COMMENT and LABEL are the same tag, as the label is written to the comment");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_assignment() {
+        let set = |k: &str, v: &str| Some((String::from(k), Some(String::from(v))));
+        assert_eq!(parse_assignment("album=X"), set("ALBUM", "X"));
+        assert_eq!(parse_assignment("YEAR=2020"), set("YEAR", "2020"));
+        assert_eq!(parse_assignment("grouping=Work"), set("GROUPING", "Work"));
+        assert_eq!(parse_assignment("disc=2"), set("DISC", "2"));
+        assert_eq!(parse_assignment("comment=Note"), set("COMMENT", "Note"));
+        assert_eq!(parse_assignment("title=A = B"), set("TITLE", "A = B"));
+        assert_eq!(
+            parse_assignment("genre="),
+            Some((String::from("GENRE"), None))
+        );
+        assert_eq!(parse_assignment("genre"), None);
+        assert_eq!(parse_assignment("mood=happy"), None);
+    }
+
+    #[test]
+    fn swaps_values() {
+        let tags = |pairs: &[(&str, Option<&str>)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (String::from(*k), v.map(String::from)))
+                .collect::<HashMap<String, Option<String>>>()
+        };
+        let current = tags(&[("ARTIST", Some("Song")), ("TITLE", Some("Band;Singer"))]);
+
+        let mut edits = HashMap::new();
+        swap(&mut edits, &current, "ARTIST", "TITLE");
+        assert_eq!(
+            edits,
+            tags(&[("ARTIST", Some("Band;Singer")), ("TITLE", Some("Song"))])
+        );
+
+        let mut edits = tags(&[("TITLE", Some("Staged"))]);
+        swap(&mut edits, &current, "TITLE", "ALBUM");
+        assert_eq!(edits, tags(&[("TITLE", None), ("ALBUM", Some("Staged"))]));
+
+        assert_eq!(
+            parse_swap("swap artist TITLE"),
+            Some((String::from("ARTIST"), String::from("TITLE")))
+        );
+        assert_eq!(parse_swap("swap artist"), None);
+        assert_eq!(parse_swap("swap artist mood"), None);
+    }
 }
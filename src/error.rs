@@ -0,0 +1,205 @@
+//! Errors that callers (e.g. shell scripts via the exit code, or code embedding tapeworm) may want
+//! to tell apart.
+
+use serde_json::json;
+use std::error::Error;
+use std::path::PathBuf;
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum TapewormError {
+    /// Invalid arguments or configuration
+    InvalidConfig(String),
+    /// A line in lib.conf or the general config that is malformed, or sets an unknown option
+    InvalidConfigLine(String),
+    /// The given library path does not contain a library config folder
+    NotALibrary(String),
+    /// The input directory was not specified (`None`) or does not exist
+    MissingInputDir(Option<PathBuf>),
+    /// The target directory was not specified (`None`) or does not exist
+    MissingTargetDir(Option<PathBuf>),
+    /// The given command does not exist
+    UnknownCommand(String),
+    /// The downloader could not be run, or failed
+    Download(String),
+    /// Some, but not all, files could be processed
+    Partial(String),
+    /// Reading or writing a file failed
+    Io(io::Error),
+    /// Any other error, e.g. from a tag or scraping library
+    Other(String),
+}
+
+impl fmt::Display for TapewormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidConfig(msg) => write!(f, "{}", msg),
+            Self::InvalidConfigLine(line) => write!(f, "Invalid config line: {}", line),
+            Self::NotALibrary(path) => write!(f, "Not a library folder: {}", path),
+            Self::MissingInputDir(None) => write!(f, "Input directory not specified. See 'help'"),
+            Self::MissingInputDir(Some(dir)) => {
+                write!(f, "Input directory not found: {}", dir.display())
+            }
+            Self::MissingTargetDir(None) => write!(f, "Target directory not specified. See 'help'"),
+            Self::MissingTargetDir(Some(dir)) => {
+                write!(f, "Target directory not found: {}", dir.display())
+            }
+            Self::UnknownCommand(cmd) => write!(f, "Unrecognized command: {}. See 'help'", cmd),
+            Self::Download(msg) => write!(f, "Download failed: {}", msg),
+            Self::Partial(msg) => write!(f, "{}", msg),
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for TapewormError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TapewormError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<String> for TapewormError {
+    fn from(msg: String) -> Self {
+        Self::Other(msg)
+    }
+}
+
+impl From<&str> for TapewormError {
+    fn from(msg: &str) -> Self {
+        Self::Other(String::from(msg))
+    }
+}
+
+impl From<Box<dyn Error>> for TapewormError {
+    fn from(e: Box<dyn Error>) -> Self {
+        match e.downcast::<TapewormError>() {
+            Ok(e) => *e,
+            Err(e) => Self::Other(e.to_string()),
+        }
+    }
+}
+
+/// Errors of the libraries that tapeworm uses, which need no variant of their own.
+macro_rules! other_from {
+    ($($error:ty),*) => {
+        $(impl From<$error> for TapewormError {
+            fn from(e: $error) -> Self {
+                Self::Other(e.to_string())
+            }
+        })*
+    };
+}
+
+other_from!(
+    std::str::ParseBoolError,
+    std::num::ParseIntError,
+    std::num::ParseFloatError,
+    serde_json::Error,
+    regex::Error,
+    url::ParseError,
+    audiotags::Error,
+    id3::Error,
+    metaflac::Error
+);
+
+#[cfg(feature = "remote-input")]
+other_from!(ureq::Error);
+
+/// The process exit code for `error`:
+/// - 1: any other error
+/// - 2: invalid arguments or configuration
+/// - 3: not a library
+/// - 4: download failure
+/// - 5: partial failure
+pub fn exit_code(error: &(dyn Error + 'static)) -> i32 {
+    match error.downcast_ref::<TapewormError>() {
+        Some(
+            TapewormError::InvalidConfig(_)
+            | TapewormError::InvalidConfigLine(_)
+            | TapewormError::MissingInputDir(_)
+            | TapewormError::MissingTargetDir(_)
+            | TapewormError::UnknownCommand(_),
+        ) => 2,
+        Some(TapewormError::NotALibrary(_)) => 3,
+        Some(TapewormError::Download(_)) => 4,
+        Some(TapewormError::Partial(_)) => 5,
+        Some(TapewormError::Io(_) | TapewormError::Other(_)) | None => 1,
+    }
+}
+
+/// Serialize `error` for tools wrapping tapeworm (see `--json-errors`), as a JSON object with the
+/// variant (or `Error` for any other error) and its message, e.g.
+/// `{"error":"NotALibrary","message":"Not a library folder: x","path":"x"}`.
+pub fn error_json(error: &(dyn Error + 'static)) -> String {
+    let mut value = json!({
+        "error": "Error",
+        "message": error.to_string(),
+    });
+    if let Some(error) = error.downcast_ref::<TapewormError>() {
+        let variant = match error {
+            TapewormError::InvalidConfig(_)
+            | TapewormError::InvalidConfigLine(_)
+            | TapewormError::MissingInputDir(_)
+            | TapewormError::MissingTargetDir(_)
+            | TapewormError::UnknownCommand(_) => "InvalidConfig",
+            TapewormError::NotALibrary(path) => {
+                value["path"] = json!(path);
+                "NotALibrary"
+            }
+            TapewormError::Download(_) => "Download",
+            TapewormError::Partial(_) => "Partial",
+            TapewormError::Io(_) | TapewormError::Other(_) => "Error",
+        };
+        value["error"] = json!(variant);
+    }
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_exit_codes() {
+        let error: Box<dyn Error> = "Something went wrong".into();
+        assert_eq!(exit_code(error.as_ref()), 1);
+
+        let inputs = [
+            (TapewormError::Other(String::new()), 1),
+            (TapewormError::InvalidConfig(String::new()), 2),
+            (TapewormError::MissingTargetDir(None), 2),
+            (TapewormError::NotALibrary(String::new()), 3),
+            (TapewormError::Download(String::new()), 4),
+            (TapewormError::Partial(String::new()), 5),
+        ];
+        for (error, expected) in inputs {
+            let error: Box<dyn Error> = Box::new(error);
+            assert_eq!(exit_code(error.as_ref()), expected);
+        }
+    }
+
+    #[test]
+    fn serializes_errors() {
+        let error: Box<dyn Error> = "Something went wrong".into();
+        assert_eq!(
+            error_json(error.as_ref()),
+            r#"{"error":"Error","message":"Something went wrong"}"#
+        );
+
+        let error: Box<dyn Error> = Box::new(TapewormError::NotALibrary(String::from("x")));
+        assert_eq!(
+            error_json(error.as_ref()),
+            r#"{"error":"NotALibrary","message":"Not a library folder: x","path":"x"}"#
+        );
+    }
+}
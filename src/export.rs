@@ -0,0 +1,50 @@
+//! Export the library as a playlist.
+
+use crate::{types, util, Config};
+use audiotags::Tag;
+use std::path::{Path, PathBuf};
+
+/// Write an extended M3U playlist of the audio files in the target directory (and its subfolders,
+/// except `.tapeworm`) to `config.playlist_path`. Each entry is named `ARTIST - TITLE` after its
+/// tags, falling back to the filename.
+pub fn run(config: &Config) -> types::UnitResult {
+    let playlist_path = config.playlist_path.as_ref().unwrap();
+    let playlist_dir = playlist_path.parent().unwrap_or(Path::new(""));
+
+    let mut files =
+        util::filepaths_in_recursive(config.target_dir.as_ref().unwrap(), &config.lib_dir)?;
+    files.sort();
+
+    let mut playlist = String::from("#EXTM3U\n");
+    let mut count = 0;
+    for file in files {
+        if let Some(entry) = entry_for(&file, playlist_dir) {
+            playlist.push_str(&entry);
+            count += 1;
+        }
+    }
+
+    util::write(playlist_path, playlist)?;
+    println!("Exported {} files to {}", count, playlist_path.display());
+    Ok(())
+}
+
+/// # Returns
+/// The `#EXTINF` line and path of the `file`, relative to the `playlist_dir` when inside of it, or
+/// `None` when the file is not a (supported) audio file. An unknown duration is written as `-1`.
+fn entry_for(file: &PathBuf, playlist_dir: &Path) -> Option<String> {
+    let ftag = Tag::new().read_from_path(file).ok()?;
+    let name = match (ftag.artist(), ftag.title()) {
+        (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+        (None, Some(title)) => String::from(title),
+        _ => file.file_stem()?.to_string_lossy().into_owned(),
+    };
+    let duration = util::duration_of(file, ftag.as_ref()).map_or(-1, |d| d.round() as i64);
+    let path = file.strip_prefix(playlist_dir).unwrap_or(file);
+    Some(format!(
+        "#EXTINF:{},{}\n{}\n",
+        duration,
+        name,
+        path.display()
+    ))
+}
@@ -0,0 +1,86 @@
+//! Keep a log of the operations run on a library, in `.tapeworm/history.log`.
+
+use crate::{types, util, Config};
+use chrono::Local;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use tabwriter::TabWriter;
+
+const HISTORY_FILE: &str = "history.log";
+
+/// Append the `operation` (e.g. "deposit") and a `summary` of what it did to the history log, as a
+/// tab-separated line with the current time. Nothing is logged outside of a library.
+///
+/// Logging is best-effort: the operation already happened, so a log that cannot be written only
+/// results in a warning on stderr.
+pub fn record(config: &Config, operation: &str, summary: &str) {
+    let Some(path) = history_path(config) else {
+        return;
+    };
+    let time = Local::now().format("%Y-%m-%d %H:%M:%S");
+    let line = format!("{}\t{}\t{}\n", time, operation, summary);
+    if let Err(e) = util::append(&path, line) {
+        eprintln!("Warning! Could not write to {}: {}", path.display(), e);
+    }
+}
+
+/// Print the `config.history_limit` most recent entries of the history log, most recent first.
+pub fn run(config: &Config) -> types::UnitResult {
+    let contents = history_path(config)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default();
+    let entries = parse(&contents);
+    if entries.is_empty() {
+        println!("No history");
+        return Ok(());
+    }
+
+    let mut tw = TabWriter::new(io::stdout().lock());
+    writeln!(&mut tw, "TIME\tOPERATION\tSUMMARY")?;
+    for (time, operation, summary) in entries.iter().rev().take(config.history_limit) {
+        writeln!(&mut tw, "{}\t{}\t{}", time, operation, summary)?;
+    }
+    tw.flush()?;
+
+    if entries.len() > config.history_limit {
+        let older = entries.len() - config.history_limit;
+        println!("\n{} older entries not shown, see --limit", older);
+    }
+    Ok(())
+}
+
+fn history_path(config: &Config) -> Option<PathBuf> {
+    Some(config.lib_conf_path.as_ref()?.parent()?.join(HISTORY_FILE))
+}
+
+/// # Returns
+/// The `(time, operation, summary)` entries of the history log `contents`, oldest first. Malformed
+/// lines are left out.
+fn parse(contents: &str) -> Vec<(&str, &str, &str)> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            Some((fields.next()?, fields.next()?, fields.next()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_history() {
+        let contents = "2024-05-01 10:00:00\tdownload\t3 inputs\nnot an entry\n\
+                        2024-05-02 11:00:00\tdeposit\t2 files moved\n";
+        assert_eq!(
+            parse(contents),
+            vec![
+                ("2024-05-01 10:00:00", "download", "3 inputs"),
+                ("2024-05-02 11:00:00", "deposit", "2 files moved"),
+            ]
+        );
+    }
+}
@@ -1,8 +1,14 @@
-use crate::{types, util, Config};
-use std::fs;
-use std::io::{self, Write};
+use crate::command::Command::{self, *};
+use crate::{scrape, types, util, Config, TapewormError};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use tabwriter::TabWriter;
 
+/// How much of a README is read for the description, so a huge README does not slow down `show`.
+const MAX_README_PREFIX: u64 = 16 * 1024;
+
 /// Show the library's status and discovered config files.
 pub fn show(config: &Config) -> types::UnitResult {
     println!(
@@ -12,31 +18,43 @@ pub fn show(config: &Config) -> types::UnitResult {
     if let Some(alias) = &config.lib_alias {
         println!("  > Alias: {}", alias);
     }
-    if let Some(desc) = &config.lib_desc {
-        println!("  > Description: {}", desc);
+    if let Some(desc) = config
+        .lib_desc
+        .clone()
+        .or_else(|| readme_description(config))
+    {
+        println!(
+            "  > Description: {}",
+            desc.replace('\n', "\n                 ")
+        );
     }
     println!();
 
-    let input_dir = config.input_dir.as_ref().unwrap();
-    println!("  Input folder: {}", input_dir.display());
-    let mut n = 0;
-    if let Ok(files) = util::filepaths_in(input_dir) {
-        n = files.len()
+    for input_dir in &config.input_dirs {
+        println!("  Input folder: {}", input_dir.display());
+        let mut n = 0;
+        if let Ok(files) = util::filepaths_in(input_dir) {
+            n = files.len()
+        }
+        println!("  > {} files", n);
+        warn_if_not_writable(input_dir);
+        println!();
     }
-    println!("  > {} files", n);
-    println!();
 
     let output_dir = config.target_dir.as_ref().unwrap();
     println!("  Target folder: {}", output_dir.display());
+    warn_if_not_writable(output_dir);
     println!();
 
     println!("  Configuration files:");
+    warn_if_not_writable(config.lib_conf_path.as_ref().unwrap().parent().unwrap());
     let input_path = config.input_path.as_ref().unwrap();
     if fs::metadata(input_path).is_ok() {
         print!("  > input.txt : ");
-        let count = fs::read_to_string(input_path)?
+        let count = util::read_text_file(input_path, config.encoding)?
             .lines()
-            .filter(|line: &&str| !line.trim().is_empty())
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
             .count();
         println!("{} to download", count);
     }
@@ -46,96 +64,346 @@ pub fn show(config: &Config) -> types::UnitResult {
     if fs::metadata(config.yt_dlp_conf_path.as_ref().unwrap()).is_ok() {
         println!("  > yt-dlp.conf");
     }
-
     println!();
+
+    if let Ok(credentials) = scrape::spotify_credentials(config) {
+        println!("  Spotify API client: {}", credentials.client_id);
+        println!();
+    }
     Ok(())
 }
 
+/// The first `config.description_lines` non-empty lines of the README of the library, which is the
+/// first of `README` and `README.md` in the library config folder or else the library folder.
+/// Markdown heading markers are stripped.
+fn readme_description(config: &Config) -> Option<String> {
+    let lib_conf_folder = config.lib_conf_path.as_ref()?.parent()?;
+    let lib_path = config.lib_path.as_ref()?;
+    let readme = [lib_conf_folder, lib_path]
+        .iter()
+        .flat_map(|dir| ["README", "README.md"].map(|name| dir.join(name)))
+        .find(|path| path.is_file())?;
+
+    let mut prefix = Vec::new();
+    File::open(readme)
+        .ok()?
+        .take(MAX_README_PREFIX)
+        .read_to_end(&mut prefix)
+        .ok()?;
+    let lines = String::from_utf8_lossy(&prefix)
+        .lines()
+        .map(|line| line.trim_start_matches('#').trim())
+        .filter(|line| !line.is_empty())
+        .take(config.description_lines)
+        .collect::<Vec<&str>>()
+        .join("\n");
+    Some(lines).filter(|lines| !lines.is_empty())
+}
+
+/// Print a warning if no files can be created in `dir`, as commands like `tag` and `deposit` would
+/// fail on it later on.
+fn warn_if_not_writable(dir: &Path) {
+    if fs::metadata(dir).is_err() {
+        println!("  ! Folder does not exist");
+    } else if !util::is_writable(dir) {
+        println!("  ! Folder is not writable");
+    }
+}
+
 /// Print the list of aliases.
+/// With `config.list_grouping`, the aliases are listed per group, under a header per group.
 pub fn list(config: &Config) {
     let mut tw = TabWriter::new(io::stdout().lock());
     writeln!(&mut tw, "ALIAS\tLIBRARY PATH").unwrap();
-    for (alias, path) in &config.aliases {
-        writeln!(&mut tw, "{}\t{}", alias, path.display()).unwrap();
+    for (group, aliases) in group_aliases(config) {
+        if config.list_grouping != ListGrouping::None {
+            writeln!(&mut tw, "\n{}", group).unwrap();
+        }
+        for (alias, path) in aliases {
+            writeln!(&mut tw, "{}\t{}", alias, path.display()).unwrap();
+        }
     }
     tw.flush().unwrap();
 }
 
-pub fn help() {
-    println!(
-        "\
+/// How `list` groups the aliases.
+#[derive(Debug, Default, PartialEq)]
+pub enum ListGrouping {
+    /// A flat list
+    #[default]
+    None,
+    /// By the parent folder of the library
+    Parent,
+}
+
+impl ListGrouping {
+    pub fn from(s: &str) -> Result<Self, TapewormError> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "parent" => Ok(Self::Parent),
+            _ => Err(format!("Invalid grouping: '{}'. See 'help'", s).into()),
+        }
+    }
+}
+
+/// # Returns
+/// The aliases per group (see `config.list_grouping`), both sorted. Without grouping, all aliases
+/// are in a single group named "".
+fn group_aliases(config: &Config) -> BTreeMap<String, Vec<(&String, &PathBuf)>> {
+    let mut groups: BTreeMap<String, Vec<(&String, &PathBuf)>> = BTreeMap::new();
+    for (alias, path) in &config.aliases {
+        let group = match config.list_grouping {
+            ListGrouping::None => String::new(),
+            ListGrouping::Parent => path
+                .parent()
+                .map(|parent| parent.display().to_string())
+                .unwrap_or_default(),
+        };
+        groups.entry(group).or_default().push((alias, path));
+    }
+    groups
+}
+
+/// Load the lib.conf of each library alias, and report its invalid lines (if any), without running
+/// any command.
+///
+/// # Errors
+/// - `TapewormError::Partial`: if any of the libraries has an invalid configuration
+pub fn check_all(config: &Config) -> types::UnitResult {
+    let mut broken = Vec::new();
+    for alias in config.aliases.keys() {
+        let errors = config.check_library(alias);
+        if errors.is_empty() {
+            println!("{}: OK", alias);
+            continue;
+        }
+
+        println!("{}: {} error(s)", alias, errors.len());
+        for (line, e) in errors {
+            match line {
+                Some(line) if !matches!(e, TapewormError::InvalidConfigLine(_)) => {
+                    println!("  {}: {}", line, e)
+                }
+                _ => println!("  {}", e),
+            }
+        }
+        broken.push(alias.as_str());
+    }
+
+    if broken.is_empty() {
+        Ok(())
+    } else {
+        Err(TapewormError::Partial(format!(
+            "{} of {} libraries have an invalid configuration: {}",
+            broken.len(),
+            config.aliases.len(),
+            broken.join(", ")
+        )))
+    }
+}
+
+/// The introduction of the full help, followed by the help of each command.
+const HEADER: &str = "\
 tapeworm - A scraper and downloader written in Rust
 
 COMMANDS
     If a command takes [OPTIONS] (sic), the GENERAL OPTIONS also apply.
     Note that LIBRARY refers to either the library path or its alias.
+    LIBRARY may also be a glob pattern, e.g. \"music-*\" (`*` matches anything, `?` any single character), to run the command on every matching alias. The result of each library is reported at the end.";
+const HELP: &str = "    help, h, -h, --help [COMMAND]
+        Show this help message, or only the help for COMMAND (e.g. `help tag`). Also available as `COMMAND --help` and `LIBRARY COMMAND --help`";
+const LIST: &str = "    list, ls, l [--group-by GROUPING]
+        List all library aliases
 
-    help, h, -h, --help
-        Show this help message
+        OPTIONS
+        --group-by GROUPING
+                    `parent` lists the aliases per parent folder of their library, `none` (the default) as a flat list";
+const CHECK_ALL: &str = "    check-all
+        Load the lib.conf of every library alias and report its invalid lines, e.g. unknown options or invalid values, without running any command. Useful after editing many configs at once. Exits with code 5 when any library has an invalid configuration";
+const EXPORT: &str = "    LIBRARY export -o FILE
+        Write an extended M3U playlist of the audio files in TARGET_DIR (and its subfolders) to FILE. Each entry is named \"ARTIST - TITLE\" after its tags (or its filename, when it has no 'title' tag), and refers to the file relative to the folder of FILE when inside of it";
+const STATS: &str = "    LIBRARY stats
+        Summarize the LIBRARY: the number of audio files (and how many lack a 'title' tag), their total size, the number of inputs left in input.txt, and the number of files per artist and per genre. With VERBOSE, each file is listed with its artist and title as well";
+const HISTORY: &str = "    LIBRARY history [--limit N]
+        Show the most recent downloads, tag runs, deposits and cleans of the LIBRARY, most recent first, with the time and a summary of each. The history is kept in .tapeworm/history.log. Dry runs are not recorded
 
-    list, ls, l
-        List all library aliases
+        OPTIONS
+        --limit N   Show the N most recent entries, instead of 20";
+const VERIFY: &str = "    LIBRARY verify [--library]
+        Check that the audio files in INPUT_DIR have all REQUIRED_TAGS, and list the files that miss any. Files that cannot be read (e.g. because they are not audio files) are reported and skipped. Exits with code 5 when any file misses a required tag, e.g. for a check before `deposit`
 
-    LIBRARY
-        Show information about the LIBRARY
+        OPTIONS
+        --library   Check the audio files in the LIBRARY (and its subfolders, except .tapeworm) instead";
+const TEMPLATE_TEST: &str = "    template-test TEMPLATE [--TAG VALUE...]
+        Print the TEMPLATE (see TITLE_TEMPLATE and FILENAME_TEMPLATE) rendered with the given tag values, without touching any files. TAG is one of the template tokens, e.g. `template-test \"{artist} - {title} [{remix}]\" --artist A --title T --remix Edit`. As when editing tags, --artist may list multiple artists separated by ';', all but the first of which end up in {feat}";
+const SHOW: &str = "    LIBRARY
+        Show information about the LIBRARY. When LIBRARY is an alias with a bound command (`alias=path;command` in the general config), run that command instead";
+const ADD: &str = "    LIBRARY add [-a] [-n N] [-f FILE] [--check-duplicates] [--allow-duplicates] [--resume-scrape] [--input-format FORMAT] [--encoding ENCODING] TERM|URL [TERM|URL...]
+        Add TERMs and/or URLs to the LIBRARY. TERMs are added as YouTube search queries. A URL is simply added, unless it points to a Spotify playlist or SoundCloud set. In this case, it will be scraped, and the found songs are added as YouTube search queries. This is because of Spotify DRM restrictions. A YouTube playlist is scraped for its videos, which are added as separate URLs (or as is, when scraping fails), and the playlist is stripped from YouTube video URLs. When a scrape yields more than SCRAPE_WARN_THRESHOLD songs, confirmation is asked before adding them. Inputs that contain a term of .tapeworm/blocklist.txt (one per line, ignoring case) are skipped.
 
-    LIBRARY add TERM|URL [TERM|URL...]
-        Add TERMs and/or URLs to the LIBRARY. TERMs are added as YouTube search queries. A URL is simply added, unless it points to a Spotify playlist. In this case, it will be scraped, and the found songs are added as YouTube search queries. This is because of Spotify DRM restrictions.
+        OPTIONS
+        -a          Add all scraped songs without confirmation
+        -n N        Download the top N search results of each TERM, instead of only the first (see SEARCH_RESULTS). 0 means all results
+        -f, --from-file FILE
+                    Also add the terms in FILE, one TERM or URL per line. Blank lines and lines starting with '#' are ignored. May be given multiple times, and TERMs may be omitted
+        --check-duplicates
+                    Ask before adding a TERM that looks like a file in TARGET_DIR already, based on the words it shares with the file's artist and title tags. With -a, such TERMs are only reported
+        --allow-duplicates
+                    Also add inputs that are in input.txt already. By default, these are skipped, comparing queries case-insensitively and URLs exactly
+        --resume-scrape
+                    Continue an interrupted scrape of a URL from its checkpoint in .tapeworm/cache/, instead of starting over. The checkpoint is written while scrolling, and removed once the end of the list is reached
+        --input-format FORMAT
+                    Read the inputs from the files given instead of TERMs. FORMAT is `csv` for `artist,title` rows, or `json` for an array of strings (URLs or queries) and/or objects with a `title` and optional `artist`. Malformed rows are reported and skipped
+        --encoding ENCODING
+                    Read the files given to --input-format as `latin1` (ISO-8859-1) instead of `utf-8`, see ENCODING
 
-        Note that YouTube search queries can be downloaded by yt-dlp.
+        Note that YouTube search queries can be downloaded by yt-dlp.";
+const REFRESH: &str = "    LIBRARY refresh
+        Scrape the Spotify playlists (and SoundCloud sets) previously added to the LIBRARY again, and add any songs that are not in input.txt yet. Scraped songs are stored in input.txt below a \"# source: URL\" line, which is how the playlist is remembered. Note that clearing the input file also forgets the playlists.";
+const REMOVE: &str = "    LIBRARY remove [-a|--all] TERM|URL [TERM|URL...]
+        Remove the TERMs and/or URLs from input.txt, e.g. a query added by mistake. A TERM matches its YouTube search query whatever its number of search results, so `remove TERM` undoes `add TERM`. Comments (such as \"# source: URL\" lines) are kept
 
-    LIBRARY download [OPTIONS]
-        Given the inputs in ~/.config/tapeworm/LIBRARY/input.txt, scrape any queries and download all (scraped) URLs, using the config in ~/.config/tapeworm/LIBRARY/yt-dlp.conf
+        OPTIONS
+        -a, --all   Clear input.txt completely, including comments";
+const DEDUPE_INPUTS: &str = "    LIBRARY dedupe-inputs
+        Remove duplicate inputs from input.txt, keeping the first occurrence of each. Comments (such as \"# source: URL\" lines) are kept";
+const PRUNE_INPUTS: &str = "    LIBRARY prune-inputs [OPTIONS]
+        Report the queries in input.txt that appear to be satisfied by a file in TARGET_DIR already, i.e. the query mentions all words of the file's artist and title tags. URLs and comments are never matched
 
         OPTIONS
-        -c          Clear the input file after scraping
-        -a          Automatically keep downloads (no confirmation prompt)
+        -o TARGET_DIR   Look for files in TARGET_DIR (and its subfolders) instead of the one in lib.conf
+        --remove        Remove the reported queries from input.txt
+        --encoding ENCODING
+                        Read input.txt as `latin1` (ISO-8859-1) instead of `utf-8`, see ENCODING";
+const DOWNLOAD: &str = "    LIBRARY download [OPTIONS]
+        Given the inputs in ~/.config/tapeworm/LIBRARY/input.txt, scrape any queries and download all (scraped) URLs, using the config in ~/.config/tapeworm/LIBRARY/yt-dlp.conf. Downloads whose filename contains a term of .tapeworm/blocklist.txt are moved to the .tapeworm/trash folder without asking. With DOWNLOAD_ARCHIVE set in lib.conf (or --download-archive in yt-dlp.conf), YouTube videos that are already in the archive are skipped. Note that with -c (or CLEAR_INPUT), skipped inputs are still removed from the input file
 
-    LIBRARY tag [OPTIONS]
-        Tag all files in the input directory
+        OPTIONS
+        -c          Clear the input file after scraping
+        --keep-going
+                    Download each input separately, so a failing input (e.g. a private video) does not stop the others. Failed inputs are reported at the end, and kept when clearing the input file
+        --input-url URL
+                    Fetch the inputs from URL instead of reading input.txt (which is left untouched). Requires the 'remote-input' feature
+        --input-format FORMAT
+                    Read the input file (or URL) as `csv` or `json` instead of one input per line, see `add`. A structured input file is left untouched
+        --encoding ENCODING
+                    Read the input file as `latin1` (ISO-8859-1) instead of `utf-8`, see ENCODING. When clearing (or removing inputs from) the input file, it is rewritten in ENCODING as well. Inputs with characters outside of Latin-1 cannot be written to a `latin1` input file, which is an error
+        -a          Automatically keep downloads (no confirmation prompt). Rejected downloads are moved to the .tapeworm/trash folder, optionally removing their input from input.txt";
+const TAG: &str = "    LIBRARY tag [OPTIONS]
+        Tag all files in the input directory. When editing the ALBUM, ALBUM_ARTIST, GENRE or YEAR of a proposal, you are asked whether to apply the edited values to all remaining files as well, e.g. when tagging an album. Answering `a` (yes to All) to a proposal accepts it and all remaining proposals without asking, as with -t
 
         OPTIONS
-        -i IN       What directory to look in for files to tag. By default, this is the `.tapeworm/tmp` folder
+        -i IN       What directory to look in for files to tag. By default, this is the `.tapeworm/tmp` folder. Separate multiple directories by `:` (or `;` on Windows), as in PATH, e.g. `-i dir1:dir2`
         -t          Automatically write discovered tags (no confirmation prompt and no edit possibility)
-
-    LIBRARY deposit [OPTIONS]
+        --file FILE Only tag FILE, instead of all files in the input directory
+        --dry-run   Only show the proposed tags and filenames, without writing or renaming anything. There are no prompts, so this is useful to review how well tags are extracted from a large batch. Also applies to --rename-only
+        --rename-only
+                    Only rename the files according to FILENAME_TEMPLATE, based on their current tags (see `rename`). Tags are not extracted or written, and there are no prompts
+        --min-duration SECONDS
+                    Skip audio files shorter than SECONDS. Files of which the duration cannot be determined are not skipped
+        --max-duration SECONDS
+                    Skip audio files longer than SECONDS, e.g. full albums uploaded as a single file
+        --json      Print each proposal as a JSON object (old and proposed value per tag) instead of prompting. Tags are only written when combined with -t
+        --resume    Skip files already handled by a previous, interrupted run
+        --report-skips
+                    List the skipped files in .tapeworm/skipped.txt, grouped by the reason they were skipped for
+        --lyrics    Embed the contents of a .lrc or .txt file with the same name as the audio file as its lyrics (mp3 and flac only)
+        --recursive Also tag files in subfolders of the input directory
+        --album-from-folder
+                    Treat each folder as one album: set the album of all its files to their most common album tag (or else the folder name), and number tracks without a track number by their sorted order. Typically combined with --recursive
+        --from-path Infer tags from the location of files organized as `IN/ARTIST/ALBUM/TITLE.ext`, instead of from the 'title' tag. Typically combined with --recursive
+        --set TAG=VALUE
+                    Set TAG to VALUE for every file, overriding extracted values, e.g. `--set album=X --set year=2020`. May be repeated. An empty VALUE clears the tag. See the tag editor for supported tags
+        --backup    Copy the files into `.tapeworm/backup/<timestamp>/` before tagging
+        --max-files N
+                    Only tag the first N files (sorted by path), e.g. to try out a config on a sample";
+const DEPOSIT: &str = "    LIBRARY deposit [OPTIONS]
         Move downloaded files to the directory specified by TARGET_DIR
 
         OPTIONS
         -d MODE     Organize files into the output directory. MODE is one of the following:
                     - \"A-Z\": Sort into alphabetic subfolders, and possibly ARTIST and ALBUM subfolders
-                    - \"DATE\": Sort into YYYY/MM subfolders
+                    - \"ARTIST\": Sort into ARTIST and ALBUM subfolders, without the alphabetic subfolder
+                    - \"DATE\": Sort into YYYY/MM subfolders by the file creation date (or the release date with DATE_SOURCE=tag), see --date-format
+                    - \"TAG-DATE\": Sort into YYYY/MM subfolders by the DATE tag, falling back to YYYY by the year of the DATE or YEAR tag, then to YYYY/MM by the file modification date
+                    - \"GENRE\": Sort into GENRE subfolders, or `Unknown` for files without a genre tag
                     - \"DROP\": Drop files directly in TARGET_DIR
-        -i IN       What directory to find files in. By default, this is the `.tapeworm/tmp` folder
+        --date-format FORMAT
+                    How DATE formats the file creation date into subfolders, e.g. \"%Y-%m-%d\" or \"%Y/%m/%d\". Defaults to \"%Y/%m\"
+        --path-template TEMPLATE
+                    Organize files into subfolders built from tags, e.g. \"{genre}/{artist}/{year} - {album}\". Empty path segments are left out
+        --by-quality
+                    Separate lossless (e.g. FLAC, WAV) from lossy (e.g. MP3, Opus) audio files into `Lossless/` and `Lossy/` subfolders, which are then organized per MODE
+        --preserve-subdir
+                    Also deposit the files in subfolders of IN, and recreate their subfolders (relative to IN) in OUT instead of organizing per MODE, e.g. `IN/source/Song.mp3` goes to `OUT/source/Song.mp3`
+        -i IN       What directory to find files in. By default, this is the `.tapeworm/tmp` folder. Separate multiple directories by `:` (or `;` on Windows), as in PATH, e.g. `-i dir1:dir2`
         -o OUT      What directory to move files to. By default, this is the library root folder
-
-    LIBRARY process [OPTIONS]
+        --min-duration SECONDS
+                    Skip audio files shorter than SECONDS, see `tag`
+        --max-duration SECONDS
+                    Skip audio files longer than SECONDS, see `tag`
+        --backup    Copy the files into `.tapeworm/backup/<timestamp>/` before moving them
+        -k, --keep  Copy the files instead of moving them, keeping the originals in IN. Useful when trying out organization modes. Existing files are still only overwritten upon confirmation
+        -n, --dry-run
+                    Only print where each file would go (marking targets that already exist), and how many files would be moved, without moving anything or prompting. Nothing is backed up or tagged (see --tag-first) either. In `process`, this also applies to the tag step (see `tag --dry-run`), but not to downloading
+        --remember-overwrite
+                    Remember the answer to each overwrite prompt in `.tapeworm/state.json`, and apply it to the same target file in later deposits instead of asking again
+        --forget-overwrites
+                    Forget all remembered overwrite answers
+        --max-files N
+                    Only move the first N files (sorted by path), see `tag`
+        --target-per-artist-limit N
+                    With A-Z organization, put at most N files in each artist folder. Further files go into `Part 2/`, `Part 3/`, etc. subfolders of the artist folder, see PER_ARTIST_LIMIT
+        --tag-first Tag each file (as with `tag -t`) right before moving it, so the new tags drive the organization. Using `process -s tag,deposit` is preferred, but this avoids a separate pass over the files";
+const UNDO: &str = "    LIBRARY undo
+        Move the files of the last deposit back to where they came from, as recorded in .tapeworm/last_deposit.json. Nothing is moved when any of the files is no longer in the target directory, or when another file took its original place; these conflicts are listed instead. Only the files that were actually moved are recorded, so after a deposit that moved nothing (e.g. copies with deposit --keep) there is nothing to undo. Dry runs are not recorded. Empty folders are left in the target directory, see `clean`";
+const PROCESS: &str = "    LIBRARY process [OPTIONS]
         Process LIBRARY as specified by `STEPS`. Any options from `download`, `tag`, `deposit` are valid here
 
         OPTIONS
         -s          Set the processing steps (commands) to run on the library as a comma-separated list, required if not set in lib.conf
-
-    LIBRARY clean OPTIONS
+        --report FILE
+                    Write a JSON summary of each step to FILE, e.g. the number of tagged files and the moved files. A failed step records its error";
+const CLEAN: &str = "    LIBRARY clean OPTIONS
         Removes empty folders from the target directory
 
         OPTIONS
         -o TARGET   What directory to clean. By default, this is the library root folder
+        --dry-run   Only print the folders that would be removed, and how many
+        --backup    Recreate the folders in `.tapeworm/backup/<timestamp>/` before removing them";
+const RENAME: &str = "    LIBRARY rename [OPTIONS]
+        Rename files in the target directory (and its subfolders) according to FILENAME_TEMPLATE, based on their current tags
 
-    LIBRARY alias [ALIAS|-r]
-        Configure the ALIAS for a library. With an alias, any library command can be specified with the alias instead of the full library path. Without an option, this command will show the library path for ALIAS
+        OPTIONS
+        -o TARGET   What directory to rename files in. By default, this is the library root folder
+        --list      Only print the old and new filenames and how many files would be renamed, without renaming";
+const ALIAS: &str = "    LIBRARY alias [ALIAS|-r] [--dry-run]
+        Configure the ALIAS for a library. With an alias, any library command can be specified with the alias instead of the full library path. Without an option, this command will show the library path for ALIAS. Any changes to the aliases are printed
 
         OPTION
         ALIAS       When LIBRARY is an alias, change the alias to ALIAS. When LIBRARY is a path, add (another) alias as ALIAS
         -r          When LIBRARY is an alias, remove the alias. When LIBRARY is a path, remove all aliases for that path
-
-GENERAL OPTIONS
-    The options from path/to/library/.tapeworm/lib.conf are loaded first.
+        --dry-run   Only print the changes, without saving them";
+const GENERAL_OPTIONS: &str = "    The options from path/to/library/.tapeworm/lib.conf are loaded first.
     Setting a CLI option will override its value in the lib.conf file, if present.
 
     -v      Verbosely show what is being processed
-
-EXAMPLE
-    tapeworm LIBRARY add song  # records 'ytsearch:song'
+    -V, --no-verbose
+            Don't show verbose output, even when VERBOSE is enabled in lib.conf
+    --lib-dir NAME
+            Use NAME as the library config folder, instead of `.tapeworm` (or the TAPEWORM_DIR environment variable, if set). Applies to any command that takes LIBRARY
+    --yes   Answer all prompts with yes, i.e. keep all downloads, write all tags and overwrite existing files. Applies to download, tag, deposit and process. When stdin is not a terminal (e.g. in a cron job), these commands refuse to run unless they cannot prompt, i.e. when using --yes or the relevant -a, -t, AUTO_* or ON_CONFLICT options
+    --force-auto
+            Apply the auto options (e.g. --yes, -a, -t) even to batches larger than CONFIRM_THRESHOLD. Applies to download, tag, deposit and process
+    --json-errors
+            Print errors as a JSON object on stderr, e.g. {\"error\":\"NotALibrary\",\"message\":\"...\",\"path\":\"...\"}. The error is one of the EXIT CODES below (InvalidConfig, NotALibrary, Download, Partial), or Error for any other error. Applies to any command";
+const EXIT_CODES: &str = "    0  Success
+    1  Any other error
+    2  Invalid arguments or configuration
+    3  LIBRARY is not a library
+    4  Downloading failed
+    5  Partial failure, e.g. some files could not be deposited";
+const EXAMPLE: &str = "    tapeworm LIBRARY add song  # records 'ytsearch:song'
     tapeworm LIBRARY add \"the artist - a song\"  # records 'ytsearch:the artist - a song'
     tapeworm LIBRARY add https://youtube.com/watch?v=123
 
@@ -145,7 +413,159 @@ EXAMPLE
     tapeworm LIBRARY deposit -d A-Z
 
     # Alternatively, using process steps
-    tapeworm LIBRARY process -s download,tag,deposit -d A-Z
-"
-    );
+    tapeworm LIBRARY process -s download,tag,deposit -d A-Z";
+/// Print the help for a single `command`, or the full help when no command is given.
+pub fn help(command: Option<&Command>) {
+    let Some(command) = command else {
+        let commands = [
+            Help,
+            List,
+            CheckAll,
+            TemplateTest,
+            Show,
+            Add,
+            Refresh,
+            Remove,
+            DedupeInputs,
+            PruneInputs,
+            Download,
+            Tag,
+            Deposit,
+            Undo,
+            Process,
+            Clean,
+            Rename,
+            Export,
+            Stats,
+            History,
+            Verify,
+            Alias,
+        ]
+        .map(|c| command_help(&c));
+        println!(
+            "{}\n\n{}\n\nGENERAL OPTIONS\n{}\n\nEXIT CODES\n{}\n\nEXAMPLE\n{}",
+            HEADER,
+            commands.join("\n\n"),
+            GENERAL_OPTIONS,
+            EXIT_CODES,
+            EXAMPLE
+        );
+        return;
+    };
+
+    println!("{}", command_help(command));
+    if command.uses_cli() {
+        println!("\nGENERAL OPTIONS\n{}", GENERAL_OPTIONS);
+    }
+    if let Some(examples) = examples_for(command) {
+        println!("\nEXAMPLE\n{}", examples);
+    }
+}
+
+fn command_help(command: &Command) -> &'static str {
+    match command {
+        Help => HELP,
+        List => LIST,
+        CheckAll => CHECK_ALL,
+        TemplateTest => TEMPLATE_TEST,
+        Show => SHOW,
+        Add => ADD,
+        Refresh => REFRESH,
+        Remove => REMOVE,
+        DedupeInputs => DEDUPE_INPUTS,
+        PruneInputs => PRUNE_INPUTS,
+        Download => DOWNLOAD,
+        Tag => TAG,
+        Deposit => DEPOSIT,
+        Undo => UNDO,
+        Process => PROCESS,
+        Clean => CLEAN,
+        Rename => RENAME,
+        Export => EXPORT,
+        Stats => STATS,
+        History => HISTORY,
+        Verify => VERIFY,
+        Alias => ALIAS,
+    }
+}
+
+fn examples_for(command: &Command) -> Option<&'static str> {
+    match command {
+        Add => Some(
+            "    tapeworm LIBRARY add song  # records 'ytsearch:song'
+    tapeworm LIBRARY add \"the artist - a song\"  # records 'ytsearch:the artist - a song'
+    tapeworm LIBRARY add https://youtube.com/watch?v=123",
+        ),
+        Alias => {
+            Some("    tapeworm path/to/library alias music  # then use as `tapeworm music ...`")
+        }
+        Deposit => Some("    tapeworm LIBRARY deposit -d A-Z"),
+        Download => Some("    tapeworm LIBRARY download -c --keep-going"),
+        Process => Some("    tapeworm LIBRARY process -s download,tag,deposit -d A-Z"),
+        Tag => Some("    tapeworm LIBRARY tag --max-files 5  # try out on a sample first"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_aliases_by_parent() {
+        let mut config = Config::default();
+        for (alias, path) in [("b", "/music/b"), ("a", "/music/a"), ("v", "/videos/v")] {
+            config
+                .aliases
+                .insert(String::from(alias), PathBuf::from(path));
+        }
+        fn names(config: &Config) -> Vec<(String, Vec<&str>)> {
+            group_aliases(config)
+                .into_iter()
+                .map(|(group, aliases)| {
+                    let aliases = aliases.iter().map(|(alias, _)| alias.as_str()).collect();
+                    (group, aliases)
+                })
+                .collect()
+        }
+
+        assert_eq!(names(&config), vec![(String::new(), vec!["a", "b", "v"])]);
+        config.list_grouping = ListGrouping::Parent;
+        assert_eq!(
+            names(&config),
+            vec![
+                (String::from("/music"), vec!["a", "b"]),
+                (String::from("/videos"), vec!["v"]),
+            ]
+        );
+        assert!(ListGrouping::from("tag").is_err());
+    }
+
+    #[test]
+    fn describes_from_readme() {
+        let dir = std::env::temp_dir().join(format!("tapeworm-readme-{}", std::process::id()));
+        let lib_conf_folder = dir.join(".tapeworm");
+        fs::create_dir_all(&lib_conf_folder).unwrap();
+        let mut config = Config::default();
+        config.lib_path = Some(dir.clone());
+        config.lib_conf_path = Some(lib_conf_folder.join("lib.conf"));
+        assert_eq!(readme_description(&config), None);
+
+        fs::write(dir.join("README.md"), "# My Library\n\nSome music\nMore\n").unwrap();
+        assert_eq!(
+            readme_description(&config),
+            Some(String::from("My Library"))
+        );
+        config.description_lines = 2;
+        assert_eq!(
+            readme_description(&config),
+            Some(String::from("My Library\nSome music"))
+        );
+
+        // The README in the library config folder takes precedence
+        fs::write(lib_conf_folder.join("README"), "\n\n").unwrap();
+        assert_eq!(readme_description(&config), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -1,58 +1,220 @@
-pub use crate::download::{Downloader, YtDlp};
+pub use crate::download::{DownloadEvent, Downloader, YtDlp};
+pub use crate::error::{error_json, exit_code, TapewormError};
 
 mod add;
 mod alias;
 mod clean;
 mod command;
+mod dedupe;
 mod deposit;
 mod download;
 mod editor;
+mod error;
+mod export;
+mod history;
 mod info;
+mod prune;
+mod refresh;
+mod remove;
+mod rename;
+mod report;
 mod scrape;
+mod state;
+mod stats;
 mod tag;
+mod template;
 mod types;
 mod util;
+mod verify;
 
+use crate::add::InputFormat;
 use crate::command::Command::{self, *};
-use crate::deposit::DepositMode;
+use crate::deposit::{ConflictMode, DateSource, DepositMode};
+use crate::info::ListGrouping;
+use crate::report::{Outcome, Report};
+use crate::scrape::Secret;
+use crate::util::Encoding;
 use std::collections::BTreeMap;
 use std::io::BufRead;
 use std::path::PathBuf;
 use std::{env, fs};
 
+/// The default name of the library config folder, see `lib_dir_name`.
+const DEFAULT_LIB_DIR: &str = ".tapeworm";
+
+/// The name of the library config folder: `TAPEWORM_DIR` if set, `.tapeworm` otherwise.
+/// Can be overridden per invocation with `--lib-dir NAME`.
+pub fn lib_dir_name() -> String {
+    env::var("TAPEWORM_DIR")
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(String::from(DEFAULT_LIB_DIR))
+}
+
+/// Filenames (without extension) that carry no information about the song, see `tag::is_junk`.
+const DEFAULT_JUNK_FILENAME_PATTERNS: [&str; 4] = [
+    r"^videoplayback$",
+    r"^download( \(\d+\))?$",
+    r"^(audio|video|track|untitled|file)[ _-]?\d*$",
+    r"^\d{6,}$", // E.g. the IDs some sites use as filenames, but not years such as 1999
+];
+
+/// Options that may be set in the general config, as defaults for all libraries.
+const GENERAL_OPTIONS: [&str; 3] = ["organize", "input_file", "ytdlp_conf"];
+
 #[derive(Debug, Default)]
 pub struct Config {
     pub commands: Vec<Command>,
     pub lib_alias: Option<String>,
     pub lib_desc: Option<String>,
+    /// How many lines of the README to show as the description, when `lib_desc` is not set
+    pub description_lines: usize,
+    /// Lowercase terms of `.tapeworm/blocklist.txt`, which inputs and downloads may not contain
+    pub blocklist: Vec<String>,
     pub aliases: BTreeMap<String, PathBuf>,
+    /// How `list` groups the aliases
+    pub list_grouping: ListGrouping,
+    /// The command to run for an alias when no command is given, as in `alias=path;command`
+    pub alias_commands: BTreeMap<String, String>,
+    pub general_options: Vec<(String, String)>,
+    pub dry_run: bool,
+    pub backup: bool,
+    /// Where to write a JSON summary of the run, see `report`
+    pub report_path: Option<PathBuf>,
+    /// Beyond this many items, the auto options fall back to prompting, see `util::auto_for`
+    pub confirm_threshold: usize,
+    /// Apply the auto options regardless of `confirm_threshold`
+    pub force_auto: bool,
+    /// Whether prompts can be answered, i.e. stdin is a terminal
+    pub interactive: bool,
 
     // Paths
     pub general_conf: PathBuf,
+    pub lib_dir: String,
     pub lib_path: Option<PathBuf>,
     pub lib_conf_path: Option<PathBuf>,
     pub input_path: Option<PathBuf>,
     pub yt_dlp_conf_path: Option<PathBuf>,
+    pub state_path: Option<PathBuf>,
 
     // Add options
     pub terms: Option<Vec<String>>, // QUERY | URL...
+    /// Files to read more terms from, one per line
+    pub term_files: Vec<String>,
+    pub scroll_limit: usize,
+    pub scroll_page_downs: usize,
+    pub spotify_selector: String,
+    /// Spotify Web API credentials from lib.conf, see `scrape::spotify_credentials`
+    pub spotify_client_id: Option<String>,
+    pub spotify_client_secret: Option<Secret>,
+    pub soundcloud_selector: String,
+    pub youtube_selector: String,
+    pub scrape_warn_threshold: usize,
+    pub search_results: usize,
+    pub auto_add: bool,
+    /// Ask before adding queries that are similar to a file in the library
+    pub check_duplicates: bool,
+    /// Add inputs that are in the input file already
+    pub allow_duplicates: bool,
+    /// Continue an unfinished scrape from its checkpoint
+    pub resume_scrape: bool,
+
+    // Remove options
+    /// Clear the input file, instead of removing the `terms`
+    pub remove_all: bool,
+
+    // Export options
+    /// The M3U playlist to write
+    pub playlist_path: Option<PathBuf>,
+
+    // History options
+    /// The number of most recent entries to show
+    pub history_limit: usize,
+
+    // Verify options
+    /// The tags each audio file should have, see `verify::TAG_NAMES`
+    pub required_tags: Vec<String>,
+    /// Verify the library instead of the input directories
+    pub verify_library: bool,
+
+    // Prune-inputs options
+    pub remove_satisfied: bool,
+
+    // Template-test options
+    pub template: Option<String>,
 
     // Download options
     pub clear_input: bool,
+    pub keep_going: bool,
+    /// How often to retry a failed download, see `download::download_with_retries`
+    pub retries: usize,
+    pub retry_delay_ms: u64,
+    /// The yt-dlp download archive, passed as `--download-archive` to yt-dlp
+    pub download_archive: Option<PathBuf>,
+    pub input_url: Option<String>,
+    /// The format of the input file (or URL) for `download`, or of the files given to `add`
+    pub input_format: InputFormat,
+    /// The encoding of the input file, and of the files given to `add --input-format`
+    pub encoding: Encoding,
     pub auto_download: bool,
     pub verbose: bool,
 
     // Tag options
     pub override_artist: bool,
+    pub uploader_as_artist: bool,
     pub title_template: String,
+    pub feat_separator: String,
+    pub feat_last_separator: String,
     pub filename_template: String,
-    pub input_dir: Option<PathBuf>,
+    pub input_dirs: Vec<PathBuf>,
+    pub min_duration: Option<f64>,
+    pub max_duration: Option<f64>,
     pub auto_tag: bool,
+    pub extract_label: bool,
+    /// Split "Work: Movement" titles into the grouping and title
+    pub extract_work: bool,
+    pub extended_separators: bool,
+    pub keep_track_in_title: bool,
+    pub version_keywords: Vec<String>,
+    pub junk_filename_patterns: Vec<regex::Regex>,
+    pub infer_album_artist: bool,
+    pub json: bool,
+    pub file: Option<PathBuf>,
+    pub set_tags: Vec<(String, Option<String>)>,
+    pub recursive: bool,
+    pub from_path: bool,
+    pub resume: bool,
+    pub report_skips: bool,
+    pub max_files: Option<usize>,
+    /// Only rename the files per `filename_template`, based on their current tags
+    pub rename_only: bool,
+    pub help_command: Option<Command>,
+    pub preserve_mtime: bool,
+    pub embed_lyrics: bool,
+    pub album_from_folder: bool,
+    pub id3_version: Option<id3::Version>,
 
     // Deposit options
     pub organize: DepositMode,
+    pub by_quality: bool,
+    /// Recreate the subfolders of the input directories instead of organizing, see
+    /// `deposit::preserve_subdir`
+    pub preserve_subdir: bool,
+    /// How `DATE` formats the creation date into subfolders, see `deposit::parse_date_format`
+    pub date_format: String,
+    pub date_source: DateSource,
+    /// Copy files into the target directory instead of moving them
+    pub keep_originals: bool,
+    pub tag_first: bool,
     pub target_dir: Option<PathBuf>,
     pub auto_overwrite: bool,
+    pub on_conflict: Option<ConflictMode>,
+    /// Remember overwrite decisions in the state, for subsequent deposits
+    pub remember_overwrite: bool,
+    pub forget_overwrites: bool,
+    pub unknown_artist: Option<String>,
+    /// The maximum number of files per artist folder in A-Z mode, see `deposit::overflow_dir`
+    pub per_artist_limit: Option<usize>,
 }
 
 impl Config {
@@ -66,10 +228,15 @@ impl Config {
         }
 
         if let Ok(cmd) = Command::from(arg.as_ref().unwrap()) {
-            if cmd == List {
+            if cmd == List || cmd == CheckAll {
                 self.commands = vec![cmd];
                 self.parse_general_config()?;
-            } else if cmd != Help {
+            } else if cmd == TemplateTest {
+                self.commands = vec![cmd]; // Does not need a library
+            } else if cmd == Help {
+                // Invoked as `tapeworm help [COMMAND]`
+                self.help_command = args.next().map(|arg| Command::from(&arg)).transpose()?;
+            } else {
                 // Invoked as `tapeworm COMMAND [OPTIONS]`
                 self.commands = vec![cmd];
                 self.setup_library(None)?;
@@ -77,8 +244,14 @@ impl Config {
         } else {
             // Invoked as `tapeworm LIBRARY [COMMAND] [OPTIONS]`
             self.setup_library(Some(arg.unwrap()))?;
+            let alias_command = self
+                .lib_alias
+                .as_ref()
+                .and_then(|alias| self.alias_commands.get(alias));
             self.commands = if let Some(arg) = args.next() {
-                vec![Command::from(&arg).unwrap()]
+                vec![Command::from(&arg)?]
+            } else if let Some(command) = alias_command {
+                vec![Command::from(command)?] // The command bound to the alias
             } else {
                 vec![Show] // The default when only LIBRARY given
             };
@@ -89,8 +262,11 @@ impl Config {
 
     /// Parse extra options for commands that require them.
     fn parse_extra_options(&mut self, args: impl Iterator<Item = String>) -> types::UnitResult {
-        // Load library settings (overrides defaults)
+        // Load library settings (overrides global defaults, which override defaults)
         if self.commands[0].uses_lib_conf() {
+            for (key, value) in self.general_options.clone() {
+                self.parse_option(&key, &value)?;
+            }
             self.build_lib_conf_options()?;
         }
 
@@ -98,13 +274,128 @@ impl Config {
         if self.commands[0].uses_cli() {
             self.parse_cli_options(args)?;
         } else if self.commands[0] == Add {
+            let mut args = args.peekable();
+            let options = [
+                "-a",
+                "-n",
+                "-f",
+                "--from-file",
+                "--check-duplicates",
+                "--allow-duplicates",
+                "--resume-scrape",
+                "--input-format",
+                "--encoding",
+            ];
+            while let Some(arg) = args.next_if(|arg| options.contains(&arg.as_str())) {
+                match arg.as_str() {
+                    "-a" => self.auto_add = true,
+                    "-f" | "--from-file" => match args.next() {
+                        Some(file) => self.term_files.push(file),
+                        None => return Err("Terms file not specified. See 'help'".into()),
+                    },
+                    "--check-duplicates" => self.check_duplicates = true,
+                    "--allow-duplicates" => self.allow_duplicates = true,
+                    "--resume-scrape" => self.resume_scrape = true,
+                    "--input-format" => {
+                        self.input_format = InputFormat::from(&args.next().unwrap_or_default())?;
+                    }
+                    "--encoding" => {
+                        self.encoding = Encoding::from(&args.next().unwrap_or_default())?;
+                    }
+                    "-n" => {
+                        let n = args.next().unwrap_or_default();
+                        self.search_results = n.parse::<usize>().map_err(|_| {
+                            format!("Invalid number of search results: '{}'. See 'help'", n)
+                        })?;
+                    }
+                    _ => unreachable!(),
+                }
+            }
             let terms = args.collect::<Vec<String>>();
-            if terms.is_empty() {
+            if terms.is_empty() && self.term_files.is_empty() {
                 return Err("Provide search term(s) and/or URL(s). See 'help'".into());
             }
             self.terms = Some(terms);
+        } else if self.commands[0] == List {
+            let mut args = args;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--group-by" => {
+                        self.list_grouping = ListGrouping::from(&args.next().unwrap_or_default())?;
+                    }
+                    _ => return Err(format!("Unexpected argument: '{}'. See 'help'", arg).into()),
+                }
+            }
+        } else if self.commands[0] == Export {
+            let mut args = args;
+            while let Some(arg) = args.next() {
+                match (arg.as_str(), args.next()) {
+                    ("-o", Some(path)) => self.playlist_path = Some(env::current_dir()?.join(path)),
+                    ("-o", None) => return Err("Playlist file not specified. See 'help'".into()),
+                    _ => return Err(format!("Unexpected argument: '{}'. See 'help'", arg).into()),
+                }
+            }
+            if self.playlist_path.is_none() {
+                return Err("Playlist file not specified. See 'help'".into());
+            }
+        } else if self.commands[0] == History {
+            let mut args = args;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--limit" => {
+                        let n = args.next().unwrap_or_default();
+                        self.history_limit = match n.parse::<usize>() {
+                            Ok(n) if n > 0 => n,
+                            _ => return Err(format!("Invalid limit: '{}'. See 'help'", n).into()),
+                        };
+                    }
+                    _ => return Err(format!("Unexpected argument: '{}'. See 'help'", arg).into()),
+                }
+            }
+        } else if self.commands[0] == Verify {
+            for arg in args {
+                match arg.as_str() {
+                    "--library" => self.verify_library = true,
+                    _ => return Err(format!("Unexpected argument: '{}'. See 'help'", arg).into()),
+                }
+            }
+        } else if self.commands[0] == Remove {
+            let mut terms = Vec::new();
+            for arg in args {
+                if arg == "-a" || arg == "--all" {
+                    self.remove_all = true;
+                } else {
+                    terms.push(arg);
+                }
+            }
+            if terms.is_empty() && !self.remove_all {
+                return Err("Provide search term(s) and/or URL(s), or --all. See 'help'".into());
+            }
+            self.terms = Some(terms);
+        } else if self.commands[0] == TemplateTest {
+            let mut args = args;
+            while let Some(arg) = args.next() {
+                if let Some(tag_name) = arg.strip_prefix("--") {
+                    let tag_value = args.next().filter(|v| !v.is_empty());
+                    self.set_tags.push((tag_name.to_uppercase(), tag_value));
+                } else if self.template.is_none() {
+                    self.template = Some(arg);
+                } else {
+                    return Err(format!("Unexpected argument: '{}'. See 'help'", arg).into());
+                }
+            }
+            if self.template.is_none() {
+                return Err("Template not specified. See 'help'".into());
+            }
         } else if self.commands[0] == Alias {
-            let terms = args.collect::<Vec<String>>();
+            let mut terms = Vec::new();
+            for arg in args {
+                if arg == "--dry-run" {
+                    self.dry_run = true;
+                } else {
+                    terms.push(arg);
+                }
+            }
             if !terms.is_empty() {
                 self.terms = Some(terms);
             }
@@ -115,16 +406,25 @@ impl Config {
             // When lib.conf and CLI did not receive 'steps'
             return Err("Steps not specified. See 'help'".into());
         }
-        if self.commands.contains(&Tag) || self.commands.contains(&Deposit) {
+        if self.file.is_some() {
+            self.require_file()?;
+        } else if self.commands.contains(&Tag)
+            || self.commands.contains(&Deposit)
+            || (self.commands[0] == Verify && !self.verify_library)
+        {
             self.require_input_dir()?;
         }
-        if self.commands.contains(&Deposit) || self.commands.contains(&Clean) {
+        if [Deposit, Clean, Rename, PruneInputs, Export]
+            .iter()
+            .any(|c| self.commands.contains(c))
+            || (self.commands[0] == Add && self.check_duplicates)
+        {
             self.require_target_dir()?;
         }
         Ok(())
     }
 
-    /// Read in the configured aliases.
+    /// Read in the configured aliases, and the global defaults for options (see `GENERAL_OPTIONS`).
     fn parse_general_config(&mut self) -> types::UnitResult {
         if let Some(contents) = fs::read_to_string(&self.general_conf).ok() {
             for line in contents.lines().map(|l| l.trim()) {
@@ -133,9 +433,23 @@ impl Config {
                 }
 
                 if let Some((aka, path)) = line.split_once("=") {
+                    if GENERAL_OPTIONS.contains(&aka.to_lowercase().as_str()) {
+                        self.general_options
+                            .push((String::from(aka), String::from(path)));
+                        continue;
+                    }
+                    let (path, command) = match path.split_once(';') {
+                        Some((path, command)) => (path, Some(command.trim())),
+                        None => (path, None),
+                    };
+                    if let Some(command) = command {
+                        Command::from(command)?; // Fail early on an invalid command
+                        self.alias_commands
+                            .insert(String::from(aka), String::from(command));
+                    }
                     self.aliases.insert(String::from(aka), PathBuf::from(path));
                 } else {
-                    return Err(format!("Invalid alias: {}", line).into());
+                    return Err(TapewormError::InvalidConfigLine(String::from(line)));
                 }
             }
         }
@@ -164,21 +478,39 @@ impl Config {
             env::current_dir()? // Assume current directory to be a library
         };
 
-        let lib_conf_folder = lib_path.join(".tapeworm");
+        let lib_conf_folder = lib_path.join(&self.lib_dir);
         if fs::metadata(&lib_conf_folder).is_err() {
-            return Err(format!("Not a library folder: {}", lib_path.to_str().unwrap()).into());
+            let path = String::from(lib_path.to_str().unwrap());
+            return Err(TapewormError::NotALibrary(path));
         }
 
         self.lib_conf_path = Some(lib_conf_folder.join("lib.conf"));
         self.input_path = Some(lib_conf_folder.join("input.txt"));
         self.yt_dlp_conf_path = Some(lib_conf_folder.join("yt-dlp.conf"));
-        self.input_dir = Some(lib_conf_folder.join("tmp"));
+        self.state_path = Some(lib_conf_folder.join("state.json"));
+        self.blocklist = fs::read_to_string(lib_conf_folder.join("blocklist.txt"))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| line.trim().to_lowercase())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+        self.input_dirs = vec![lib_conf_folder.join("tmp")];
         self.target_dir = Some(lib_path.clone());
         self.lib_path = Some(lib_path);
 
         Ok(())
     }
 
+    /// Resolve the `name` of a file in the library config folder, overriding its default name.
+    /// An absolute path is used as is.
+    fn lib_conf_file(&self, name: &str) -> types::PathBufResult {
+        if name.is_empty() {
+            return Err("File name not specified".into());
+        }
+        let lib_conf_folder = self.lib_conf_path.as_ref().unwrap().parent().unwrap();
+        Ok(lib_conf_folder.join(name))
+    }
+
     /// Attempt to read in options from lib.conf if it exists.
     /// For any option that is not present in the file, the default will be kept.
     ///
@@ -186,46 +518,152 @@ impl Config {
     /// - If a line does not follow the `option=value` format
     /// - If an option is not recognized
     fn build_lib_conf_options(&mut self) -> types::UnitResult {
+        match self.parse_lib_conf().into_iter().next() {
+            Some((_, e)) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Read in options from lib.conf like `build_lib_conf_options`, but carry on past invalid
+    /// lines, so all of them can be reported at once.
+    ///
+    /// # Returns
+    /// Each invalid line, along with its error
+    fn parse_lib_conf(&mut self) -> Vec<(String, TapewormError)> {
         let contents = fs::read_to_string(&self.lib_conf_path.clone().unwrap());
         if contents.is_err() {
-            return Ok(()); // Leave defaults when file not present
+            return Vec::new(); // Leave defaults when file not present
         }
 
+        let mut errors = Vec::new();
         for line in contents.unwrap().lines().map(|l| l.trim()) {
             if line.is_empty() || line.starts_with("#") {
                 continue;
             }
 
-            let option = line.split_once("=");
-            if option.is_none() {
-                return Err(format!("Invalid config line: {}", line).into());
-            }
-
-            let (key, value) = option.unwrap();
-            match key.to_lowercase().as_str() {
-                // General
-                "description" => self.lib_desc = Some(String::from(value)),
-                "verbose" => self.verbose = value.parse::<bool>()?,
-                // Download
-                "clear_input" => self.clear_input = value.parse::<bool>()?,
-                "auto_download" => self.auto_download = value.parse::<bool>()?,
-                // Tag
-                "override_artist" => self.override_artist = value.parse::<bool>()?,
-                "filename_template" => self.filename_template = String::from(value),
-                "title_template" => self.title_template = String::from(value),
-                "auto_tag" => self.auto_tag = value.parse::<bool>()?,
-                // Tag, Deposit
-                "input_dir" => self.input_dir = Some(PathBuf::from(value)),
-                // Deposit
-                "target_dir" => self.target_dir = Some(PathBuf::from(value)),
-                "organize" => self.organize = DepositMode::from(value)?,
-                "auto_overwrite" => self.auto_overwrite = value.parse::<bool>()?,
-                // Process
-                "steps" => self.parse_steps(Some(String::from(value)))?,
-                _ => return Err(format!("Invalid config option: {}", key).into()),
+            let result = match line.split_once("=") {
+                Some((key, value)) => self.parse_option(key, value),
+                None => Err(TapewormError::InvalidConfigLine(String::from(line))),
+            };
+            if let Err(e) = result {
+                errors.push((String::from(line), e));
             }
         }
+        errors
+    }
+
+    /// Load the lib.conf of the library at `alias`, without running any command.
+    ///
+    /// # Returns
+    /// Each invalid line, along with its error. When the library itself cannot be set up, e.g. as
+    /// the alias does not point to a library, that error is returned without a line.
+    fn check_library(&self, alias: &str) -> Vec<(Option<String>, TapewormError)> {
+        let mut config = Config::default();
+        config.general_conf = self.general_conf.clone();
+        config.lib_dir = self.lib_dir.clone();
+        if let Err(e) = config.setup_library(Some(String::from(alias))) {
+            return vec![(None, e)];
+        }
+        config
+            .parse_lib_conf()
+            .into_iter()
+            .map(|(line, e)| (Some(line), e))
+            .collect()
+    }
 
+    /// Set a single lib.conf (or general config) option.
+    ///
+    /// # Errors
+    /// - If the option is not recognized
+    /// - If the value is invalid for the option
+    fn parse_option(&mut self, key: &str, value: &str) -> types::UnitResult {
+        match key.to_lowercase().as_str() {
+            // General
+            "description" => self.lib_desc = Some(String::from(value)),
+            "description_lines" => self.description_lines = value.parse::<usize>()?,
+            "verbose" => self.verbose = value.parse::<bool>()?,
+            "confirm_threshold" => self.confirm_threshold = value.parse::<usize>()?,
+            "input_file" => self.input_path = Some(self.lib_conf_file(value)?),
+            "encoding" => self.encoding = Encoding::from(value)?,
+            "ytdlp_conf" => self.yt_dlp_conf_path = Some(self.lib_conf_file(value)?),
+            // Add
+            "scroll_limit" => self.scroll_limit = value.parse::<usize>()?,
+            "scroll_page_downs" => self.scroll_page_downs = value.parse::<usize>()?,
+            "spotify_selector" => self.spotify_selector = String::from(value),
+            "spotify_client_id" => {
+                self.spotify_client_id = Some(String::from(value)).filter(|s| !s.is_empty())
+            }
+            "spotify_client_secret" => {
+                self.spotify_client_secret = Some(Secret::from(value)).filter(|s| !s.is_empty())
+            }
+            "soundcloud_selector" => self.soundcloud_selector = String::from(value),
+            "youtube_selector" => self.youtube_selector = String::from(value),
+            "scrape_warn_threshold" => self.scrape_warn_threshold = value.parse::<usize>()?,
+            "search_results" => self.search_results = value.parse::<usize>()?,
+            // Download
+            "clear_input" => self.clear_input = value.parse::<bool>()?,
+            "keep_going" => self.keep_going = value.parse::<bool>()?,
+            "retries" => self.retries = value.parse::<usize>()?,
+            "retry_delay_ms" => self.retry_delay_ms = value.parse::<u64>()?,
+            "download_archive" => {
+                let name = if value.is_empty() {
+                    "archive.txt"
+                } else {
+                    value
+                };
+                self.download_archive = Some(self.lib_conf_file(name)?);
+            }
+            "auto_download" => self.auto_download = value.parse::<bool>()?,
+            // Tag
+            "override_artist" => self.override_artist = value.parse::<bool>()?,
+            "embed_lyrics" => self.embed_lyrics = value.parse::<bool>()?,
+            "preserve_mtime" => self.preserve_mtime = value.parse::<bool>()?,
+            "uploader_as_artist" => self.uploader_as_artist = value.parse::<bool>()?,
+            "filename_template" => self.filename_template = String::from(value),
+            "title_template" => self.title_template = String::from(value),
+            "feat_separator" => self.feat_separator = String::from(unquote(value)),
+            "feat_last_separator" => self.feat_last_separator = String::from(unquote(value)),
+            "auto_tag" => self.auto_tag = value.parse::<bool>()?,
+            "extract_label" => self.extract_label = value.parse::<bool>()?,
+            "extract_work" => self.extract_work = value.parse::<bool>()?,
+            "extended_separators" => self.extended_separators = value.parse::<bool>()?,
+            "keep_track_in_title" => self.keep_track_in_title = value.parse::<bool>()?,
+            "version_keywords" => {
+                self.version_keywords = value
+                    .split(',')
+                    .map(|k| String::from(k.trim()))
+                    .filter(|k| !k.is_empty())
+                    .collect()
+            }
+            "junk_filename_patterns" => {
+                self.junk_filename_patterns = Config::parse_patterns(value)?
+            }
+            "infer_album_artist" => self.infer_album_artist = value.parse::<bool>()?,
+            "id3_version" => self.id3_version = Config::parse_id3_version(value)?,
+            // Verify
+            "required_tags" => self.required_tags = verify::parse_required_tags(value)?,
+            // Tag, Deposit
+            "input_dir" => self.input_dirs = Config::parse_dirs(value),
+            // Deposit
+            "target_dir" => self.target_dir = Some(PathBuf::from(value)),
+            "organize" => self.organize = DepositMode::from(value)?,
+            "date_format" => self.date_format = deposit::parse_date_format(value)?,
+            "date_source" => self.date_source = DateSource::from(value)?,
+            "path_template" => self.organize = DepositMode::Template(String::from(value)),
+            "auto_overwrite" => self.auto_overwrite = value.parse::<bool>()?,
+            "on_conflict" => self.on_conflict = Some(ConflictMode::from(value)?),
+            "unknown_artist" => {
+                self.unknown_artist = Some(String::from(value)).filter(|s| !s.is_empty())
+            }
+            "per_artist_limit" => self.per_artist_limit = Config::parse_limit(value)?,
+            "by_quality" => self.by_quality = value.parse::<bool>()?,
+            // Process
+            "steps" => self.parse_steps(Some(String::from(value)))?,
+            _ => {
+                let line = format!("{}={}", key, value);
+                return Err(TapewormError::InvalidConfigLine(line));
+            }
+        }
         Ok(())
     }
 
@@ -238,10 +676,15 @@ impl Config {
             if !arg.starts_with('-') {
                 break; // no (more) options
             }
+            if let Some(option) = arg.strip_prefix("--") {
+                self.parse_long_option(option, &mut args)?;
+                continue;
+            }
 
             for c in arg[1..].chars() {
                 match c {
                     'v' => self.verbose = true,
+                    'V' => self.verbose = false,
                     'c' if [Download, Process].contains(&self.commands[0]) => {
                         self.clear_input = true;
                     }
@@ -250,7 +693,7 @@ impl Config {
                     }
                     't' if [Tag, Process].contains(&self.commands[0]) => self.auto_tag = true,
                     'i' if [Tag, Deposit, Process].contains(&self.commands[0]) => {
-                        self.input_dir = args.next().map(PathBuf::from);
+                        self.input_dirs = Config::parse_dirs(&args.next().unwrap_or_default());
                     }
                     'd' if [Deposit, Process].contains(&self.commands[0]) => {
                         if let Some(mode) = args.next() {
@@ -259,9 +702,15 @@ impl Config {
                             return Err("Organization mode not specified. See 'help'".into());
                         }
                     }
-                    'o' if [Deposit, Clean, Process].contains(&self.commands[0]) => {
+                    'o' if [Deposit, Clean, Process, Rename, PruneInputs]
+                        .contains(&self.commands[0]) =>
+                    {
                         self.target_dir = args.next().map(PathBuf::from);
                     }
+                    'k' if [Deposit, Process].contains(&self.commands[0]) => {
+                        self.keep_originals = true;
+                    }
+                    'n' if [Deposit, Process].contains(&self.commands[0]) => self.dry_run = true,
                     's' if self.commands[0] == Process => self.parse_steps(args.next())?,
                     _ => {
                         return Err(format!(
@@ -277,6 +726,196 @@ impl Config {
         Ok(())
     }
 
+    /// Attempts to override options with a CLI option of the form `--option`.
+    ///
+    /// # Errors
+    /// - If the option is not recognized for the Config's command
+    fn parse_long_option(
+        &mut self,
+        option: &str,
+        args: &mut impl Iterator<Item = String>,
+    ) -> types::UnitResult {
+        match option {
+            "no-verbose" => self.verbose = false,
+            "json" if [Tag, Process].contains(&self.commands[0]) => self.json = true,
+            "backup" if [Tag, Deposit, Clean, Process].contains(&self.commands[0]) => {
+                self.backup = true
+            }
+            "resume" if [Tag, Process].contains(&self.commands[0]) => self.resume = true,
+            // Steps from lib.conf have already replaced Process
+            "report"
+                if self.commands[0] == Process || self.commands[0].is_valid_processing_step() =>
+            {
+                if let Some(path) = args.next() {
+                    self.report_path = Some(PathBuf::from(path));
+                } else {
+                    return Err("Report file not specified. See 'help'".into());
+                }
+            }
+            "keep-going" if [Download, Process].contains(&self.commands[0]) => {
+                self.keep_going = true;
+            }
+            "max-files" if [Tag, Deposit, Process].contains(&self.commands[0]) => {
+                let max = args.next().unwrap_or_default();
+                self.max_files = match max.parse::<usize>() {
+                    Ok(max) if max > 0 => Some(max),
+                    _ => return Err(format!("Invalid max files: '{}'. See 'help'", max).into()),
+                };
+            }
+            "encoding" if [Download, PruneInputs, Process].contains(&self.commands[0]) => {
+                self.encoding = Encoding::from(&args.next().unwrap_or_default())?;
+            }
+            "input-url" if [Download, Process].contains(&self.commands[0]) => {
+                let url = args.next().unwrap_or_default();
+                if url::Url::parse(&url).is_err() {
+                    return Err(format!("Invalid input URL: '{}'. See 'help'", url).into());
+                }
+                self.input_url = Some(url);
+            }
+            "input-format" if [Download, Process].contains(&self.commands[0]) => {
+                self.input_format = InputFormat::from(&args.next().unwrap_or_default())?;
+            }
+            "report-skips" if [Tag, Process].contains(&self.commands[0]) => {
+                self.report_skips = true;
+            }
+            "album-from-folder" if [Tag, Process].contains(&self.commands[0]) => {
+                self.album_from_folder = true;
+            }
+            "dry-run" if [Clean, Tag, Deposit, Process].contains(&self.commands[0]) => {
+                self.dry_run = true;
+            }
+            "lyrics" if [Tag, Process].contains(&self.commands[0]) => self.embed_lyrics = true,
+            "recursive" if [Tag, Process].contains(&self.commands[0]) => self.recursive = true,
+            "from-path" if [Tag, Process].contains(&self.commands[0]) => self.from_path = true,
+            "force-auto" if [Download, Tag, Deposit, Process].contains(&self.commands[0]) => {
+                self.force_auto = true;
+            }
+            "yes" if [Download, Tag, Deposit, Process].contains(&self.commands[0]) => {
+                self.auto_download = true;
+                self.auto_tag = true;
+                self.auto_overwrite = true;
+            }
+            "target-per-artist-limit" if [Deposit, Process].contains(&self.commands[0]) => {
+                self.per_artist_limit = Config::parse_limit(&args.next().unwrap_or_default())?;
+            }
+            "rename-only" if self.commands[0] == Tag => self.rename_only = true,
+            "file" if self.commands[0] == Tag => {
+                if let Some(file) = args.next() {
+                    self.file = Some(PathBuf::from(file));
+                } else {
+                    return Err("File not specified. See 'help'".into());
+                }
+            }
+            "list" if self.commands[0] == Rename => self.dry_run = true,
+            "remove" if self.commands[0] == PruneInputs => self.remove_satisfied = true,
+            "keep" if [Deposit, Process].contains(&self.commands[0]) => self.keep_originals = true,
+            "by-quality" if [Deposit, Process].contains(&self.commands[0]) => {
+                self.by_quality = true;
+            }
+            "preserve-subdir" if [Deposit, Process].contains(&self.commands[0]) => {
+                self.preserve_subdir = true;
+            }
+            "tag-first" if self.commands[0] == Deposit => self.tag_first = true,
+            "remember-overwrite" if [Deposit, Process].contains(&self.commands[0]) => {
+                self.remember_overwrite = true;
+            }
+            "forget-overwrites" if [Deposit, Process].contains(&self.commands[0]) => {
+                self.forget_overwrites = true;
+            }
+            "date-format" if [Deposit, Process].contains(&self.commands[0]) => {
+                if let Some(format) = args.next() {
+                    self.date_format = deposit::parse_date_format(&format)?;
+                } else {
+                    return Err("Date format not specified. See 'help'".into());
+                }
+            }
+            "path-template" if [Deposit, Process].contains(&self.commands[0]) => {
+                if let Some(template) = args.next() {
+                    self.organize = DepositMode::Template(template);
+                } else {
+                    return Err("Path template not specified. See 'help'".into());
+                }
+            }
+            "set" if [Tag, Process].contains(&self.commands[0]) => {
+                let assignment = args.next().unwrap_or_default();
+                if let Some(tag) = editor::parse_assignment(&assignment) {
+                    self.set_tags.push(tag);
+                } else {
+                    return Err(
+                        format!("Invalid tag assignment: '{}'. See 'help'", assignment).into(),
+                    );
+                }
+            }
+            "min-duration" if [Tag, Deposit, Process].contains(&self.commands[0]) => {
+                self.min_duration = Some(Config::parse_duration(args.next())?);
+            }
+            "max-duration" if [Tag, Deposit, Process].contains(&self.commands[0]) => {
+                self.max_duration = Some(Config::parse_duration(args.next())?);
+            }
+            _ => {
+                return Err(format!(
+                    "Unrecognized option '--{}' for command '{:?}'. See 'help'",
+                    option, self.commands[0]
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse a list of directories, separated as in the PATH environment variable (':' on Unix,
+    /// ';' on Windows), so that directory names may contain commas.
+    fn parse_dirs(dirs: &str) -> Vec<PathBuf> {
+        env::split_paths(dirs)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect()
+    }
+
+    /// Parse a duration in seconds.
+    fn parse_duration(duration: Option<String>) -> Result<f64, TapewormError> {
+        if let Ok(Some(duration)) = util::parse::<f64>(duration) {
+            if duration >= 0.0 {
+                return Ok(duration);
+            }
+        }
+        Err("Duration not specified or invalid. See 'help'".into())
+    }
+
+    /// Parse comma-separated regexes, which match case-insensitively. A comma within a regex (e.g.
+    /// of a `{m,n}` repetition) is escaped as `\,`.
+    fn parse_patterns(patterns: &str) -> Result<Vec<regex::Regex>, TapewormError> {
+        let mut split = vec![String::new()];
+        let mut chars = patterns.chars().peekable();
+        while let Some(c) = chars.next() {
+            let pattern = split.last_mut().unwrap();
+            match (c, chars.peek()) {
+                ('\\', Some(',')) => pattern.push(chars.next().unwrap()),
+                ('\\', Some(_)) => pattern.extend([c, chars.next().unwrap()]),
+                (',', _) => split.push(String::new()),
+                _ => pattern.push(c),
+            }
+        }
+
+        let mut regexes = Vec::new();
+        for pattern in split.iter().map(|p| p.trim()).filter(|p| !p.is_empty()) {
+            match regex::Regex::new(&format!("(?i){}", pattern)) {
+                Ok(regex) => regexes.push(regex),
+                Err(e) => return Err(format!("Invalid pattern: {}\n{}", pattern, e).into()),
+            }
+        }
+        Ok(regexes)
+    }
+
+    /// Parse an ID3v2 version to write mp3 tags as. An empty value keeps the default behavior.
+    fn parse_id3_version(version: &str) -> Result<Option<id3::Version>, TapewormError> {
+        match version {
+            "" => Ok(None),
+            "2.3" => Ok(Some(id3::Version::Id3v23)),
+            "2.4" => Ok(Some(id3::Version::Id3v24)),
+            _ => Err(format!("Invalid ID3 version: {}. Use 2.3 or 2.4", version).into()),
+        }
+    }
+
     fn parse_steps(&mut self, steps: Option<String>) -> types::UnitResult {
         if self.commands[0] != Process {
             return Ok(());
@@ -302,16 +941,39 @@ impl Config {
         }
     }
 
+    /// # Returns
+    /// The `per_artist_limit`, where 0 means unlimited
+    fn parse_limit(value: &str) -> Result<Option<usize>, TapewormError> {
+        match value.parse::<usize>() {
+            Ok(0) => Ok(None),
+            Ok(limit) => Ok(Some(limit)),
+            Err(_) => Err(format!("Invalid per artist limit: '{}'. See 'help'", value).into()),
+        }
+    }
+
     fn require_input_dir(&mut self) -> types::UnitResult {
-        if self.input_dir.is_none() {
-            return Err("Input directory not specified. See 'help'".into());
+        if self.input_dirs.is_empty() {
+            return Err(TapewormError::MissingInputDir(None));
         }
 
-        let lib_path = self.lib_path.as_ref();
-        self.input_dir = Some(lib_path.unwrap().join(self.input_dir.as_ref().unwrap()));
-        let input_dir = self.input_dir.as_ref().unwrap();
-        if fs::metadata(input_dir).is_err() {
-            return Err(format!("Input directory not found: {}", input_dir.display()).into());
+        let lib_path = self.lib_path.as_ref().unwrap();
+        for input_dir in self.input_dirs.iter_mut() {
+            *input_dir = lib_path.join(&input_dir);
+            if fs::metadata(&input_dir).is_err() {
+                return Err(TapewormError::MissingInputDir(Some(input_dir.clone())));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn require_file(&self) -> types::UnitResult {
+        let file = self.file.as_ref().unwrap();
+        if !fs::metadata(file).is_ok_and(|m| m.is_file()) {
+            return Err(format!("File not found: {}", file.display()).into());
+        }
+        if let Err(e) = fs::File::open(file) {
+            return Err(format!("File not readable: {}: {}", file.display(), e).into());
         }
 
         Ok(())
@@ -319,14 +981,14 @@ impl Config {
 
     fn require_target_dir(&mut self) -> types::UnitResult {
         if self.target_dir.is_none() {
-            return Err("Target directory not specified. See 'help'".into());
+            return Err(TapewormError::MissingTargetDir(None));
         }
 
         let lib_path = self.lib_path.as_ref();
         self.target_dir = Some(lib_path.unwrap().join(self.target_dir.as_ref().unwrap()));
         let target_dir = self.target_dir.as_ref().unwrap();
         if fs::metadata(target_dir).is_err() {
-            return Err(format!("Target directory not found: {}", target_dir.display()).into());
+            return Err(TapewormError::MissingTargetDir(Some(target_dir.clone())));
         }
 
         Ok(())
@@ -338,20 +1000,203 @@ impl Config {
             general_conf: PathBuf::from(dirs::config_dir().unwrap())
                 .join("tapeworm")
                 .join("tapeworm.conf"),
-            title_template: String::from("{title} ({feat}) [{remix}]"),
+            lib_dir: lib_dir_name(),
+            scroll_limit: 50,
+            scroll_page_downs: 2,
+            scrape_warn_threshold: 50,
+            search_results: 1,
+            soundcloud_selector: String::from("li.trackList__item"),
+            youtube_selector: String::from("ytd-playlist-video-renderer a#video-title"),
+            spotify_selector: String::from(
+                "div[data-testid='playlist-tracklist'] div[aria-colindex='2']",
+            ),
+            title_template: String::from("{title} ({feat}) [{remix}] ({version})"),
+            version_keywords: ["live", "acoustic", "demo", "session"]
+                .map(String::from)
+                .to_vec(),
+            junk_filename_patterns: Config::parse_patterns(
+                &DEFAULT_JUNK_FILENAME_PATTERNS
+                    .map(|p| p.replace(',', "\\,"))
+                    .join(","),
+            )
+            .unwrap(),
+            feat_separator: String::from(", "),
+            feat_last_separator: String::from(" & "),
             filename_template: String::from("{artist} - {title}"),
+            date_format: String::from("%Y/%m"),
+            retry_delay_ms: 1000,
+            description_lines: 1,
+            history_limit: 20,
+            required_tags: ["artist", "title"].map(String::from).to_vec(),
+            interactive: true,
             ..Default::default()
         }
     }
 
-    pub fn build(mut args: impl Iterator<Item = String>) -> types::ConfigResult {
-        args.next(); // Consume program name
+    /// Build the Config from the program arguments.
+    ///
+    /// # Errors
+    /// - `TapewormError::NotALibrary`: if the library has no library config folder
+    /// - `TapewormError::InvalidConfigLine`: if lib.conf or the general config has an invalid line
+    /// - `TapewormError::MissingInputDir`, `TapewormError::MissingTargetDir`: if a required
+    ///   directory is not specified or does not exist
+    /// - `TapewormError::UnknownCommand`: if the command does not exist
+    /// - `TapewormError::InvalidConfig`: for any other invalid argument or configuration
+    pub fn build(args: impl Iterator<Item = String>) -> types::ConfigResult {
+        Config::build_from(args).map_err(|e| match e {
+            TapewormError::Io(_) | TapewormError::Other(_) => {
+                TapewormError::InvalidConfig(e.to_string())
+            }
+            e => e,
+        })
+    }
+
+    fn build_from(args: impl Iterator<Item = String>) -> types::ConfigResult {
+        let mut args = args.skip(1).collect::<Vec<String>>(); // Skip program name
 
         let mut config = Config::default();
+        if let Some(i) = args.iter().position(|arg| arg == "--lib-dir") {
+            // Must be known before setting up the library, so it may appear anywhere
+            args.remove(i);
+            if i >= args.len() {
+                return Err("Library config folder name not specified. See 'help'".into());
+            }
+            config.lib_dir = args.remove(i);
+        }
+        if let Some(i) = args.iter().position(|arg| arg == "--help") {
+            // Invoked as `tapeworm [LIBRARY] COMMAND --help`, which needs no (valid) library
+            config.help_command = args[..i]
+                .iter()
+                .filter_map(|arg| Command::from(arg).ok())
+                .find(|cmd| *cmd != Help);
+            return Ok(config);
+        }
+
+        let mut args = args.into_iter();
         config.parse_library_and_command(&mut args)?;
         config.parse_extra_options(args)?;
         Ok(config)
     }
+
+    /// Build a Config per library, when the LIBRARY argument is a glob pattern (containing `*` or
+    /// `?`) matching one or more aliases, e.g. `music-*`. Otherwise, this is the same as `build`.
+    ///
+    /// # Errors
+    /// - `TapewormError::InvalidConfig`: if the pattern matches no alias
+    /// - Any error of `build`, for any of the matching libraries
+    pub fn build_all(args: impl Iterator<Item = String>) -> types::VecConfigResult {
+        let args = args.collect::<Vec<String>>();
+        let pattern = match args.get(1) {
+            Some(arg) if arg.contains(['*', '?']) && Command::from(arg).is_err() => arg.clone(),
+            _ => return Ok(vec![Config::build(args.into_iter())?]),
+        };
+
+        let mut config = Config::default();
+        config
+            .parse_general_config()
+            .map_err(|e| TapewormError::InvalidConfig(e.to_string()))?;
+        let aliases = config
+            .aliases
+            .keys()
+            .filter(|alias| util::glob_matches(&pattern, alias))
+            .collect::<Vec<&String>>();
+        if aliases.is_empty() {
+            let msg = format!("No aliases match: {}. See 'list'", pattern);
+            return Err(TapewormError::InvalidConfig(msg));
+        }
+
+        aliases
+            .into_iter()
+            .map(|alias| {
+                let mut args = args.clone();
+                args[1] = alias.clone();
+                Config::build(args.into_iter())
+            })
+            .collect()
+    }
+
+    /// Whether running the commands may prompt the user, i.e. an auto option is not set. Auto
+    /// options only fall back to prompting beyond CONFIRM_THRESHOLD when stdin is a terminal, see
+    /// `util::auto_for`, so they need no terminal either.
+    fn prompts(&self) -> bool {
+        self.commands.iter().any(|cmd| match cmd {
+            Download => !self.auto_download,
+            Tag => !self.auto_tag && !self.json && !self.rename_only && !self.dry_run,
+            Deposit => !self.auto_overwrite && self.on_conflict.is_none() && !self.dry_run,
+            _ => false,
+        })
+    }
+
+    /// Ensure that prompts can be answered, as reading from a non-interactive stdin would either
+    /// block forever or silently take the default answer.
+    ///
+    /// # Errors
+    /// - `TapewormError::InvalidConfig`: if the commands prompt, but stdin is not `interactive`
+    pub fn require_interactive(&self, interactive: bool) -> types::UnitResult {
+        if self.prompts() && !interactive {
+            return Err(TapewormError::InvalidConfig(String::from(
+                "interactive prompt required but stdin is not a terminal; use -a/-t/--yes",
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Strip surrounding double quotes from a lib.conf value, which allows for leading and trailing
+/// whitespace in the value.
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Run each of the `configs`, as built by `Config::build_all`. With multiple libraries, a failing
+/// library does not stop the others, and the result of each library is reported at the end.
+///
+/// # Errors
+/// - The error of the library, when there is only one
+/// - `TapewormError::Partial`: if any of multiple libraries failed
+pub fn run_all<R, D>(configs: Vec<Config>, mut reader: R, downloader: D) -> types::UnitResult
+where
+    R: BufRead,
+    D: download::Downloader + Clone,
+{
+    if configs.len() == 1 {
+        return run(configs.into_iter().next().unwrap(), reader, downloader);
+    }
+
+    let total = configs.len();
+    let mut results = Vec::with_capacity(total);
+    for config in configs {
+        let alias = config.lib_alias.clone().unwrap_or_default();
+        println!("\n===== {} =====", alias);
+        let result = run(config, &mut reader, downloader.clone());
+        results.push((alias, result));
+    }
+
+    println!("\nResults:");
+    let mut failed = Vec::new();
+    for (alias, result) in results {
+        match result {
+            Ok(_) => println!("  {}: OK", alias),
+            Err(e) => {
+                println!("  {}: {}", alias, e);
+                failed.push(alias);
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(TapewormError::Partial(format!(
+            "{} of {} libraries failed: {}",
+            failed.len(),
+            total,
+            failed.join(", ")
+        )))
+    }
 }
 
 pub fn run<R, D>(config: Config, mut reader: R, downloader: D) -> types::UnitResult
@@ -359,19 +1204,220 @@ where
     R: BufRead,
     D: download::Downloader,
 {
+    let mut report = Report::default();
     for cmd in &config.commands {
-        match cmd {
-            Help => info::help(),
-            List => info::list(&config),
-            Alias => alias::run(&config)?,
-            Show => info::show(&config)?,
-            Clean => clean::run(&config)?,
-            Add => add::run(&config)?,
-            Download => download::run(&config, &mut reader, &downloader)?,
-            Tag => tag::run(&config, &mut reader)?,
-            Deposit => deposit::run(&config, &mut reader)?,
-            _ => return Err(format!("Cannot run this command: {:?}. See 'help'", cmd).into()),
+        let result = run_command(cmd, &config, &mut reader, &downloader);
+        if let Some(report_path) = &config.report_path {
+            report.add(cmd, &result);
+            report.write(report_path)?; // Also on failure, so the report covers what was done
         }
+        result?;
     }
     Ok(())
 }
+
+/// Run a single command, returning what it did for the `--report`.
+fn run_command<R, D>(
+    cmd: &Command,
+    config: &Config,
+    reader: R,
+    downloader: &D,
+) -> types::OutcomeResult
+where
+    R: BufRead,
+    D: download::Downloader,
+{
+    match cmd {
+        Help => info::help(config.help_command.as_ref()),
+        List => info::list(config),
+        CheckAll => info::check_all(config)?,
+        Alias => alias::run(config)?,
+        Show => info::show(config)?,
+        Clean => clean::run(config)?,
+        Add => add::run(config, reader)?,
+        Refresh => refresh::run(config)?,
+        Remove => remove::run(config)?,
+        DedupeInputs => dedupe::run(config)?,
+        PruneInputs => prune::run(config)?,
+        Download => download::run(config, reader, downloader)?,
+        Tag => return tag::run(config, reader),
+        Deposit => return deposit::run(config, reader),
+        Rename => rename::run(config)?,
+        Export => export::run(config)?,
+        Stats => stats::run(config)?,
+        History => history::run(config)?,
+        Undo => deposit::undo(config)?,
+        Verify => verify::run(config)?,
+        TemplateTest => template::run(config)?,
+        _ => return Err(format!("Cannot run this command: {:?}. See 'help'", cmd).into()),
+    }
+    Ok(Outcome::Done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a `deposit` Config for the library at `lib_path`, using the general config at
+    /// `general_conf`.
+    fn build_deposit(general_conf: &PathBuf, lib_path: &PathBuf, args: Vec<&str>) -> Config {
+        let mut config = Config::default();
+        config.general_conf = general_conf.clone();
+        config.commands = vec![Deposit];
+        config
+            .setup_library(Some(String::from(lib_path.to_str().unwrap())))
+            .unwrap();
+        config
+            .parse_extra_options(args.into_iter().map(String::from))
+            .unwrap();
+        config
+    }
+
+    #[test]
+    fn applies_global_organize_default() {
+        let dir = env::temp_dir().join(format!("tapeworm-general-{}", std::process::id()));
+        let lib_path = dir.join("lib");
+        fs::create_dir_all(lib_path.join(DEFAULT_LIB_DIR).join("tmp")).unwrap();
+        let general_conf = dir.join("tapeworm.conf");
+        let contents = format!("organize=A-Z\nlib={}\n", lib_path.display());
+        fs::write(&general_conf, contents).unwrap();
+
+        // The library relies on the global default
+        let config = build_deposit(&general_conf, &lib_path, vec![]);
+        assert_eq!(config.organize, DepositMode::AZ);
+        assert_eq!(config.aliases.get("lib"), Some(&lib_path));
+
+        // The CLI overrides the global default
+        let config = build_deposit(&general_conf, &lib_path, vec!["-d", "DROP"]);
+        assert_eq!(config.organize, DepositMode::Drop);
+
+        // The library's own setting overrides the global default
+        fs::write(
+            lib_path.join(DEFAULT_LIB_DIR).join("lib.conf"),
+            "organize=DATE\n",
+        )
+        .unwrap();
+        let config = build_deposit(&general_conf, &lib_path, vec![]);
+        assert_eq!(config.organize, DepositMode::Date);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn runs_alias_command() {
+        let dir = env::temp_dir().join(format!("tapeworm-alias-cmd-{}", std::process::id()));
+        let lib_path = dir.join("lib");
+        fs::create_dir_all(lib_path.join(DEFAULT_LIB_DIR)).unwrap();
+        let general_conf = dir.join("tapeworm.conf");
+        let contents = format!("a={0}\nb={0};clean\n", lib_path.display());
+        fs::write(&general_conf, contents).unwrap();
+
+        let commands_for = |args: &[&str]| {
+            let mut config = Config::default();
+            config.general_conf = general_conf.clone();
+            let mut args = args.iter().map(|s| String::from(*s));
+            config.parse_library_and_command(&mut args).unwrap();
+            config.commands
+        };
+        assert_eq!(commands_for(&["a"]), vec![Show]);
+        assert_eq!(commands_for(&["b"]), vec![Clean]);
+        assert_eq!(commands_for(&["b", "show"]), vec![Show]);
+
+        fs::write(&general_conf, format!("c={};nope\n", lib_path.display())).unwrap();
+        let mut config = Config::default();
+        config.general_conf = general_conf.clone();
+        assert!(config.parse_general_config().is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn checks_all_libraries() {
+        let dir = env::temp_dir().join(format!("tapeworm-check-all-{}", std::process::id()));
+        let lib_path = dir.join("lib");
+        fs::create_dir_all(lib_path.join(DEFAULT_LIB_DIR)).unwrap();
+        let lib_conf = "verbose=true\nverbose=maybe\nnope=1\nnot a line\norganize=A-Z\n";
+        fs::write(lib_path.join(DEFAULT_LIB_DIR).join("lib.conf"), lib_conf).unwrap();
+        let general_conf = dir.join("tapeworm.conf");
+        let contents = format!("lib={}\ngone={}\n", lib_path.display(), dir.display());
+        fs::write(&general_conf, contents).unwrap();
+
+        let mut config = Config::default();
+        config.general_conf = general_conf;
+        let lines = config
+            .check_library("lib")
+            .into_iter()
+            .map(|(line, _)| line.unwrap())
+            .collect::<Vec<String>>();
+        assert_eq!(lines, vec!["verbose=maybe", "nope=1", "not a line"]);
+
+        let errors = config.check_library("gone");
+        assert!(matches!(
+            errors[..],
+            [(None, TapewormError::NotALibrary(_))]
+        ));
+
+        // Loading a library still fails on the first invalid line
+        config.commands = vec![Show];
+        config.setup_library(Some(String::from("lib"))).unwrap();
+        assert!(config.build_lib_conf_options().is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn requires_interactive_prompts() {
+        let mut config = Config::default();
+        config.commands = vec![Download, Tag, Deposit];
+        assert!(config.require_interactive(true).is_ok());
+        assert!(config.require_interactive(false).is_err());
+
+        config.auto_download = true;
+        config.auto_tag = true;
+        assert!(config.require_interactive(false).is_err());
+
+        config.auto_overwrite = true;
+        assert!(config.require_interactive(false).is_ok());
+
+        config.commands = vec![Show];
+        config.auto_download = false;
+        assert!(config.require_interactive(false).is_ok());
+    }
+
+    #[test]
+    fn parses_escaped_patterns() {
+        let patterns = Config::parse_patterns(r"^a{2\,3}$, ^b\\,^c$").unwrap();
+        let patterns = patterns.iter().map(|p| p.as_str()).collect::<Vec<&str>>();
+        assert_eq!(patterns, vec![r"(?i)^a{2,3}$", r"(?i)^b\\", r"(?i)^c$"]);
+        assert!(Config::parse_patterns(r"^a{2,3}$").is_err());
+    }
+
+    #[test]
+    fn parses_id3_version() {
+        assert_eq!(Config::parse_id3_version("").unwrap(), None);
+        assert_eq!(
+            Config::parse_id3_version("2.3").unwrap(),
+            Some(id3::Version::Id3v23)
+        );
+        assert_eq!(
+            Config::parse_id3_version("2.4").unwrap(),
+            Some(id3::Version::Id3v24)
+        );
+        assert!(Config::parse_id3_version("2.2").is_err());
+    }
+
+    #[test]
+    fn parses_help_command() {
+        let build = |args: &[&str]| {
+            let args = ["tapeworm"].iter().chain(args).map(|s| String::from(*s));
+            Config::build(args).unwrap()
+        };
+
+        assert_eq!(build(&[]).help_command, None);
+        assert_eq!(build(&["help"]).help_command, None);
+        assert_eq!(build(&["help", "tag"]).help_command, Some(Tag));
+        assert_eq!(build(&["deposit", "--help"]).help_command, Some(Deposit));
+        assert_eq!(build(&["no-lib", "add", "--help"]).commands, vec![Help]);
+        assert!(Config::build(["tapeworm", "help", "x"].map(String::from).into_iter()).is_err());
+    }
+}
@@ -1,13 +1,35 @@
-use std::{env, io, process};
+use std::error::Error;
+use std::io::{self, IsTerminal};
+use std::{env, process};
 
 fn main() {
-    let config = tapeworm::Config::build(env::args()).unwrap_or_else(|e| {
-        eprintln!("Problem parsing arguments: {}", e);
-        process::exit(1);
+    let mut args = env::args().collect::<Vec<String>>();
+    // Must be known before parsing the arguments, as that may fail as well
+    let json_errors = args.iter().any(|arg| arg == "--json-errors");
+    args.retain(|arg| arg != "--json-errors");
+
+    let mut configs = tapeworm::Config::build_all(args.into_iter()).unwrap_or_else(|e| {
+        exit("Problem parsing arguments", &e, json_errors);
     });
 
-    if let Err(e) = tapeworm::run(config, io::stdin().lock(), tapeworm::YtDlp {}) {
-        eprintln!("Application error: {}", e);
-        process::exit(1);
+    for config in &mut configs {
+        config.interactive = io::stdin().is_terminal();
+        if let Err(e) = config.require_interactive(config.interactive) {
+            exit("Problem parsing arguments", &e, json_errors);
+        }
+    }
+
+    if let Err(e) = tapeworm::run_all(configs, io::stdin().lock(), tapeworm::YtDlp {}) {
+        exit("Application error", &e, json_errors);
+    }
+}
+
+/// Report the error on stderr, as JSON with `json_errors`, and exit with its exit code.
+fn exit(context: &str, e: &(dyn Error + 'static), json_errors: bool) -> ! {
+    if json_errors {
+        eprintln!("{}", tapeworm::error_json(e));
+    } else {
+        eprintln!("{}: {}", context, e);
     }
+    process::exit(tapeworm::exit_code(e));
 }
@@ -0,0 +1,131 @@
+//! Find (and remove) queries in the input file that appear to be satisfied by the library.
+
+use crate::{types, util, Config, TapewormError};
+use audiotags::Tag;
+use regex::Regex;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// Report the queries in the input file that match a file in the target directory (and its
+/// subfolders, except `.tapeworm`), based on the file's artist and title tags. URLs and comments
+/// are never matched. As matching is heuristic, the queries are only removed with
+/// `config.remove_satisfied` (`--remove`).
+pub fn run(config: &Config) -> types::UnitResult {
+    let input_path = config.input_path.as_ref().unwrap();
+    let contents = util::read_text_file(input_path, config.encoding).unwrap_or_default();
+
+    let songs = songs_in(config)?;
+    let mut matched = 0;
+    let mut pruned = String::new();
+
+    for line in contents.lines() {
+        let query = match query_from(line) {
+            Some(query) => query,
+            None => {
+                pruned.push_str(line);
+                pruned.push('\n');
+                continue;
+            }
+        };
+
+        if let Some((file, _)) = songs.iter().find(|(_, song)| matches(&query, song)) {
+            matched += 1;
+            println!("  {}\n= {}", line.trim(), file.display());
+        } else {
+            pruned.push_str(line);
+            pruned.push('\n');
+        }
+    }
+
+    if matched == 0 {
+        println!("No satisfied queries");
+        return Ok(());
+    }
+
+    if config.remove_satisfied {
+        util::write_text_file(input_path, pruned, config.encoding)?;
+        println!("Removed {} satisfied queries", matched);
+    } else {
+        println!(
+            "Found {} satisfied queries, use --remove to remove them",
+            matched
+        );
+    }
+    Ok(())
+}
+
+/// # Returns
+/// Each audio file in the target directory, along with the words of its artist and title tags.
+/// Files without a 'title' tag are skipped.
+fn songs_in(config: &Config) -> Result<Vec<(PathBuf, Song)>, TapewormError> {
+    let brackets = Regex::new(r"[(\[{][^)\]}]*[)\]}]").unwrap();
+    let mut songs = Vec::new();
+    for file in util::filepaths_in_recursive(config.target_dir.as_ref().unwrap(), &config.lib_dir)?
+    {
+        let ftag = match Tag::new().read_from_path(&file) {
+            Ok(ftag) => ftag,
+            Err(_) => continue, // Not a (supported) audio file
+        };
+        if let Some(title) = ftag.title() {
+            let song = Song {
+                artist: util::words(ftag.artist().unwrap_or_default()),
+                title: util::words(&brackets.replace_all(title, "")),
+            };
+            if !song.title.is_empty() {
+                songs.push((file, song));
+            }
+        }
+    }
+    Ok(songs)
+}
+
+struct Song {
+    artist: HashSet<String>,
+    title: HashSet<String>,
+}
+
+/// # Returns
+/// The words of the query on the input `line`, or `None` when the line is not a query.
+pub fn query_from(line: &str) -> Option<HashSet<String>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') || line.contains("://") {
+        return None;
+    }
+    // Strip the search prefix, e.g. `ytsearch:` or `ytsearch3:`
+    let query = match line.split_once(':') {
+        Some((prefix, query)) if prefix.starts_with("ytsearch") => query,
+        _ => line,
+    };
+    Some(util::words(query))
+}
+
+/// A query is satisfied by a song when it mentions all words of the song's artist and title,
+/// ignoring case and punctuation. Bracketed parts of the title, such as `(feat. X)`, are ignored.
+fn matches(query: &HashSet<String>, song: &Song) -> bool {
+    song.title.is_subset(query) && song.artist.is_subset(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_queries() {
+        let song = Song {
+            artist: util::words("The Band"),
+            title: util::words("Some Song"),
+        };
+        let query = |line: &str| query_from(line).unwrap();
+
+        assert!(matches(&query("ytsearch:the band - some song"), &song));
+        assert!(matches(
+            &query("ytsearch:Some Song, The Band (Official Video)"),
+            &song
+        ));
+        assert!(!matches(&query("ytsearch:the band - another song"), &song));
+        assert!(!matches(&query("ytsearch:some song"), &song));
+        assert!(matches(&query("ytsearch3:the band - some song"), &song));
+        assert!(query_from("https://youtu.be/abc").is_none());
+        assert!(query_from("# source: https://open.spotify.com/playlist/x").is_none());
+    }
+}
@@ -0,0 +1,56 @@
+//! Re-scrape the sources of previously scraped inputs, adding any new results.
+
+use crate::{add, types, util, Config};
+use std::collections::HashSet;
+use url::Url;
+
+/// Re-scrape each source recorded in the input file (see `add::SOURCE_PREFIX`). Results that are
+/// not yet present in the input file are appended under their source.
+pub fn run(config: &Config) -> types::UnitResult {
+    let input_path = config.input_path.as_ref().unwrap();
+    let contents = util::read_text_file(input_path, config.encoding).unwrap_or_default();
+    let existing: HashSet<&str> = contents.lines().map(|l| l.trim()).collect();
+
+    let mut sources = Vec::new();
+    for line in contents.lines() {
+        if let Some(source) = line.trim().strip_prefix(add::SOURCE_PREFIX) {
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+    }
+    if sources.is_empty() {
+        println!("Nothing to refresh. No scraped sources found.");
+        return Ok(());
+    }
+
+    let mut new_inputs = String::new();
+    for source in sources {
+        let url = Url::parse(source);
+        let scraper = url.as_ref().ok().and_then(add::scraper_for);
+        if scraper.is_none() {
+            println!("! Not a scrapeable source: {}, skipping", source);
+            continue;
+        }
+
+        let found = add::scrape(url.as_ref().unwrap(), scraper.unwrap(), config)
+            .into_iter()
+            .filter(|input| !existing.contains(input.as_str()))
+            .collect::<Vec<String>>();
+        println!("{} new inputs from {}", found.len(), source);
+        if !found.is_empty() {
+            new_inputs.push_str(&format!(
+                "{}{}\n{}\n",
+                add::SOURCE_PREFIX,
+                source,
+                found.join("\n")
+            ));
+        }
+    }
+
+    if new_inputs.is_empty() {
+        Ok(())
+    } else {
+        util::append_text_file(input_path, new_inputs, config.encoding)
+    }
+}
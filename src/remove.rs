@@ -0,0 +1,105 @@
+//! Remove inputs from the input file.
+
+use crate::{add, types, util, Config};
+use url::Url;
+
+/// Rewrite the input file without the lines holding any of the `config.terms`, or clear it
+/// completely with `config.remove_all`. URLs are normalized as `add` does, and a search term
+/// matches its query under any search prefix, so `remove TERM` undoes `add TERM` regardless of the
+/// `SEARCH_RESULTS` it was added with.
+pub fn run(config: &Config) -> types::UnitResult {
+    let input_path = config.input_path.as_ref().unwrap();
+    let contents = util::read_text_file(input_path, config.encoding).unwrap_or_default();
+
+    if config.remove_all {
+        let count = inputs_in(&contents);
+        util::write_text_file(input_path, String::new(), config.encoding)?;
+        println!("Removed {} inputs", count);
+        return Ok(());
+    }
+
+    let inputs = config
+        .terms
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|term| normalize(term.as_str()))
+        .collect::<Vec<String>>();
+    let (kept, removed) = remove(&contents, &inputs);
+    if removed == 0 {
+        println!("No matching inputs");
+        return Ok(());
+    }
+
+    util::write_text_file(input_path, kept, config.encoding)?;
+    println!("Removed {} inputs", removed);
+    Ok(())
+}
+
+/// # Returns
+/// The (bare video) URL that `add` would add for the `term`, or the search term itself.
+fn normalize(term: &str) -> String {
+    match Url::parse(term) {
+        Ok(url) => add::bare_video_url(&url).to_string(),
+        Err(_) => String::from(term),
+    }
+}
+
+/// # Returns
+/// The query of the `line` without its search prefix (`ytsearch:`, `ytsearch5:`, `ytsearchall:`),
+/// or `None` if the `line` is no search query.
+fn query_of(line: &str) -> Option<&str> {
+    let (count, query) = line.strip_prefix("ytsearch")?.split_once(':')?;
+    match count.is_empty() || count == "all" || count.chars().all(|c| c.is_ascii_digit()) {
+        true => Some(query),
+        false => None,
+    }
+}
+
+/// # Returns
+/// The `contents` without the lines holding any of the `inputs`, and the number of lines removed.
+fn remove(contents: &str, inputs: &[String]) -> (String, usize) {
+    let mut kept = String::new();
+    let mut removed = 0;
+    for line in contents.lines() {
+        let line_query = query_of(line.trim());
+        if inputs
+            .iter()
+            .any(|input| input == line.trim() || line_query == Some(input.as_str()))
+        {
+            removed += 1;
+        } else {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+    }
+    (kept, removed)
+}
+
+/// # Returns
+/// The number of inputs in the `contents`, i.e. lines that are neither blank nor comments.
+fn inputs_in(contents: &str) -> usize {
+    contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_matching_lines() {
+        let contents = "ytsearch:a\n# source: x\nytsearch5:b \nhttps://youtu.be/c\nytsearchall:a\n";
+        let inputs = [String::from("a"), String::from("b")];
+        let expected = "# source: x\nhttps://youtu.be/c\n";
+        assert_eq!(remove(contents, &inputs), (String::from(expected), 3));
+        assert_eq!(remove(expected, &inputs), (String::from(expected), 0));
+        assert_eq!(inputs_in(contents), 4);
+
+        let inputs = [String::from("https://youtu.be/c"), String::from("x")];
+        assert_eq!(remove("ytsearchx:x\nhttps://youtu.be/c\n", &inputs).1, 1);
+    }
+}
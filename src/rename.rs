@@ -0,0 +1,114 @@
+//! Rename files in the library according to the `filename_template`, based on their current tags.
+
+use crate::{tag, types, util, Config, TapewormError};
+use audiotags::Tag;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Rename each file in the target directory (and its subfolders, except `.tapeworm`) whose
+/// filename does not match the one rendered from its tags by `config.filename_template`. Files
+/// without a 'title' tag are left alone, as are files whose new name is already taken.
+///
+/// With `config.dry_run` (`--list`), only the old and new filenames are printed.
+pub fn run(config: &Config) -> types::UnitResult {
+    let mut changes = 0;
+
+    for file in util::filepaths_in_recursive(config.target_dir.as_ref().unwrap(), &config.lib_dir)?
+    {
+        let to = match target_for(config, &file) {
+            Some(to) => to,
+            None => continue,
+        };
+
+        changes += 1;
+        println!("  {}\n> {}", file.display(), to.display());
+        if !config.dry_run {
+            rename_file(&file, &to)?;
+        }
+    }
+
+    if config.dry_run {
+        println!("\n{} files would be renamed", changes);
+    } else {
+        println!("\n{} files renamed", changes);
+    }
+    Ok(())
+}
+
+/// The path that `file` should be renamed to, as rendered from its tags by
+/// `config.filename_template`.
+///
+/// # Returns
+/// `None` if `file` is not a (supported) audio file, has no 'title' tag, or is already named so
+pub fn target_for(config: &Config, file: &PathBuf) -> Option<PathBuf> {
+    let ftag = Tag::new().read_from_path(file).ok()?;
+    let filename = tag::filename_from_tags(config, &ftag)?;
+
+    let mut to = file.with_file_name(filename);
+    if let Some(ext) = file.extension() {
+        to.set_extension(ext);
+    }
+    Some(to).filter(|to| to != file)
+}
+
+/// Find a free path for `file` to be moved to at `to`: `to` itself if it is not taken (or holds
+/// the same contents as `file`), else the first of `STEM (2).EXT`, `STEM (3).EXT`, etc. that is.
+pub fn free_path(file: &Path, to: &Path) -> PathBuf {
+    let stem = to.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = to
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()));
+
+    let mut candidate = to.to_path_buf();
+    let mut n = 2;
+    while fs::metadata(&candidate).is_ok() && !same_contents(file, &candidate) {
+        candidate = to.with_file_name(format!("{} ({}){}", stem, n, ext.as_deref().unwrap_or("")));
+        n += 1;
+    }
+    candidate
+}
+
+fn same_contents(a: &Path, b: &Path) -> bool {
+    matches!((fs::read(a), fs::read(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Rename `file` to `to`, unless `to` is already taken.
+///
+/// # Returns
+/// Whether `file` was renamed
+pub fn rename_file(file: &PathBuf, to: &PathBuf) -> Result<bool, TapewormError> {
+    if fs::metadata(to).is_ok() {
+        println!("! File already exists, skipping");
+        return Ok(false);
+    }
+    fs::rename(file, to)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_taken_paths() {
+        let dir = std::env::temp_dir().join(format!("tapeworm-free-path-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("new.mp3");
+        fs::write(&file, "new").unwrap();
+
+        let to = dir.join("Song.mp3");
+        assert_eq!(free_path(&file, &to), to);
+
+        fs::write(&to, "old").unwrap();
+        assert_eq!(free_path(&file, &to), dir.join("Song (2).mp3"));
+
+        fs::write(dir.join("Song (2).mp3"), "older").unwrap();
+        assert_eq!(free_path(&file, &to), dir.join("Song (3).mp3"));
+
+        // The same song is not duplicated
+        fs::write(&to, "new").unwrap();
+        assert_eq!(free_path(&file, &to), to);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
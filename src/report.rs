@@ -0,0 +1,101 @@
+//! A JSON summary of a run, written with `--report FILE`.
+
+use crate::{types, Command};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+
+/// What a single command did, as far as it is worth reporting.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// Nothing (yet) worth reporting
+    Done,
+    /// The number of files considered, tagged (i.e. written) and skipped
+    Tagged {
+        total: usize,
+        tagged: usize,
+        skipped: usize,
+    },
+    /// The files moved, as `(from, to)`
+    Deposited(Vec<(PathBuf, PathBuf)>),
+}
+
+impl Outcome {
+    fn to_json(&self) -> Value {
+        match self {
+            Self::Done => json!({}),
+            Self::Tagged {
+                total,
+                tagged,
+                skipped,
+            } => json!({ "total": total, "tagged": tagged, "skipped": skipped }),
+            Self::Deposited(moves) => json!({
+                "moved": moves
+                    .iter()
+                    .map(|(from, to)| json!({ "from": from, "to": to }))
+                    .collect::<Vec<Value>>(),
+            }),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Report {
+    steps: Vec<Value>,
+}
+
+impl Report {
+    /// Record the `result` of running `command`. A failed step only records the error.
+    pub fn add(&mut self, command: &Command, result: &types::OutcomeResult) {
+        let mut step = match result {
+            Ok(outcome) => outcome.to_json(),
+            Err(e) => json!({ "error": e.to_string() }),
+        };
+        step["step"] = json!(format!("{:?}", command).to_lowercase());
+        self.steps.push(step);
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "steps": self.steps })
+    }
+
+    pub fn write(&self, path: &PathBuf) -> types::UnitResult {
+        fs::write(path, serde_json::to_string_pretty(&self.to_json())?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_steps() {
+        let mut report = Report::default();
+        report.add(
+            &Command::Tag,
+            &Ok(Outcome::Tagged {
+                total: 3,
+                tagged: 1,
+                skipped: 2,
+            }),
+        );
+        report.add(
+            &Command::Deposit,
+            &Ok(Outcome::Deposited(vec![(
+                PathBuf::from("in/a.mp3"),
+                PathBuf::from("out/A/a.mp3"),
+            )])),
+        );
+        report.add(&Command::Clean, &Err("Oops".into()));
+
+        assert_eq!(
+            report.to_json(),
+            json!({ "steps": [
+                { "step": "tag", "total": 3, "tagged": 1, "skipped": 2 },
+                { "step": "deposit", "moved": [{ "from": "in/a.mp3", "to": "out/A/a.mp3" }] },
+                { "step": "clean", "error": "Oops" },
+            ]})
+        );
+    }
+}
@@ -1,47 +1,363 @@
-use crate::types;
+use crate::{add, types, util, Config, TapewormError};
+use headless_chrome::Element;
 use std::collections::HashSet;
+use std::error::Error;
+use std::path::PathBuf;
+use std::{env, fmt, fs};
+use url::Url;
+
+const SPOTIFY_CLIENT_ID: &str = "SPOTIFY_CLIENT_ID";
+const SPOTIFY_CLIENT_SECRET: &str = "SPOTIFY_CLIENT_SECRET";
+
+/// Starts the line of the partial file holding the number of scrolls, see `read_partial`
+const SCROLLS_PREFIX: &str = "#scrolls ";
+
+/// A secret, such as an API client secret. It is never printed, as its `Debug` output is redacted.
+#[derive(Clone, PartialEq)]
+pub struct Secret(String);
+
+impl From<&str> for Secret {
+    fn from(secret: &str) -> Self {
+        Secret(String::from(secret))
+    }
+}
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+/// The credentials of a Spotify Web API client.
+#[derive(Debug, PartialEq)]
+pub struct SpotifyCredentials {
+    pub client_id: String,
+    pub client_secret: Secret,
+}
+
+/// Resolve the Spotify Web API credentials. The `SPOTIFY_CLIENT_ID` and `SPOTIFY_CLIENT_SECRET`
+/// environment variables take priority over `spotify_client_id` and `spotify_client_secret` in
+/// lib.conf, so that the secret can be kept out of (possibly shared) config files.
+///
+/// # Errors
+/// - If either credential is missing, naming the environment variables to set
+pub fn spotify_credentials(config: &Config) -> Result<SpotifyCredentials, TapewormError> {
+    resolve_credentials(config, |key| env::var(key).ok())
+}
+
+/// See `spotify_credentials`, reading the environment variables with `var`.
+fn resolve_credentials<V>(config: &Config, var: V) -> Result<SpotifyCredentials, TapewormError>
+where
+    V: Fn(&str) -> Option<String>,
+{
+    let var = |key| var(key).filter(|value: &String| !value.is_empty());
+    let client_id = var(SPOTIFY_CLIENT_ID).or_else(|| config.spotify_client_id.clone());
+    let client_secret = var(SPOTIFY_CLIENT_SECRET)
+        .map(Secret)
+        .or_else(|| config.spotify_client_secret.clone());
+    match (client_id, client_secret) {
+        (Some(client_id), Some(client_secret)) => Ok(SpotifyCredentials {
+            client_id,
+            client_secret,
+        }),
+        _ => Err(TapewormError::InvalidConfig(format!(
+            "Spotify API credentials are missing. Set the {} and {} environment variables",
+            SPOTIFY_CLIENT_ID, SPOTIFY_CLIENT_SECRET
+        ))),
+    }
+}
 
 /// Scrape a Spotify playlist for a list of songs.
 /// Returns the list of songs, where each song is formatted like "TITLE ARTIST"
-pub fn spotify_playlist(playlist_url: &str) -> types::HashSetResult {
-    let browser = headless_chrome::Browser::default()?;
-    let tab = browser.new_tab()?;
-    tab.navigate_to(playlist_url)?;
+pub fn spotify_playlist(playlist_url: &str, config: &Config) -> types::VecStringResult {
+    scrape_list(playlist_url, &config.spotify_selector, config, |html| {
+        let text = html.get_inner_text()?.replace("\n", " ");
+        Ok(Some(text).filter(|text| text != "Title"))
+    })
+}
 
-    println!("Scraping {}...", playlist_url);
+/// Scrape a SoundCloud set for a list of tracks.
+/// Returns the list of tracks, where each track is formatted like "TITLE ARTIST"
+pub fn soundcloud_set(set_url: &str, config: &Config) -> types::VecStringResult {
+    scrape_list(set_url, &config.soundcloud_selector, config, |html| {
+        let title = html
+            .find_element(".trackItem__trackTitle")?
+            .get_inner_text()?;
+        let artist = html
+            .find_element(".trackItem__username")?
+            .get_inner_text()?;
+        Ok(Some(format!("{} {}", title.trim(), artist.trim())))
+    })
+}
+
+/// Scrape a YouTube playlist for its videos.
+/// Returns the list of video URLs, without the playlist parameters
+pub fn youtube_playlist(playlist_url: &str, config: &Config) -> types::VecStringResult {
+    let base = Url::parse(playlist_url)?;
+    scrape_list(
+        playlist_url,
+        &config.youtube_selector,
+        config,
+        |html| match html.get_attribute_value("href")? {
+            Some(href) => Ok(Some(add::bare_video_url(&base.join(&href)?).to_string())),
+            None => Ok(None),
+        },
+    )
+}
 
-    let mut results = HashSet::new();
+/// Scrape the page at `url` for the elements matching `selector`, turning each into a result with
+/// `extract`. The results are unique, in the order of the page. An element for which `extract` yields `None` is skipped, while an error stops the
+/// current scroll.
+///
+/// The page is scrolled down until no new results show up for two consecutive scrolls, or until
+/// `config.scroll_limit` scrolls have been made.
+///
+/// The results are checkpointed to a partial file (see `partial_path`) after each scroll, along
+/// with the number of scrolls made, and the file is removed once the end of the list is reached.
+/// With `config.resume_scrape`, an unfinished scrape continues from it, see `scroll_results`.
+fn scrape_list<F>(url: &str, selector: &str, config: &Config, extract: F) -> types::VecStringResult
+where
+    F: Fn(&Element) -> Result<Option<String>, Box<dyn Error>>,
+{
+    let browser = headless_chrome::Browser::default().map_err(|e| e.to_string())?;
+    let tab = browser.new_tab().map_err(|e| e.to_string())?;
+    tab.navigate_to(url).map_err(|e| e.to_string())?;
 
-    // Attempt scraping. If any error occurs, return what's been found so far
-    'outer: for _ in 0..5 {
-        let elements =
-            tab.wait_for_elements("div[data-testid='playlist-tracklist'] div[aria-colindex='2']");
-        if elements.is_err() {
-            break;
+    println!("Scraping {}...", url);
+
+    let partial = partial_path(url, config);
+    let mut checkpoint = (0, Vec::new());
+    if config.resume_scrape {
+        if let Some(partial) = &partial {
+            checkpoint = read_partial(partial);
+            println!("Resuming with {} results", checkpoint.1.len());
         }
+    }
 
-        for html in elements.unwrap() {
-            let text = html.get_inner_text();
-            if text.is_err() {
-                break;
+    // Attempt scraping. If any error occurs, return what's been found so far
+    let read = || {
+        let elements = tab.wait_for_elements(selector).ok()?;
+        let mut texts = Vec::new();
+        for html in elements {
+            match extract(&html) {
+                Ok(Some(text)) => texts.push(text),
+                Ok(None) => continue,
+                Err(_) => break,
             }
+        }
+        Some(texts)
+    };
+    let scroll = || (0..config.scroll_page_downs).all(|_| tab.press_key("PageDown").is_ok());
+    let (finished, results) = scroll_results(checkpoint, config, partial.as_ref(), read, scroll)?;
 
-            let text = text.unwrap().replace("\n", " ");
-            if text == "Title" {
-                continue;
-            }
+    if finished {
+        if let Some(partial) = &partial {
+            let _ = fs::remove_file(partial); // The scrape is complete, so there is nothing to resume
+        }
+    }
+    println!("Total unique results: {}", results.len());
+    Ok(results)
+}
+
+/// Collect the results shown by `read` while scrolling down with `scroll`, starting from the
+/// `(scrolls, results)` of a `checkpoint`. As the page starts at the top again, the checkpointed
+/// scrolls are first made again to catch up with the known results. These do not count against
+/// `config.scroll_limit`, so a resumed scrape gets further than the previous one, and scrolling
+/// past the known results does not count as stale.
+///
+/// # Returns
+/// Whether the end of the list was reached, and the results
+fn scroll_results<R, S>(
+    checkpoint: (usize, Vec<String>),
+    config: &Config,
+    partial: Option<&PathBuf>,
+    mut read: R,
+    mut scroll: S,
+) -> Result<(bool, Vec<String>), Box<dyn Error>>
+where
+    R: FnMut() -> Option<Vec<String>>,
+    S: FnMut() -> bool,
+{
+    let (catch_up, mut results) = checkpoint;
+    let mut seen = results.iter().cloned().collect::<HashSet<String>>();
+    let mut scrolls = 0;
+    let mut caught_up = false;
+    let mut stale = 0; // Number of consecutive scrolls without new results
 
-            println!("Found: {}", text);
-            results.insert(text);
+    loop {
+        let Some(texts) = read() else {
+            return Ok((false, results));
+        };
+
+        let found = results.len();
+        for text in texts {
+            if seen.insert(text.clone()) {
+                println!("Found: {}", text);
+                results.push(text);
+            }
         }
 
-        for _ in 0..2 {
-            if tab.press_key("PageDown").is_err() {
-                break 'outer;
+        if results.len() > found {
+            caught_up = true;
+            stale = 0;
+            if let Some(partial) = partial {
+                write_partial(partial, scrolls, &results)?;
             }
+        } else if caught_up || scrolls >= catch_up {
+            stale += 1;
+            if stale == 2 {
+                return Ok((true, results));
+            }
+        }
+
+        if scrolls == catch_up + config.scroll_limit || !scroll() {
+            return Ok((false, results));
         }
+        scrolls += 1;
     }
+}
 
-    println!("Total unique results: {}", results.len());
-    Ok(results)
+/// # Returns
+/// The checkpoint file of the scrape of `url`: `.tapeworm/cache/HASH.partial`, where HASH is the
+/// FNV-1a hash of the URL, which is stable across runs (unlike the hasher of `HashMap`).
+fn partial_path(url: &str, config: &Config) -> Option<PathBuf> {
+    let lib_conf_folder = config.lib_conf_path.as_ref()?.parent()?;
+    let hash = url.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    Some(
+        lib_conf_folder
+            .join("cache")
+            .join(format!("{:016x}.partial", hash)),
+    )
+}
+
+/// # Returns
+/// The number of scrolls and the results checkpointed in the `partial` file: a `#scrolls N` line,
+/// followed by one result per line. A missing file has no scrolls and no results.
+fn read_partial(partial: &PathBuf) -> (usize, Vec<String>) {
+    let contents = fs::read_to_string(partial).unwrap_or_default();
+    let mut lines = contents.lines().peekable();
+    let scrolls = lines
+        .next_if(|line| line.starts_with(SCROLLS_PREFIX))
+        .and_then(|line| line[SCROLLS_PREFIX.len()..].parse().ok())
+        .unwrap_or(0);
+    let results = lines
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect();
+    (scrolls, results)
+}
+
+fn write_partial(partial: &PathBuf, scrolls: usize, results: &[String]) -> types::UnitResult {
+    util::guarantee_dir_path(partial.parent().unwrap().to_path_buf())?;
+    let header = format!("{}{}\n", SCROLLS_PREFIX, scrolls);
+    util::write(partial, header + &results.join("\n") + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn checkpoints_results() {
+        let dir = std::env::temp_dir().join(format!("tapeworm-partial-{}", std::process::id()));
+        let mut config = Config::default();
+        config.lib_conf_path = Some(dir.join("lib.conf"));
+
+        let url = "https://open.spotify.com/playlist/123";
+        let partial = partial_path(url, &config).unwrap();
+        assert_eq!(partial, dir.join("cache").join("9f028be022aeb81f.partial"));
+        assert_ne!(
+            partial_path("https://open.spotify.com/playlist/124", &config),
+            Some(partial.clone())
+        );
+
+        assert_eq!(read_partial(&partial), (0, Vec::new()));
+        let results = vec![String::from("Song Band"), String::from("Other Band")];
+        write_partial(&partial, 3, &results).unwrap();
+        assert_eq!(read_partial(&partial), (3, results));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_spotify_credentials() {
+        let mut config = Config::default();
+        let no_env = |_: &str| None;
+        let err = resolve_credentials(&config, no_env)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(SPOTIFY_CLIENT_ID) && err.contains(SPOTIFY_CLIENT_SECRET));
+
+        config.spotify_client_id = Some(String::from("conf-id"));
+        config.spotify_client_secret = Some(Secret::from("conf-secret"));
+        let credentials = resolve_credentials(&config, no_env).unwrap();
+        assert_eq!(credentials.client_id, "conf-id");
+        assert_eq!(credentials.client_secret.expose(), "conf-secret");
+
+        // The environment takes priority, and the secret is never printed
+        let env = |key: &str| Some(format!("env-{}", key.to_lowercase()));
+        let credentials = resolve_credentials(&config, env).unwrap();
+        assert_eq!(credentials.client_id, "env-spotify_client_id");
+        assert_eq!(
+            credentials.client_secret.expose(),
+            "env-spotify_client_secret"
+        );
+        assert!(!format!("{:?}", credentials).contains("env-spotify_client_secret"));
+        assert!(!format!("{:?}", config).contains("conf-secret"));
+    }
+
+    #[test]
+    fn resumes_beyond_previous_scrolls() {
+        let dir = std::env::temp_dir().join(format!("tapeworm-resume-{}", std::process::id()));
+        let partial = dir.join("cache").join("list.partial");
+        let mut config = Config::default();
+        config.scroll_limit = 2;
+
+        // A list of 10 results, of which each scroll reveals 2 more
+        let scrape = |checkpoint| {
+            let position = Cell::new(0);
+            let read = || {
+                Some(
+                    (0..(position.get() + 1) * 2)
+                        .take(10)
+                        .map(|n| n.to_string())
+                        .collect(),
+                )
+            };
+            let scroll = || {
+                position.set(position.get() + 1);
+                true
+            };
+            scroll_results(checkpoint, &config, Some(&partial), read, scroll).unwrap()
+        };
+
+        let (finished, results) = scrape((0, Vec::new()));
+        assert!(!finished);
+        assert_eq!(results.len(), 6);
+        assert_eq!(read_partial(&partial), (2, results));
+
+        // Catching up does not count against the scroll limit, and the results keep the page order
+        let (finished, results) = scrape(read_partial(&partial));
+        assert!(!finished);
+        assert_eq!(results, (0..10).map(|n| n.to_string()).collect::<Vec<_>>());
+
+        let (finished, results) = scrape(read_partial(&partial));
+        assert!(finished);
+        assert_eq!(results.len(), 10);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,59 @@
+//! Persistent library state, stored as JSON in `.tapeworm/state.json`.
+
+use crate::types;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::PathBuf;
+
+pub struct State {
+    path: PathBuf,
+    data: Map<String, Value>,
+}
+
+impl State {
+    /// Load the state from `path`. A missing or malformed file yields an empty state.
+    pub fn load(path: &PathBuf) -> Self {
+        let data = fs::read_to_string(path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<Value>(&s).ok())
+            .and_then(|v| match v {
+                Value::Object(map) => Some(map),
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        State {
+            path: path.clone(),
+            data,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.data.get(key)
+    }
+
+    pub fn set(&mut self, key: &str, value: Value) {
+        self.data.insert(String::from(key), value);
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.data.remove(key);
+    }
+
+    /// Get the list of strings stored under `key`, ignoring any non-string items.
+    pub fn strings(&self, key: &str) -> Vec<String> {
+        match self.get(key) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .filter_map(|i| i.as_str().map(String::from))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn save(&self) -> types::UnitResult {
+        let contents = serde_json::to_string_pretty(&Value::Object(self.data.clone()))?;
+        fs::write(&self.path, contents)?;
+        Ok(())
+    }
+}
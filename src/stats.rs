@@ -0,0 +1,138 @@
+//! Summarize the contents of a library.
+
+use crate::{types, util, Config};
+use audiotags::Tag;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use tabwriter::TabWriter;
+
+/// The name counted for files without an 'artist' or 'genre' tag.
+const UNKNOWN: &str = "(unknown)";
+
+#[derive(Debug, Default, PartialEq)]
+struct Stats {
+    /// The number of audio files
+    files: usize,
+    /// The number of audio files without a 'title' tag
+    untagged: usize,
+    /// The total size of the audio files, in bytes
+    size: u64,
+    by_artist: BTreeMap<String, usize>,
+    by_genre: BTreeMap<String, usize>,
+}
+
+/// Print an overview of the audio files in the library (and its subfolders, except `.tapeworm`),
+/// and the number of inputs left to download. With `config.verbose`, each file is listed as well.
+pub fn run(config: &Config) -> types::UnitResult {
+    let mut files =
+        util::filepaths_in_recursive(config.lib_path.as_ref().unwrap(), &config.lib_dir)?;
+    files.sort();
+    let stats = collect(&files, config.verbose);
+
+    let inputs = util::read_text_file(config.input_path.as_ref().unwrap(), config.encoding)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .count();
+
+    let mut tw = TabWriter::new(io::stdout().lock());
+    writeln!(&mut tw, "Audio files\t{}", stats.files)?;
+    writeln!(&mut tw, "Untagged files\t{}", stats.untagged)?;
+    writeln!(&mut tw, "Total size\t{}", human_size(stats.size))?;
+    writeln!(&mut tw, "Inputs to download\t{}", inputs)?;
+    tw.flush()?;
+
+    print_counts("ARTIST", &stats.by_artist)?;
+    print_counts("GENRE", &stats.by_genre)?;
+    Ok(())
+}
+
+/// Gather the `Stats` of the (audio) `files`. Files that are not (supported) audio files are
+/// skipped. With `verbose`, the artist and title of each file are printed.
+fn collect(files: &[PathBuf], verbose: bool) -> Stats {
+    let mut stats = Stats::default();
+    for file in files {
+        let Ok(ftag) = Tag::new().read_from_path(file) else {
+            continue; // Not a (supported) audio file
+        };
+        stats.files += 1;
+        stats.size += fs::metadata(file).map(|m| m.len()).unwrap_or_default();
+        if ftag.title().is_none() {
+            stats.untagged += 1;
+        }
+        let artist = ftag.artist().unwrap_or(UNKNOWN);
+        let genre = ftag.genre().unwrap_or(UNKNOWN);
+        *stats.by_artist.entry(String::from(artist)).or_default() += 1;
+        *stats.by_genre.entry(String::from(genre)).or_default() += 1;
+
+        if verbose {
+            let title = ftag.title().unwrap_or(UNKNOWN);
+            println!("  {}: {} - {}", file.display(), artist, title);
+        }
+    }
+    stats
+}
+
+/// Print the `counts` as a table, most common first, under the `name` column.
+fn print_counts(name: &str, counts: &BTreeMap<String, usize>) -> types::UnitResult {
+    if counts.is_empty() {
+        return Ok(());
+    }
+    let mut counts = counts.iter().collect::<Vec<(&String, &usize)>>();
+    counts.sort_by(|a, b| b.1.cmp(a.1)); // Stable, so equal counts stay sorted by name
+
+    println!();
+    let mut tw = TabWriter::new(io::stdout().lock());
+    writeln!(&mut tw, "{}\tFILES", name)?;
+    for (value, count) in counts {
+        writeln!(&mut tw, "{}\t{}", value, count)?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+/// # Returns
+/// The `bytes` in the largest fitting binary unit, e.g. `1.5 MiB`.
+fn human_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+
+    #[test]
+    fn collects_stats() {
+        let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test");
+        let files = ["title.mp3", "no_title.mp3", "no_tags.mp3", "not_audio.jpg"]
+            .map(|name| resources.join(name));
+        let stats = collect(&files, false);
+
+        assert_eq!(stats.files, 3);
+        assert_eq!(stats.untagged, 2);
+        assert_eq!(stats.by_artist.values().sum::<usize>(), 3);
+        assert_eq!(stats.by_genre.get(UNKNOWN), Some(&3));
+    }
+}
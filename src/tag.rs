@@ -1,29 +1,59 @@
 //! This module provides functionality for extracting tags from a filename.
 
-use crate::util::PromptOption::{Edit, No, Yes};
-use crate::{editor, types, util, Config};
+use crate::report::Outcome;
+use crate::state::State;
+use crate::util::PromptOption::{Edit, No, Yes, YesToAll};
+use crate::{editor, history, rename, types, util, Config, TapewormError};
 use audiotags::{AudioTag, Tag};
+use id3::TagLike;
 use regex::Regex;
 use sanitize_filename;
-use std::collections::HashMap;
-use std::{fs, io::BufRead, path::PathBuf};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::{fs, io::BufRead};
+
+const PROGRESS_KEY: &str = "tag_progress";
+
+/// Lyrics sidecars larger than this (in bytes) are not embedded, as they are unlikely to be lyrics.
+const MAX_LYRICS_SIZE: u64 = 64 * 1024;
 
 type TagBox = Box<dyn AudioTag + Sync + Send>;
 
-struct TagExtractor {
+/// The tags that an edit may carry over to the remaining files, as they are shared by an album.
+const PERSISTABLE_TAGS: [&str; 4] = ["ALBUM", "ALBUM_ARTIST", "GENRE", "YEAR"];
+
+/// What is carried across the files of a single `tag` run.
+#[derive(Default)]
+struct Session {
+    /// The skipped files, along with the reason, see `skip`
+    skipped: Vec<(PathBuf, String)>,
+    /// Edited tag values to apply to each remaining file, see `persist`
+    persisted: Vec<(String, Option<String>)>,
+    /// Accept the remaining proposals without prompting, as with `auto_tag`
+    accept_all: bool,
+}
+
+/// The (verbose mode) alternatives that separate artists, see `TagExtractor::separate`.
+const ARTIST_SEPARATORS: &str =
+    r"\s(x|and)\s | (^|\s) (feat(uring|\.)? | ft\.? | w[⧸/] ) | & | , | ，";
+
+pub struct TagExtractor {
     artist_separator: Regex,
     title_formats: Vec<Regex>,
     catch_all: Regex,
+    label: Option<Regex>,
+    version: Option<Regex>,
+    work: Option<Regex>,
+    /// Keep a leading track number, e.g. "04.", in the title, see `with_kept_track`
+    keep_track: bool,
     verbose: bool,
 }
 
 impl TagExtractor {
     fn new(verbose: bool) -> Self {
         Self {
-            artist_separator: Regex::new(
-                r"(?ix) ( \s(x|and)\s | (^|\s) (feat(uring|\.)? | ft\.? | w[⧸/] ) | & | , | ， )",
-            )
-            .unwrap(),
+            artist_separator: Regex::new(&format!(r"(?ix) ( {} )", ARTIST_SEPARATORS)).unwrap(),
             title_formats: vec![
                 Regex::new(
                     // 「GENRE」[ARTISTS] TITLE
@@ -69,10 +99,70 @@ impl TagExtractor {
         ",
             )
             .unwrap(),
+            label: None,
+            version: None,
+            work: None,
+            keep_track: false,
             verbose,
         }
     }
 
+    pub fn from(config: &Config) -> Self {
+        let mut extractor = Self::new(config.verbose);
+        if config.extract_label {
+            extractor = extractor.with_label();
+        }
+        if config.extended_separators {
+            extractor = extractor.with_extended_separators();
+        }
+        if config.extract_work {
+            extractor = extractor.with_work();
+        }
+        if config.keep_track_in_title {
+            extractor = extractor.with_kept_track();
+        }
+        if !config.version_keywords.is_empty() {
+            extractor = extractor.with_versions(&config.version_keywords);
+        }
+        extractor
+    }
+
+    /// Also extract version qualifiers containing any of the `keywords`, e.g. "(Live)" or
+    /// "[Acoustic Session]".
+    fn with_versions(mut self, keywords: &[String]) -> Self {
+        let keywords: Vec<String> = keywords.iter().map(|k| regex::escape(k)).collect();
+        self.version = Some(
+            Regex::new(&format!(
+                r"(?ix) [\[(] [^\[\]()]* \b({})\b [^\[\]()]* [\])]",
+                keywords.join("|")
+            ))
+            .unwrap(),
+        );
+        self
+    }
+
+    /// Also separate artists on "vs", "versus", "presents", "pres." and "with", e.g. "A vs. B".
+    /// These are not separated by default, as they may be part of an artist name.
+    fn with_extended_separators(mut self) -> Self {
+        self.artist_separator = Regex::new(&format!(
+            r"(?ix) ( {} | \s(vs\.? | versus | presents | pres\. | with)\s )",
+            ARTIST_SEPARATORS
+        ))
+        .unwrap();
+        self
+    }
+
+    /// Also extract label/catalog information, e.g. "[LABEL CAT001]" or "(CAT-001)".
+    fn with_label(mut self) -> Self {
+        self.label = Some(
+            Regex::new(
+                r"(?x) [\[(] \s* (?<label> ([^\[\]()]*\s)? [A-Z]{2,}[A-Z0-9]* -? \d{2,} ) \s* [\])]",
+            )
+            .unwrap(),
+        );
+        self
+    }
+
     /// Separates a string like "Band ft Artist, Musician & Singer"
     /// into a vector like ["Band", "Artist", "Musician", "Singer"].
     fn separate(&self, artists: &str) -> Vec<String> {
@@ -83,6 +173,49 @@ impl TagExtractor {
             .collect()
     }
 
+    /// Build tags from the location of `entry` in `root`, for files organized as
+    /// `root/ARTIST/ALBUM/TITLE.ext`: the parent folder is the album, its parent the artist, and
+    /// the filename is parsed like a title (see `build_tags`). Folders outside `root` are ignored.
+    fn build_tags_from_path(&self, root: &Path, entry: &Path) -> TagProposal {
+        let stem = entry.file_stem().unwrap().to_string_lossy();
+        let mut proposal = self.build_tags(stem.trim());
+
+        let folders: Vec<String> = entry
+            .strip_prefix(root)
+            .ok()
+            .and_then(|p| p.parent())
+            .map(|p| p.iter().map(|f| f.to_string_lossy().into_owned()).collect())
+            .unwrap_or_default();
+        let mut folders = folders.iter().rev();
+        if let Some(album) = folders.next() {
+            proposal.album = Some(album.clone());
+        }
+        if let Some(artist) = folders.next() {
+            let mut artists = self.separate(artist);
+            if let Some(featured) = &proposal.all_artists {
+                artists.extend(featured.iter().cloned()); // Keep those from the filename
+            }
+            proposal.all_artists = None;
+            proposal.feature(artists);
+        }
+
+        proposal
+    }
+
+    /// Also split a "Work: Movement" title into the grouping (the work) and the title (the
+    /// movement), e.g. "Symphony No. 5, Op. 67: I. Allegro con brio".
+    fn with_work(mut self) -> Self {
+        self.work = Some(Regex::new(r"(?x) ^ (?<work> [^:]+ ) : \s+ (?<movement> .+ ) $").unwrap());
+        self
+    }
+
+    /// Keep a leading track number in the title, e.g. "04. Song" for "04. Band - Song", so the
+    /// filename sorts by track. The track tag is extracted all the same.
+    fn with_kept_track(mut self) -> Self {
+        self.keep_track = true;
+        self
+    }
+
     /// Attempt to extract the following tags from the title:
     /// - genre
     /// - artists: can be a single artist or multiple, e.g. "Band", "Artist ft Singer"
@@ -140,6 +273,8 @@ impl TagExtractor {
         let mut meta_title = String::from(meta_title);
         // The resulting actual track title (some info might be stripped / added)
         let mut title = meta_title.to_string();
+        // The leading track number, when kept in the title
+        let mut kept_track = None;
 
         if let Some(tags) = self.tags_from(&meta_title) {
             if let Some(genre) = tags.get("genre") {
@@ -149,6 +284,9 @@ impl TagExtractor {
             if let Some(track) = tags.get("track") {
                 let track = track.to_string();
                 title = util::remove_str_from_string(title, &track);
+                if self.keep_track {
+                    kept_track = Some(track.clone());
+                }
                 let track = String::from(&track[..track.len() - 1]); // Omit "."
                 proposal.track = track.parse::<u16>().ok();
             }
@@ -167,6 +305,25 @@ impl TagExtractor {
             }
         }
 
+        if let Some(label) = &self.label {
+            // Extract before anything else, as catalog numbers may contain e.g. a "year"
+            if let Some(caps) = label.captures(&meta_title.clone()) {
+                let full = caps.get(0).unwrap().as_str();
+                title = util::remove_str_from_string(title, full);
+                meta_title = util::remove_str_from_string(meta_title, full);
+                proposal.label = Some(String::from(caps["label"].trim()));
+            }
+        }
+
+        if let Some(version) = &self.version {
+            // Extract before the remix, so e.g. "(Live Edit)" is not taken as a remix
+            if let Some(m) = version.find(&meta_title.clone()) {
+                title = util::remove_str_from_string(title, m.as_str());
+                meta_title = util::remove_str_from_string(meta_title, m.as_str());
+                proposal.version = Some(util::remove_brackets(m.as_str()));
+            }
+        }
+
         for caps in self.catch_all.captures_iter(&meta_title) {
             if self.verbose {
                 println!("{:#?}", caps);
@@ -213,6 +370,17 @@ impl TagExtractor {
             }
         }
 
+        if let Some(work) = &self.work {
+            // Extract last, as the movement may still contain e.g. a year or remix
+            if let Some(caps) = work.captures(title.trim()) {
+                proposal.grouping = Some(String::from(caps["work"].trim()));
+                title = String::from(caps["movement"].trim());
+            }
+        }
+
+        if let Some(track) = kept_track {
+            title = format!("{} {}", track, title.trim());
+        }
         proposal.title = Some(title);
 
         if self.verbose {
@@ -228,12 +396,19 @@ struct TagProposal {
     album_artist: Option<String>,
     all_artists: Option<Vec<String>>,
     artist: Option<String>,
+    disc: Option<u16>,
     filename: String,
     final_title: Option<String>,
     genre: Option<String>,
+    /// The work a movement belongs to, written to TIT1 (mp3) or GROUPING (flac)
+    grouping: Option<String>,
+    /// Written to the comment, so also editable as COMMENT
+    label: Option<String>,
+    lyrics: Option<String>,
     remix: Option<String>,
     title: Option<String>,
     track: Option<u16>,
+    version: Option<String>,
     year: Option<i32>,
 }
 impl TagProposal {
@@ -250,35 +425,49 @@ impl TagProposal {
     }
 
     /// Update the `artist` field based on the first artist of the `all_artists` field,
-    /// and update the (original) `title` and `filename` based on provided templates.
-    fn update(&mut self, title_template: &String, filename_template: &String) {
-        let mut feat = String::new();
+    /// and update the (original) `title` and `filename` based on the configured templates.
+    /// With `infer_album_artist`, an absent `album_artist` is set to `artist` if there is an `album`.
+    fn update(&mut self, config: &Config) {
         if let Some(featuring) = &self.all_artists {
-            for (i, a) in featuring.iter().enumerate() {
-                if i == 0 {
-                    self.artist = Some(String::from(a));
-                } else if i == featuring.len() - 1 {
-                    feat.push_str(a);
-                } else {
-                    feat.push_str(&format!("{}, ", String::from(a)));
-                }
-            }
-            if let Some(i) = feat.rfind(',') {
-                feat.replace_range(i..=i, " &");
-            }
+            self.artist = featuring.first().cloned();
         }
+        let feat = self.feat(config);
 
+        if config.infer_album_artist && self.album.is_some() && self.album_artist.is_none() {
+            self.album_artist = self.artist.clone();
+        }
+
+        let title_template = &config.title_template;
         self.final_title = Some(self.apply_template(&feat, &self.title, title_template));
 
+        let filename_template = &config.filename_template;
         let filename = self.apply_template(&feat, &self.final_title, filename_template);
         self.filename = sanitize_filename::sanitize(filename);
     }
 
-    fn present(&self, ftag: &TagBox, entry: &PathBuf) {
+    /// Join all but the first of `all_artists` with the configured separators, for `{feat}`.
+    fn feat(&self, config: &Config) -> String {
+        let mut feat = String::new();
+        if let Some(featuring) = &self.all_artists {
+            let count = featuring.len().saturating_sub(1);
+            for (i, a) in featuring.iter().skip(1).enumerate() {
+                if i > 0 && i == count - 1 {
+                    feat.push_str(&config.feat_last_separator);
+                } else if i > 0 {
+                    feat.push_str(&config.feat_separator);
+                }
+                feat.push_str(a);
+            }
+        }
+        feat
+    }
+
+    fn present(&self, ftag: &TagBox, entry: &Path) {
         let album = self.album.as_ref().map(|s| s.as_str());
         let album_artist = self.album_artist.as_ref().map(|s| s.as_str());
         let artist = self.artist.as_ref().map(|s| s.as_str());
         let genre = self.genre.as_ref().map(|s| s.as_str());
+        let label = self.label.as_ref().map(|s| s.as_str());
         let title = self.final_title.as_ref().map(|s| s.as_str());
         let old_filename = entry.file_stem().unwrap().to_owned().into_string().unwrap();
 
@@ -286,48 +475,181 @@ impl TagProposal {
         print_proposal("ARTIST", &ftag.artist(), &artist);
         print_proposal("ALBUM_ARTIST", &ftag.album_artist(), &album_artist);
         print_proposal("ALBUM", &ftag.album_title(), &album);
+        print_proposal("DISC", &ftag.disc_number(), &self.disc);
         print_proposal("TRACK", &ftag.track_number(), &self.track);
         print_proposal("TITLE", &ftag.title(), &title);
         print_proposal("YEAR", &ftag.year(), &self.year);
         print_proposal("GENRE", &ftag.genre(), &genre);
+        print_proposal("LABEL", &ftag.comment(), &label);
+        if self.grouping.is_some() {
+            print_proposal("GROUPING", &read_grouping(entry), &self.grouping);
+        }
+        if let Some(lyrics) = &self.lyrics {
+            let lines = |l: &String| format!("({} lines)", l.lines().count());
+            print_proposal(
+                "LYRICS",
+                &read_lyrics(entry).map(|l| lines(&l)),
+                &Some(lines(lyrics)),
+            );
+        }
         print_proposal("FILENAME", &Some(&old_filename), &Some(&self.filename));
+        if let Some(existing) = self.collision(entry) {
+            println!(
+                "  ! will collide with existing file: {}\n",
+                existing.display()
+            );
+        }
     }
 
-    fn edit<R: BufRead>(&mut self, mut reader: R) -> types::UnitResult {
-        for (tag_name, tag_value) in editor::edit(&mut reader)? {
-            match tag_name.as_str() {
-                "ARTIST" => {
-                    self.all_artists = None;
-                    if let Some(artists) = tag_value {
-                        self.feature(artists.split(";").map(|s| s.to_string()).collect());
-                    }
+    /// # Returns
+    /// The path that the `entry` is renamed to, per the proposed filename.
+    fn target(&self, entry: &Path) -> PathBuf {
+        let mut to = entry.with_file_name(&self.filename);
+        if let Some(ext) = entry.extension() {
+            to.set_extension(ext);
+        }
+        to
+    }
+
+    /// # Returns
+    /// The file that already exists at the `target` of the `entry`, if any. Merely changing the
+    /// case of the filename is not a collision, as the `entry` itself may be found on a
+    /// case-insensitive file system.
+    fn collision(&self, entry: &Path) -> Option<PathBuf> {
+        let to = self.target(entry);
+        let same = to.as_os_str().eq_ignore_ascii_case(entry.as_os_str());
+        Some(to).filter(|to| !same && fs::metadata(to).is_ok())
+    }
+
+    /// # Returns
+    /// `serde_json::Value`: an object with the `old` and `new` value of each tag, the file `path`,
+    /// and the extracted `all_artists`, `remix` and `version`
+    fn to_json(&self, ftag: &TagBox, entry: &PathBuf) -> serde_json::Value {
+        let old_filename = entry.file_stem().unwrap().to_owned().into_string().unwrap();
+        json!({
+            "path": entry.to_str(),
+            "all_artists": self.all_artists,
+            "remix": self.remix,
+            "version": self.version,
+            "artist": { "old": ftag.artist(), "new": self.artist },
+            "album_artist": { "old": ftag.album_artist(), "new": self.album_artist },
+            "album": { "old": ftag.album_title(), "new": self.album },
+            "disc": { "old": ftag.disc_number(), "new": self.disc },
+            "track": { "old": ftag.track_number(), "new": self.track },
+            "title": { "old": ftag.title(), "new": self.final_title },
+            "year": { "old": ftag.year(), "new": self.year },
+            "genre": { "old": ftag.genre(), "new": self.genre },
+            "label": { "old": ftag.comment(), "new": self.label },
+            "grouping": { "old": read_grouping(entry), "new": self.grouping },
+            "lyrics": { "old": read_lyrics(entry), "new": self.lyrics },
+            "filename": { "old": old_filename, "new": self.filename },
+        })
+    }
+
+    /// # Returns
+    /// The names of the edited tags
+    fn edit<R: BufRead>(&mut self, mut reader: R) -> types::VecStringResult {
+        let current = editor::SUPPORTED_TAGS
+            .map(|tag_name| (String::from(tag_name), self.get(tag_name)))
+            .into_iter()
+            .collect();
+        let mut edited = Vec::new();
+        for (tag_name, tag_value) in editor::edit(&mut reader, &current)? {
+            self.set(&tag_name, tag_value);
+            edited.push(tag_name);
+        }
+
+        Ok(edited)
+    }
+
+    /// # Returns
+    /// The proposed value of the tag with the (uppercase) `tag_name`, as it would be set in the
+    /// editor, e.g. all artists separated by ';' for ARTIST.
+    fn get(&self, tag_name: &str) -> Option<String> {
+        match tag_name {
+            "ARTIST" => match &self.all_artists {
+                Some(artists) => Some(artists.join(";")),
+                None => self.artist.clone(),
+            },
+            "ALBUM" => self.album.clone(),
+            "ALBUM_ARTIST" => self.album_artist.clone(),
+            "DISC" => self.disc.map(|disc| disc.to_string()),
+            "GENRE" => self.genre.clone(),
+            "GROUPING" => self.grouping.clone(),
+            "LABEL" | "COMMENT" => self.label.clone(),
+            "TITLE" => self.title.clone(),
+            "TRACK" => self.track.map(|track| track.to_string()),
+            "YEAR" => self.year.map(|year| year.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Set (or clear, when `None`) the tag with the (uppercase) `tag_name`, overriding any
+    /// extracted value.
+    fn set(&mut self, tag_name: &str, tag_value: Option<String>) {
+        match tag_name {
+            "ARTIST" => {
+                self.all_artists = None;
+                if let Some(artists) = tag_value {
+                    self.feature(artists.split(";").map(|s| s.to_string()).collect());
                 }
-                "ALBUM" => self.album = tag_value,
-                "ALBUM_ARTIST" => self.album_artist = tag_value,
-                "GENRE" => self.genre = tag_value,
-                "TITLE" => self.title = tag_value,
-                "TRACK" => {
-                    if let Ok(track) = util::parse::<u16>(tag_value) {
-                        self.track = track;
-                    } else {
-                        println!("TRACK is not a valid number, ignoring");
-                    }
+            }
+            "ALBUM" => self.album = tag_value,
+            "ALBUM_ARTIST" => self.album_artist = tag_value,
+            "DISC" => {
+                if let Ok(disc) = util::parse_track(tag_value) {
+                    self.disc = disc;
+                } else {
+                    println!("DISC is not a valid number, ignoring");
                 }
-                "YEAR" => {
-                    if let Ok(year) = util::parse::<i32>(tag_value) {
-                        self.year = year;
-                    } else {
-                        println!("YEAR is not a valid number, ignoring");
-                    }
+            }
+            "GENRE" => self.genre = tag_value,
+            "GROUPING" => self.grouping = tag_value,
+            "LABEL" | "COMMENT" => self.label = tag_value,
+            "TITLE" => self.title = tag_value,
+            "TRACK" => {
+                if let Ok(track) = util::parse_track(tag_value) {
+                    self.track = track;
+                } else {
+                    println!("TRACK is not a valid number, ignoring");
                 }
-                _ => println!("Unsupported tag: '{}', skipping", tag_name),
             }
+            "YEAR" => {
+                if let Ok(year) = util::parse_year(tag_value) {
+                    self.year = year;
+                } else {
+                    println!("YEAR is not a valid number, ignoring");
+                }
+            }
+            _ => println!("Unsupported tag: '{}', skipping", tag_name),
+        }
+    }
+
+    /// Whether accepting this proposal would change any tag of `ftag` or the filename of `entry`.
+    /// Values that are not proposed (`None`) are kept, so they do not count as a change.
+    fn changes(&self, ftag: &TagBox, entry: &PathBuf) -> bool {
+        fn differs<T: PartialEq + ?Sized>(new: Option<&T>, old: Option<&T>) -> bool {
+            new.is_some() && new != old
         }
 
-        Ok(())
+        let old_filename = entry.file_stem().unwrap().to_owned().into_string().unwrap();
+        differs(self.album.as_deref(), ftag.album_title())
+            || differs(self.album_artist.as_deref(), ftag.album_artist())
+            || differs(self.genre.as_deref(), ftag.genre())
+            || differs(self.label.as_deref(), ftag.comment())
+            || differs(self.artist.as_deref(), ftag.artist())
+            || differs(self.final_title.as_deref(), ftag.title())
+            || differs(self.disc.as_ref(), ftag.disc_number().as_ref())
+            || differs(self.track.as_ref(), ftag.track_number().as_ref())
+            || differs(self.year.as_ref(), ftag.year().as_ref())
+            || (self.grouping.is_some() && self.grouping != read_grouping(entry))
+            || (self.lyrics.is_some() && self.lyrics != read_lyrics(entry))
+            || self.filename != old_filename
     }
 
-    fn accept(self, mut ftag: TagBox, entry: &PathBuf) -> types::UnitResult {
+    /// Write the tags and rename the file, returning its new path.
+    fn accept(self, config: &Config, mut ftag: TagBox, entry: &PathBuf) -> types::PathBufResult {
+        let to = self.target(entry);
         if let Some(s) = self.album {
             ftag.set_album_title(&s);
         }
@@ -337,29 +659,48 @@ impl TagProposal {
         if let Some(s) = self.genre {
             ftag.set_genre(&s);
         }
+        if let Some(s) = self.label {
+            ftag.set_comment(s);
+        }
         if let Some(s) = self.artist {
             ftag.set_artist(&s);
         }
         if let Some(s) = self.final_title {
             ftag.set_title(&s);
         }
+        if let Some(i) = self.disc {
+            ftag.set_disc_number(i);
+        }
         if let Some(i) = self.track {
             ftag.set_track_number(i);
         }
         if let Some(i) = self.year {
             ftag.set_year(i);
         }
+        let mtime = util::snapshot_mtime(config, entry);
         ftag.write_to_path(entry.to_str().unwrap())?;
-
-        let mut to = entry.with_file_name(self.filename);
-        if let Some(ext) = entry.extension() {
-            to.set_extension(ext);
+        if let Some(version) = config.id3_version {
+            if entry
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("mp3"))
+            {
+                // audiotags picks the ID3 version itself, so rewrite the tag in the requested one
+                id3::Tag::read_from_path(entry)?.write_to_path(entry, version)?;
+            }
+        }
+        if let Some(grouping) = &self.grouping {
+            write_grouping(config, entry, grouping)?;
+        }
+        if let Some(lyrics) = &self.lyrics {
+            write_lyrics(entry, lyrics)?;
         }
+
         if to != entry.file_name().unwrap() {
-            fs::rename(entry, to)?;
+            fs::rename(entry, &to)?;
         }
+        util::restore_mtime(&to, mtime)?;
 
-        Ok(())
+        Ok(to)
     }
 
     fn apply_template(&self, feat: &String, title: &Option<String>, template: &String) -> String {
@@ -373,8 +714,14 @@ impl TagProposal {
         s = s.replace("{artist}", self.artist.as_ref().unwrap_or(&String::new()));
         s = s.replace("{feat}", feat);
         s = s.replace("{genre}", self.genre.as_ref().unwrap_or(&String::new()));
+        s = s.replace(
+            "{grouping}",
+            self.grouping.as_ref().unwrap_or(&String::new()),
+        );
+        s = s.replace("{label}", self.label.as_ref().unwrap_or(&String::new()));
         s = s.replace("{remix}", self.remix.as_ref().unwrap_or(&String::new()));
         s = s.replace("{title}", title.as_ref().unwrap_or(&String::new()));
+        s = s.replace("{version}", self.version.as_ref().unwrap_or(&String::new()));
         if let Some(track) = &self.track {
             s = s.replace("{track}", &format!("{}", track));
         } else {
@@ -411,72 +758,638 @@ where
     }
 }
 
+/// The filename (without extension) that `config.filename_template` yields for the current tags
+/// of a file, or `None` if the file has no 'title' tag.
+pub fn filename_from_tags(config: &Config, ftag: &TagBox) -> Option<String> {
+    let title = ftag
+        .title()
+        .map(|t| String::from(t.trim()))
+        .filter(|t| !t.is_empty());
+    title.as_ref()?;
+
+    let proposal = TagProposal {
+        album: ftag.album_title().map(String::from),
+        album_artist: ftag.album_artist().map(String::from),
+        artist: ftag.artist().map(String::from),
+        genre: ftag.genre().map(String::from),
+        label: ftag.comment().map(String::from),
+        track: ftag.track_number(),
+        year: ftag.year(),
+        ..Default::default()
+    };
+    let filename = proposal.apply_template(&String::new(), &title, &config.filename_template);
+    Some(sanitize_filename::sanitize(filename))
+}
+
+/// Render the `template` for the given (uppercase) tag names and values, exactly as tagging would.
+/// Besides the editable tags, REMIX, VERSION and FEAT may be given. As with editing, ARTIST may
+/// hold multiple artists separated by ';', of which all but the first end up in `{feat}`.
+///
+/// # Errors
+/// - If a tag name is not a template token
+pub fn render_template(
+    config: &Config,
+    template: &str,
+    values: &[(String, Option<String>)],
+) -> types::StringResult {
+    let mut proposal = TagProposal::default();
+    let mut feat = Vec::new();
+    for (tag_name, tag_value) in values {
+        match tag_name.as_str() {
+            "REMIX" => proposal.remix = tag_value.clone(),
+            "VERSION" => proposal.version = tag_value.clone(),
+            "FEAT" => feat.extend(
+                tag_value
+                    .iter()
+                    .flat_map(|v| v.split(';'))
+                    .map(String::from),
+            ),
+            "ARTIST" | "ALBUM" | "ALBUM_ARTIST" | "GENRE" | "GROUPING" | "LABEL" | "TITLE"
+            | "TRACK" | "YEAR" => proposal.set(tag_name, tag_value.clone()),
+            _ => return Err(format!("Unsupported tag: '{}'. See 'help'", tag_name).into()),
+        }
+    }
+    if !feat.is_empty() {
+        proposal.feature(feat);
+    }
+
+    proposal.update(config);
+    let feat = proposal.feat(config);
+    Ok(proposal.apply_template(&feat, &proposal.title, &String::from(template)))
+}
+
+/// Ask whether to apply the `edited` tags of the `proposal` that are `PERSISTABLE_TAGS` to all
+/// remaining files of the `session` as well. Nothing is asked when they are persisted already.
+fn persist<R: BufRead>(
+    proposal: &TagProposal,
+    edited: &[String],
+    session: &mut Session,
+    reader: R,
+) -> types::UnitResult {
+    let values = PERSISTABLE_TAGS
+        .iter()
+        .filter(|tag_name| edited.iter().any(|e| e == *tag_name))
+        .map(|tag_name| (String::from(*tag_name), proposal.get(tag_name)))
+        .filter(|value| !session.persisted.contains(value))
+        .collect::<Vec<(String, Option<String>)>>();
+    if values.is_empty() {
+        return Ok(());
+    }
+
+    let names = values
+        .iter()
+        .map(|(n, _)| n.as_str())
+        .collect::<Vec<&str>>();
+    let prompt = format!("Apply {} to all remaining files?", names.join(", "));
+    if util::select(&prompt, vec![No, YesToAll], No, reader)? == YesToAll {
+        for (tag_name, tag_value) in values {
+            session.persisted.retain(|(n, _)| *n != tag_name);
+            session.persisted.push((tag_name, tag_value));
+        }
+    }
+    Ok(())
+}
+
+/// Report why a file is skipped. In JSON mode, this is printed to stderr to keep stdout parseable.
+fn skip(config: &Config, reason: &str, entry: &Path, skipped: &mut Vec<(PathBuf, String)>) {
+    note(config, &format!("! {}, skipping", reason));
+    skipped.push((entry.to_path_buf(), String::from(reason)));
+}
+
+/// Print a `message` besides the proposals. In JSON mode, this is printed to stderr to keep stdout
+/// parseable.
+fn note(config: &Config, message: &str) {
+    if config.json {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+/// List the `skipped` files, grouped by the reason they were skipped for.
+fn skip_report(skipped: &[(PathBuf, String)]) -> String {
+    let mut groups: Vec<(&String, Vec<&PathBuf>)> = Vec::new();
+    for (entry, reason) in skipped {
+        if let Some((_, entries)) = groups.iter_mut().find(|(r, _)| *r == reason) {
+            entries.push(entry);
+        } else {
+            groups.push((reason, vec![entry]));
+        }
+    }
+
+    let mut report = String::new();
+    for (reason, entries) in groups {
+        report.push_str(&format!("{} ({}):\n", reason, entries.len()));
+        for entry in entries {
+            report.push_str(&format!("  {}\n", entry.display()));
+        }
+    }
+    report
+}
+
 /// For each downloaded file, use its "title" metadata tag to extract more tags. If this tag is not
 /// present in the file, it will not be affected.
 ///
 /// Titles generally contain extra information, e.g. "Artist ft. Band - Song (2024) [Remix]"
 /// Information such as collaborating artists, year, remix, etc. are extracted.
-pub fn run<R: BufRead>(config: &Config, mut reader: R) -> types::UnitResult {
-    let downloads = util::filepaths_in(config.input_dir.as_ref().unwrap())?;
+///
+/// Files are taken from all input directories. When `config.file` is set, only that file is
+/// processed. With `config.recursive`, files in subfolders of the input directories are processed
+/// as well. With `config.from_path`, tags are
+/// inferred from the location of the file instead, see `TagExtractor::build_tags_from_path`.
+///
+/// When the ALBUM, ALBUM_ARTIST, GENRE or YEAR of a file is edited, the user may apply the edited
+/// values to all remaining files as well, e.g. when tagging an album.
+///
+/// With `config.report_skips`, the skipped files are listed in `.tapeworm/skipped.txt`, grouped
+/// by the reason they were skipped for.
+///
+/// With `config.dry_run` (`--dry-run`), the proposals are only presented: nothing is written,
+/// renamed or backed up, and there are no prompts.
+///
+/// # Returns
+/// The number of files tagged and skipped, for the `--report`
+pub fn run<R: BufRead>(config: &Config, mut reader: R) -> types::OutcomeResult {
+    let mut downloads = if let Some(file) = &config.file {
+        vec![file.clone()]
+    } else if config.recursive {
+        let mut files = Vec::new();
+        for input_dir in &config.input_dirs {
+            files.extend(util::filepaths_in_recursive(input_dir, &config.lib_dir)?);
+        }
+        files
+    } else {
+        util::filepaths_in_all(&config.input_dirs)?
+    };
+
+    // Filenames handled so far, so an interrupted run can be resumed. These are skipped before
+    // limiting, so that each limited run continues where the previous one left off
+    let mut state = State::load(config.state_path.as_ref().unwrap());
+    let mut handled = if config.resume {
+        state.strings(PROGRESS_KEY)
+    } else {
+        Vec::new()
+    };
+    downloads.retain(|d| {
+        let filename = d.file_name().unwrap().to_string_lossy();
+        !handled.iter().any(|h| *h == filename)
+    });
+    let remaining = downloads.len();
+    util::limit_files(config, &mut downloads);
+    let limited = downloads.len() < remaining;
+    if config.rename_only {
+        return rename_only(config, &downloads);
+    }
     let total = downloads.len();
+    let auto_tag = if config.dry_run {
+        Some(false)
+    } else {
+        util::auto_for(config, config.auto_tag, total)
+    };
+    let Some(auto_tag) = auto_tag else {
+        return Ok(Outcome::Done);
+    };
+    if config.backup && !config.dry_run && !downloads.is_empty() {
+        let backup_dir = util::backup(&downloads, config)?;
+        println!("Backed up {} files to {}", total, backup_dir.display());
+    }
 
-    let extractor = TagExtractor::new(config.verbose);
+    let extractor = TagExtractor::from(config);
+    let mut session = Session::default();
+    let folder_albums = if config.album_from_folder {
+        folder_albums(&downloads)
+    } else {
+        HashMap::new()
+    };
+    let mut tagged = 0;
 
     for (i, entry) in downloads.iter().enumerate() {
         let filename = entry.file_name().unwrap().to_owned().into_string().unwrap();
-        println!("\nTagging {} of {}: {}", i + 1, total, filename);
+        if !config.json {
+            println!("\nTagging {} of {}: {}", i + 1, total, filename);
+        }
+
+        let handled_name = tag_file(
+            config,
+            entry,
+            &extractor,
+            auto_tag,
+            folder_albums.get(entry),
+            &mut reader,
+            &mut session,
+        )?;
+        if handled_name.is_some() {
+            tagged += 1;
+        }
+        if config.dry_run {
+            continue; // Nothing was handled
+        }
+        handled.push(handled_name.unwrap_or(filename));
+        state.set(PROGRESS_KEY, json!(handled));
+        state.save()?;
+    }
+
+    // The run completed, so there is nothing left to resume (unless the limit left out files)
+    if !config.dry_run {
+        if !limited {
+            state.remove(PROGRESS_KEY);
+            state.save()?;
+        }
+        let skipped = session.skipped.len();
+        let summary = format!("{} of {} files tagged, {} skipped", tagged, total, skipped);
+        history::record(config, "tag", &summary);
+    }
+
+    if config.report_skips {
+        let report_path = config
+            .lib_path
+            .as_ref()
+            .unwrap()
+            .join(&config.lib_dir)
+            .join("skipped.txt");
+        util::write(&report_path, skip_report(&session.skipped))?;
+        if !config.json {
+            println!(
+                "\nSkipped {} files, see {}",
+                session.skipped.len(),
+                report_path.display()
+            );
+        }
+    }
+
+    Ok(Outcome::Tagged {
+        total,
+        tagged,
+        skipped: session.skipped.len(),
+    })
+}
+
+/// Rename the `files` according to `config.filename_template`, based on their current tags, as
+/// `rename` does for the library. The tags themselves are left alone, and there are no prompts.
+/// With `config.dry_run`, the renames are only printed.
+fn rename_only(config: &Config, files: &[PathBuf]) -> types::OutcomeResult {
+    let mut renamed = 0;
+    for file in files {
+        if let Some(to) = rename::target_for(config, file) {
+            println!("  {}\n> {}", file.display(), to.display());
+            if config.dry_run || rename::rename_file(file, &to)? {
+                renamed += 1;
+            }
+        }
+    }
+    if config.dry_run {
+        println!("\n{} files would be renamed", renamed);
+    } else {
+        println!("\n{} files renamed", renamed);
+    }
+    Ok(Outcome::Done)
+}
+
+/// Tag a single file without prompting (as with `auto_tag`), e.g. right before depositing it. When
+/// the file cannot be tagged, the error is reported and the file is left as is.
+///
+/// # Returns
+/// The path of the file, which changes when the file was renamed
+pub fn auto_tag_file(config: &Config, entry: &PathBuf, extractor: &TagExtractor) -> PathBuf {
+    match tag_file(
+        config,
+        entry,
+        extractor,
+        true,
+        None,
+        &mut std::io::empty(),
+        &mut Session::default(),
+    ) {
+        Ok(Some(filename)) => entry.with_file_name(filename),
+        Ok(None) => entry.clone(),
+        Err(e) => {
+            eprintln!("! Could not tag {}: {}", entry.display(), e);
+            entry.clone()
+        }
+    }
+}
+
+/// Treat each folder of `files` as a single album, for `config.album_from_folder`. The album is the
+/// most common 'album' tag among the files in the folder, or else the name of the folder. The
+/// track number is the position of the file in its folder, sorted by filename. Files that cannot
+/// be tagged are left out.
+///
+/// # Returns
+/// The album and track number for each file
+fn folder_albums(files: &[PathBuf]) -> HashMap<PathBuf, (String, u16)> {
+    let mut folders: BTreeMap<&Path, Vec<(&PathBuf, Option<String>)>> = BTreeMap::new();
+    for file in files {
+        if let Ok(ftag) = Tag::new().read_from_path(file) {
+            let album = ftag.album_title().map(|a| a.trim().to_string());
+            let folder = file.parent().unwrap_or(Path::new(""));
+            folders.entry(folder).or_default().push((file, album));
+        }
+    }
+
+    let mut albums = HashMap::new();
+    for (folder, mut files) in folders {
+        files.sort();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for album in files.iter().filter_map(|(_, album)| album.as_deref()) {
+            if !album.is_empty() {
+                *counts.entry(album).or_default() += 1;
+            }
+        }
+        // The most common album, or the first alphabetically when tied
+        let album = match counts
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+        {
+            Some((album, _)) => String::from(album),
+            None => folder
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+        };
+
+        for (i, (file, _)) in files.iter().enumerate() {
+            albums.insert(PathBuf::from(file), (album.clone(), i as u16 + 1));
+        }
+    }
+    albums
+}
+
+/// Find a `.lrc` or `.txt` file with the same stem next to `entry`, to embed as its lyrics.
+///
+/// # Returns
+/// - `Ok(None)`: if there is no sidecar
+/// - `Ok(Some)`: the (trimmed) contents of the sidecar
+/// - `Err`: the reason to not embed the sidecar, e.g. as it is too large or not a text file
+fn lyrics_for(entry: &Path) -> Result<Option<String>, String> {
+    let sidecar = ["lrc", "txt"]
+        .map(|ext| entry.with_extension(ext))
+        .into_iter()
+        .find(|sidecar| sidecar.is_file());
+    let sidecar = match sidecar {
+        Some(sidecar) => sidecar,
+        None => return Ok(None),
+    };
+    let name = sidecar.file_name().unwrap().to_string_lossy();
+
+    if !["mp3", "flac"].contains(&extension(entry).as_str()) {
+        return Err(String::from("only supported for mp3 and flac files"));
+    }
+    let size = fs::metadata(&sidecar).map_err(|e| e.to_string())?.len();
+    if size > MAX_LYRICS_SIZE {
+        return Err(format!("'{}' is too large ({} bytes)", name, size));
+    }
+    let contents = fs::read(&sidecar).map_err(|e| e.to_string())?;
+    match String::from_utf8(contents) {
+        Ok(lyrics) if !lyrics.contains('\0') => {
+            Ok(Some(lyrics.trim().to_string()).filter(|l| !l.is_empty()))
+        }
+        _ => Err(format!("'{}' is not a text file", name)),
+    }
+}
+
+/// The lowercase extension of `entry`, or an empty string if there is none.
+fn extension(entry: &Path) -> String {
+    entry
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Read the embedded lyrics of an mp3 (USLT frame) or flac (LYRICS comment) file.
+fn read_lyrics(entry: &Path) -> Option<String> {
+    match extension(entry).as_str() {
+        "mp3" => {
+            let tag = id3::Tag::read_from_path(entry).ok()?;
+            let lyrics = tag.lyrics().next().map(|l| l.text.clone());
+            lyrics
+        }
+        "flac" => {
+            let tag = metaflac::Tag::read_from_path(entry).ok()?;
+            let lyrics = tag.get_vorbis("LYRICS")?.next().map(String::from);
+            lyrics
+        }
+        _ => None,
+    }
+}
 
-        let ftag = Tag::new().read_from_path(entry);
-        if let Err(e) = ftag {
-            println!("! {}, skipping", e);
-            continue;
+/// Read the grouping of an mp3 (TIT1 frame) or flac (GROUPING comment) file.
+fn read_grouping(entry: &Path) -> Option<String> {
+    match extension(entry).as_str() {
+        "mp3" => {
+            let tag = id3::Tag::read_from_path(entry).ok()?;
+            let grouping = tag.get("TIT1")?.content().text().map(String::from);
+            grouping
+        }
+        "flac" => {
+            let tag = metaflac::Tag::read_from_path(entry).ok()?;
+            let grouping = tag.get_vorbis("GROUPING")?.next().map(String::from);
+            grouping
+        }
+        _ => None,
+    }
+}
+
+/// Write the `grouping` into an mp3 (TIT1 frame) or flac (GROUPING comment) file, replacing any.
+/// Other files are left alone, so the other tags are still written.
+fn write_grouping(config: &Config, entry: &Path, grouping: &str) -> types::UnitResult {
+    match extension(entry).as_str() {
+        "mp3" => {
+            let mut tag = id3::Tag::read_from_path(entry)?;
+            tag.set_text("TIT1", grouping);
+            tag.write_to_path(entry, tag.version())?;
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(entry)?;
+            tag.set_vorbis("GROUPING", vec![grouping]);
+            tag.save()?;
+        }
+        _ => note(
+            config,
+            "Not writing GROUPING: only supported for mp3 and flac files",
+        ),
+    }
+    Ok(())
+}
+
+/// Embed the `lyrics` into an mp3 (USLT frame) or flac (LYRICS comment) file, replacing any.
+fn write_lyrics(entry: &Path, lyrics: &str) -> types::UnitResult {
+    match extension(entry).as_str() {
+        "mp3" => {
+            let mut tag = id3::Tag::read_from_path(entry)?;
+            tag.remove_all_lyrics();
+            tag.add_frame(id3::frame::Lyrics {
+                lang: String::from("eng"),
+                description: String::new(),
+                text: String::from(lyrics),
+            });
+            tag.write_to_path(entry, tag.version())?;
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(entry)?;
+            tag.set_vorbis("LYRICS", vec![lyrics]);
+            tag.save()?;
+        }
+        _ => return Err("Embedding lyrics is only supported for mp3 and flac files".into()),
+    }
+    Ok(())
+}
+
+/// Whether `name` matches any of `config.junk_filename_patterns`, i.e. it carries no information
+/// to extract tags from, such as `videoplayback` or `download (1)`.
+fn is_junk(config: &Config, name: &str) -> bool {
+    config
+        .junk_filename_patterns
+        .iter()
+        .any(|pattern| pattern.is_match(name.trim()))
+}
+
+/// Propose tags for a single file, returning the new filename when the file was renamed.
+/// With `auto_tag` (or once the user chose to accept all proposals of the `session`), the
+/// proposal is accepted without prompting. The `folder_album` (see
+/// `folder_albums`) overrides the album, and sets the track number when there is none. Skipped
+/// files are added to the `session`, and its persisted tags are applied to the proposal.
+fn tag_file<R: BufRead>(
+    config: &Config,
+    entry: &PathBuf,
+    extractor: &TagExtractor,
+    auto_tag: bool,
+    folder_album: Option<&(String, u16)>,
+    reader: &mut R,
+    session: &mut Session,
+) -> Result<Option<String>, TapewormError> {
+    let ftag = Tag::new().read_from_path(entry);
+    if let Err(e) = ftag {
+        skip(config, &e.to_string(), entry, &mut session.skipped);
+        return Ok(None);
+    }
+    let ftag = ftag.unwrap();
+
+    if let Some(reason) = util::check_duration(config, util::duration_of(entry, ftag.as_ref())) {
+        skip(config, &reason, entry, &mut session.skipped);
+        return Ok(None);
+    }
+
+    let mut proposal = if config.from_path {
+        // The input directory holding the file, as the input directories may be nested
+        let root = config
+            .input_dirs
+            .iter()
+            .filter(|dir| entry.starts_with(dir))
+            .max_by_key(|dir| dir.components().count())
+            .unwrap_or(&config.input_dirs[0]);
+        extractor.build_tags_from_path(root, entry)
+    } else {
+        let stem = entry.file_stem().unwrap_or_default().to_string_lossy();
+        let title = ftag.title().map(str::trim).filter(|t| !t.is_empty());
+        if is_junk(config, &stem) && title.is_none_or(|t| is_junk(config, t)) {
+            skip(
+                config,
+                "Junk filename, needs manual tagging",
+                entry,
+                &mut session.skipped,
+            );
+            return Ok(None);
         }
-        let ftag = ftag.unwrap();
 
         let title = if let Some(title) = ftag.title() {
             title.trim()
         } else {
-            println!("! No 'title' tag present, skipping");
-            continue;
+            skip(
+                config,
+                "No 'title' tag present",
+                entry,
+                &mut session.skipped,
+            );
+            return Ok(None);
         };
 
         if title.is_empty() {
-            println!("! Empty 'title' tag, skipping");
-            continue;
+            skip(config, "Empty 'title' tag", entry, &mut session.skipped);
+            return Ok(None);
         }
 
-        let mut proposal = extractor.build_tags(title);
-        if !config.override_artist {
-            if let Some(old_artist) = ftag.artist() {
-                proposal.feature(extractor.separate(old_artist)); // Keep the old artist(s)
-            }
+        extractor.build_tags(title)
+    };
+    if !config.override_artist && !config.from_path {
+        if let Some(old_artist) = ftag.artist() {
+            proposal.feature(extractor.separate(old_artist)); // Keep the old artist(s)
+        }
+    } else if config.uploader_as_artist && proposal.all_artists.as_ref().is_none_or(Vec::is_empty) {
+        // No artist was extracted, so use the artist set by yt-dlp, i.e. the uploader/channel
+        if let Some(uploader) = ftag.artist().map(str::trim).filter(|a| !a.is_empty()) {
+            proposal.feature(vec![String::from(uploader)]);
+        }
+    }
+    if let Some((album, track)) = folder_album {
+        proposal.album = Some(album.clone());
+        if proposal.track.is_none() && ftag.track_number().is_none() {
+            proposal.track = Some(*track);
+        }
+    }
+    for (tag_name, tag_value) in config.set_tags.iter().chain(&session.persisted) {
+        proposal.set(tag_name, tag_value.clone());
+    }
+    if config.embed_lyrics {
+        match lyrics_for(entry) {
+            Ok(lyrics) => proposal.lyrics = lyrics,
+            Err(reason) => note(config, &format!("Not embedding lyrics: {}", reason)),
         }
+    }
 
-        loop {
-            proposal.update(&config.title_template, &config.filename_template);
+    loop {
+        proposal.update(config);
+        if config.json {
+            println!("{}", proposal.to_json(&ftag, entry));
+        } else {
             proposal.present(&ftag, entry);
+        }
 
-            if config.auto_tag {
-                if let Err(e) = proposal.accept(ftag, entry) {
-                    println!("! Could not write tag or filename: {}, skipping", e);
-                }
-                break;
+        if config.dry_run {
+            if !config.json {
+                println!("(dry run, not written)");
             }
+            return Ok(None);
+        }
+        if config.json && !auto_tag {
+            return Ok(None); // Only write when auto tagging, as there is no prompt
+        }
 
-            match util::select("Accept?", vec![Yes, No, Edit], Yes, &mut reader) {
-                Ok(Edit) => proposal.edit(&mut reader)?,
-                Ok(Yes) => {
-                    if let Err(e) = proposal.accept(ftag, entry) {
-                        println!("! Could not write tag or filename: {}, skipping", e);
-                    }
-                    break;
+        if !auto_tag && !session.accept_all {
+            let options = vec![Yes, No, Edit, YesToAll];
+            match util::select("Accept?", options, Yes, &mut *reader) {
+                Ok(Edit) => {
+                    let edited = proposal.edit(&mut *reader)?;
+                    persist(&proposal, &edited, session, &mut *reader)?;
+                    continue;
                 }
-                _ => break, // Don't write changes on Err(_) or Ok(No)
+                Ok(Yes) => {}
+                Ok(YesToAll) => session.accept_all = true,
+                _ => return Ok(None), // Don't write changes on Err(_) or Ok(No)
             }
         }
-    }
 
-    Ok(())
+        if !proposal.changes(&ftag, entry) {
+            // Don't rewrite the file, which would needlessly touch its modification time
+            if !config.json {
+                println!("No changes");
+            }
+            return Ok(None);
+        }
+
+        return match proposal.accept(config, ftag, entry) {
+            Ok(to) => Ok(to.file_name().map(|f| f.to_string_lossy().into_owned())),
+            Err(e) => {
+                skip(
+                    config,
+                    &format!("Could not write tag or filename: {}", e),
+                    entry,
+                    &mut session.skipped,
+                );
+                Ok(None)
+            }
+        };
+    }
 }
 
 #[cfg(test)]
@@ -563,6 +1476,20 @@ mod tests {
         check(&r, "Band ｜ Song", song!("Band", "Song"));
     }
 
+    #[test]
+    fn separates_on_extended_separators() {
+        let r = TagExtractor::new(true);
+        assert_eq!(r.separate("A vs B"), vec!["A vs B"]);
+        assert_eq!(r.separate("A presents B"), vec!["A presents B"]);
+
+        let r = TagExtractor::new(true).with_extended_separators();
+        assert_eq!(r.separate("A vs B"), vec!["A", "B"]);
+        assert_eq!(r.separate("A vs. B"), vec!["A", "B"]);
+        assert_eq!(r.separate("A presents B"), vec!["A", "B"]);
+        assert_eq!(r.separate("A pres. B & C"), vec!["A", "B", "C"]);
+        assert_eq!(r.separate("Withered Hand"), vec!["Withered Hand"]);
+    }
+
     #[test]
     fn parses_featuring_artists() {
         let r = TagExtractor::new(true);
@@ -594,6 +1521,10 @@ mod tests {
     fn parses_track_number() {
         let r = TagExtractor::new(true);
         check(&r, "04. Band - Song", track!(4, "Band", "Song"));
+
+        let r = TagExtractor::new(true).with_kept_track();
+        check(&r, "04. Band - Song", track!(4, "Band", "04. Song"));
+        check(&r, "Band - Song", song!("Band", "Song"));
     }
 
     #[test]
@@ -614,6 +1545,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parses_version() {
+        let keywords = ["live", "acoustic"].map(String::from);
+        let r = TagExtractor::new(true).with_versions(&keywords);
+        let mut expected = song!("Band", "Song");
+        expected.version = Some(String::from("Live"));
+        check(&r, "Band - Song (Live)", expected);
+
+        let mut expected = song!("Band", "Song");
+        expected.version = Some(String::from("Acoustic Version"));
+        check(&r, "Band - Song [Acoustic Version]", expected);
+
+        // Not a version when the keyword is not configured, or only part of a word
+        check(&r, "Band - Song (Demo)", song!("Band", "Song (Demo)"));
+        check(&r, "Band - Song (Alive)", song!("Band", "Song (Alive)"));
+    }
+
     #[test]
     fn strips_useless_info() {
         let r = TagExtractor::new(true);
@@ -645,10 +1593,106 @@ mod tests {
         check(&r, "A - Title (F/C Vibes)", album!("Vibes", "A", "Title"));
     }
 
+    #[test]
+    fn builds_tags_from_path() {
+        let r = TagExtractor::new(true);
+        let root = PathBuf::from("in");
+        let inputs = [
+            ("in/Band/Album/Song.mp3", album!("Album", "Band", "Song")),
+            (
+                "in/Band/Album/Band - Song.mp3",
+                album!("Album", "Band", "Song"),
+            ),
+            (
+                "in/Band/Album/Song ft. Singer.mp3",
+                album!("Album", "Band;Singer", "Song"),
+            ),
+            (
+                "in/Label/Band/Album/Song.mp3",
+                album!("Album", "Band", "Song"),
+            ),
+            (
+                "in/Album/Song.mp3",
+                TagProposal {
+                    album: Some(String::from("Album")),
+                    title: Some(String::from("Song")),
+                    ..Default::default()
+                },
+            ),
+            (
+                "in/Song.mp3",
+                TagProposal {
+                    title: Some(String::from("Song")),
+                    ..Default::default()
+                },
+            ),
+            (
+                "elsewhere/Band/Album/Song.mp3",
+                TagProposal {
+                    title: Some(String::from("Song")),
+                    ..Default::default()
+                },
+            ),
+        ];
+        for (input, expected) in inputs {
+            assert_eq!(
+                r.build_tags_from_path(&root, &PathBuf::from(input)),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn parses_label() {
+        let r = TagExtractor::new(true).with_label();
+        let inputs = [
+            ("Band - Song [Monstercat MCS123]", "Monstercat MCS123"),
+            ("Band - Song [ANJ-123]", "ANJ-123"),
+            (
+                "Band - Song (Spinnin' Records SP1234)",
+                "Spinnin' Records SP1234",
+            ),
+        ];
+        for (input_str, expected_output) in inputs {
+            let mut expected = song!("Band", "Song");
+            expected.label = Some(String::from(expected_output));
+            check(&r, input_str, expected);
+        }
+    }
+
+    #[test]
+    fn parses_work() {
+        let r = TagExtractor::new(true).with_work();
+        let mut expected = song!("Composer", "I. Allegro con brio");
+        expected.grouping = Some(String::from("Symphony No. 5 in C minor, Op. 67"));
+        check(
+            &r,
+            "Composer - Symphony No. 5 in C minor, Op. 67: I. Allegro con brio",
+            expected,
+        );
+        check(&r, "Band - Song", song!("Band", "Song"));
+
+        let r = TagExtractor::new(true);
+        check(
+            &r,
+            "Composer - Work: Movement",
+            song!("Composer", "Work: Movement"),
+        );
+    }
+
+    #[test]
+    fn keeps_label_when_not_enabled() {
+        let r = TagExtractor::new(true);
+        check(&r, "Band - Song [ANJ-123]", song!("Band", "Song [ANJ-123]"));
+    }
+
     #[test]
     fn generates_filename_from_template() {
-        let title_template = String::from("{title} ({feat}) [{remix}]");
-        let filename_template = String::from("{artist} - {title}");
+        let mut config = Config::default();
+        config.title_template = String::from("{title} ({feat}) [{remix}]");
+        config.filename_template = String::from("{artist} - {title}");
+        config.feat_separator = String::from(", ");
+        config.feat_last_separator = String::from(" & ");
 
         let inputs = [
             (TagProposal::default(), "-"),
@@ -658,8 +1702,169 @@ mod tests {
             (rmx!("A;B", "Song", "Edit"), "A - Song (B) [Edit]"),
         ];
         for (mut proposal, expected) in inputs {
-            proposal.update(&title_template, &filename_template);
+            proposal.update(&config);
             assert_eq!(proposal.filename, expected);
         }
     }
+
+    #[test]
+    fn joins_featured_artists() {
+        let mut config = Config::default();
+        config.title_template = String::from("{title} ({feat})");
+        config.feat_separator = String::from(", ");
+        config.feat_last_separator = String::from(" & ");
+
+        let mut proposal = song!("M;A;B;C", "Song");
+        proposal.update(&config);
+        assert_eq!(proposal.final_title, Some(String::from("Song (A, B & C)")));
+
+        config.feat_separator = String::from(" & ");
+        proposal.update(&config);
+        assert_eq!(proposal.final_title, Some(String::from("Song (A & B & C)")));
+
+        config.feat_separator = String::from(" x ");
+        config.feat_last_separator = String::from(" x ");
+        proposal.update(&config);
+        assert_eq!(proposal.final_title, Some(String::from("Song (A x B x C)")));
+    }
+
+    #[test]
+    fn detects_junk_filenames() {
+        let config = Config::default();
+        for name in [
+            "videoplayback",
+            "download (1)",
+            "Download",
+            "audio_01",
+            "1234567",
+        ] {
+            assert!(is_junk(&config, name), "{}", name);
+        }
+        for name in ["Artist - Song", "Downloaded", "Track Star - Audio", "1999"] {
+            assert!(!is_junk(&config, name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_unfit_lyrics_sidecars() {
+        let dir = std::env::temp_dir().join(format!("tapeworm-lyrics-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let entry = dir.join("Song.mp3");
+        assert_eq!(lyrics_for(&entry), Ok(None));
+
+        fs::write(dir.join("Song.txt"), "  Some lyrics\n").unwrap();
+        assert_eq!(lyrics_for(&entry), Ok(Some(String::from("Some lyrics"))));
+        assert!(lyrics_for(&dir.join("Song.ogg")).is_err());
+
+        fs::write(dir.join("Song.txt"), [0xff, 0x00, 0xfe]).unwrap();
+        assert!(lyrics_for(&entry).is_err());
+
+        fs::write(
+            dir.join("Song.txt"),
+            "a".repeat(MAX_LYRICS_SIZE as usize + 1),
+        )
+        .unwrap();
+        assert!(lyrics_for(&entry).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn detects_filename_collisions() {
+        let dir = std::env::temp_dir().join(format!("tapeworm-collide-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let entry = dir.join("song.mp3");
+        fs::write(&entry, "").unwrap();
+        let mut proposal = TagProposal::default();
+
+        proposal.filename = String::from("Song");
+        assert_eq!(proposal.collision(&entry), None);
+        proposal.filename = String::from("Band - Song");
+        assert_eq!(proposal.collision(&entry), None);
+        fs::write(dir.join("Band - Song.mp3"), "").unwrap();
+        assert_eq!(
+            proposal.collision(&entry),
+            Some(dir.join("Band - Song.mp3"))
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn groups_skips_by_reason() {
+        let skipped = [
+            (
+                PathBuf::from("a.mp3"),
+                String::from("No 'title' tag present"),
+            ),
+            (
+                PathBuf::from("b.jpg"),
+                String::from("Unknown file extension"),
+            ),
+            (
+                PathBuf::from("c.mp3"),
+                String::from("No 'title' tag present"),
+            ),
+        ];
+        let expected = "No 'title' tag present (2):\n  a.mp3\n  c.mp3\n\
+                        Unknown file extension (1):\n  b.jpg\n";
+        assert_eq!(skip_report(&skipped), expected);
+        assert_eq!(skip_report(&[]), "");
+    }
+
+    #[test]
+    fn renders_template() {
+        let config = Config::default();
+        let value =
+            |tag_name: &str, value: &str| (String::from(tag_name), Some(String::from(value)));
+
+        let values = [
+            value("ARTIST", "A"),
+            value("TITLE", "T"),
+            value("REMIX", "Edit"),
+        ];
+        let rendered = render_template(&config, "{artist} - {title} [{remix}]", &values);
+        assert_eq!(rendered.unwrap(), "A - T [Edit]");
+
+        let values = [
+            value("ARTIST", "A;B"),
+            value("FEAT", "C"),
+            value("TITLE", "T"),
+        ];
+        let rendered = render_template(&config, "{title} ({feat}) [{remix}]", &values);
+        assert_eq!(rendered.unwrap(), "T (B & C)");
+
+        assert!(render_template(&config, "{title}", &[value("MOOD", "happy")]).is_err());
+    }
+
+    #[test]
+    fn infers_album_artist() {
+        let mut config = Config::default();
+
+        let mut proposal = album!("Album", "A;B", "Song");
+        proposal.update(&config);
+        assert_eq!(proposal.album_artist, None);
+
+        config.infer_album_artist = true;
+        proposal.update(&config);
+        assert_eq!(proposal.album_artist, Some(String::from("A")));
+
+        let mut proposal = song!("A", "Song"); // No album, so no album artist
+        proposal.update(&config);
+        assert_eq!(proposal.album_artist, None);
+    }
+
+    #[test]
+    fn sets_disc_and_comment() {
+        let mut proposal = TagProposal::default();
+        proposal.set("DISC", Some(String::from("2/3")));
+        assert_eq!(proposal.disc, Some(2));
+        proposal.set("DISC", Some(String::from("two")));
+        assert_eq!(proposal.disc, Some(2)); // Invalid input is ignored
+        assert_eq!(proposal.get("DISC"), Some(String::from("2")));
+
+        proposal.set("COMMENT", Some(String::from("Note")));
+        assert_eq!(proposal.label, Some(String::from("Note")));
+        assert_eq!(proposal.get("LABEL"), proposal.get("COMMENT"));
+    }
 }
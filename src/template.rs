@@ -0,0 +1,13 @@
+//! Try out a title or filename template, without touching any files.
+
+use crate::{tag, types, Config};
+
+/// Print the template rendered for the given tag values, see `tag::render_template`.
+pub fn run(config: &Config) -> types::UnitResult {
+    let template = config.template.as_ref().unwrap();
+    println!(
+        "{}",
+        tag::render_template(config, template, &config.set_tags)?
+    );
+    Ok(())
+}
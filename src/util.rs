@@ -1,8 +1,13 @@
-use crate::types;
+use crate::{types, Config, TapewormError};
+use audiotags::AudioTag;
+use chrono::Local;
+use regex::Regex;
+use std::collections::HashSet;
 use std::fs;
 use std::io::{BufRead, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::SystemTime;
 
 #[derive(PartialEq)]
 pub enum PromptOption {
@@ -95,6 +100,77 @@ pub fn select<R: BufRead>(
     }
 }
 
+/// The encoding of text files that tapeworm reads, such as the input file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// ISO-8859-1, as written by some older (Windows) tools
+    Latin1,
+}
+
+impl Encoding {
+    pub fn from(s: &str) -> Result<Self, TapewormError> {
+        match s.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Self::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Self::Latin1),
+            _ => Err(format!("Invalid encoding: '{}'. See 'help'", s).into()),
+        }
+    }
+}
+
+/// Read the text file at `path` in the given `encoding`.
+///
+/// # Errors
+/// - If the file cannot be read, or is not valid UTF-8 when the `encoding` is `Encoding::Utf8`
+pub fn read_text_file<P: AsRef<Path>>(path: P, encoding: Encoding) -> types::StringResult {
+    match encoding {
+        Encoding::Utf8 => Ok(fs::read_to_string(path)?),
+        // Each Latin-1 byte is the Unicode code point of the same value
+        Encoding::Latin1 => Ok(fs::read(path)?.into_iter().map(char::from).collect()),
+    }
+}
+
+/// Overwrite the text file at `path` with the `content`, encoded in the given `encoding`, so a file
+/// read with `read_text_file` keeps its encoding.
+///
+/// # Errors
+/// - If the `content` cannot be encoded, e.g. a character beyond ISO-8859-1 for `Encoding::Latin1`
+/// - If the file cannot be written
+pub fn write_text_file<P: AsRef<Path>>(
+    path: P,
+    content: String,
+    encoding: Encoding,
+) -> types::UnitResult {
+    Ok(fs::write(path, encode(content, encoding)?)?)
+}
+
+/// Append the `content` to the text file at `path`, encoded as with `write_text_file`.
+pub fn append_text_file<P: AsRef<Path>>(
+    path: P,
+    content: String,
+    encoding: Encoding,
+) -> types::UnitResult {
+    Ok(fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&encode(content, encoding)?)?)
+}
+
+fn encode(content: String, encoding: Encoding) -> Result<Vec<u8>, TapewormError> {
+    match encoding {
+        Encoding::Utf8 => Ok(content.into_bytes()),
+        Encoding::Latin1 => content
+            .chars()
+            .map(|c| {
+                u8::try_from(c)
+                    .map_err(|_| format!("Cannot write '{}' as latin1. See ENCODING", c).into())
+            })
+            .collect(),
+    }
+}
+
 /// Append the `content` to the file at `path`
 pub fn append<P: AsRef<Path>>(path: P, content: String) -> types::UnitResult {
     Ok(fs::OpenOptions::new()
@@ -128,6 +204,17 @@ pub fn guarantee_dir_path(dir: PathBuf) -> types::PathBufResult {
     Ok(dir)
 }
 
+/// # Returns
+/// - `Err`: if any of the `dirs` does not exist
+/// - `Vec<PathBuf>`: a list of files present in all `dirs`, may be empty
+pub fn filepaths_in_all(dirs: &[PathBuf]) -> types::VecPathBufResult {
+    let mut files = Vec::new();
+    for dir in dirs {
+        files.extend(filepaths_in(dir)?);
+    }
+    Ok(files)
+}
+
 /// # Returns
 /// - `Err`: if the `dir` path does not exist
 /// - `Vec<PathBuf>`: a list of files present, may be empty
@@ -141,12 +228,214 @@ pub fn filepaths_in(dir: &PathBuf) -> types::VecPathBufResult {
         .collect())
 }
 
+/// # Returns
+/// - `Err`: if the `dir` path does not exist
+/// - `Vec<PathBuf>`: a sorted list of files present in `dir` and its subfolders (except for
+///   folders named `exclude`, e.g. the library config folder), may be empty
+pub fn filepaths_in_recursive(dir: &PathBuf, exclude: &str) -> types::VecPathBufResult {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)?.filter_map(|e| e.ok()) {
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if entry.file_name() != exclude {
+                files.append(&mut filepaths_in_recursive(&entry.path(), exclude)?);
+            }
+        } else if file_type.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// # Returns
+/// The modification time of `file` with `config.preserve_mtime`, to be restored by `restore_mtime`
+/// after writing to or moving the file.
+pub fn snapshot_mtime(config: &Config, file: &Path) -> Option<SystemTime> {
+    if !config.preserve_mtime {
+        return None;
+    }
+    fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+/// Set the modification time of `file` back to the `mtime` taken by `snapshot_mtime`, if any.
+pub fn restore_mtime(file: &Path, mtime: Option<SystemTime>) -> types::UnitResult {
+    if let Some(mtime) = mtime {
+        fs::File::options()
+            .write(true)
+            .open(file)?
+            .set_modified(mtime)?;
+    }
+    Ok(())
+}
+
+/// With `config.max_files`, keep only the first (sorted by path) that many `files`, e.g. to try out
+/// a config on a sample of a big folder.
+pub fn limit_files(config: &Config, files: &mut Vec<PathBuf>) {
+    if let Some(max) = config.max_files.filter(|max| *max < files.len()) {
+        files.sort();
+        println!("Processing {} of {} files (limited)", max, files.len());
+        files.truncate(max);
+    }
+}
+
+/// Whether to apply an `auto` option (e.g. `config.auto_tag`) to a batch of `count` items. Beyond
+/// `config.confirm_threshold` items (0 means no threshold), tapeworm falls back to prompting unless
+/// `config.force_auto` is set, so e.g. a scrape gone wrong does not flood the library unattended.
+/// When stdin is not a terminal, nobody can answer these prompts, so the threshold decides on its
+/// own and the batch is left alone.
+///
+/// # Returns
+/// - `None`: if the batch is to be left alone
+/// - `bool`: whether to apply the `auto` option
+pub fn auto_for(config: &Config, auto: bool, count: usize) -> Option<bool> {
+    let threshold = config.confirm_threshold;
+    if !auto || config.force_auto || threshold == 0 || count <= threshold {
+        return Some(auto);
+    }
+    let msg = format!("{} items exceed CONFIRM_THRESHOLD ({})", count, threshold);
+    if !config.interactive {
+        println!(
+            "{}, but stdin is not a terminal; skipping (use --force-auto to apply anyway)",
+            msg
+        );
+        return None;
+    }
+    println!(
+        "{}, asking for confirmation (use --force-auto to skip)",
+        msg
+    );
+    Some(false)
+}
+
+/// Copy `files` into `.tapeworm/backup/<timestamp>/` of the library, preserving their structure
+/// relative to the library. Files outside the library are copied by name only.
+/// Files already present in the backup folder are not overwritten, keeping the oldest copy.
+/// Folders (e.g. the empty folders `clean` removes) are recreated without their contents.
+///
+/// # Returns
+/// - `Err`: if a file could not be copied
+/// - `PathBuf`: the backup folder
+pub fn backup(files: &[PathBuf], config: &Config) -> types::PathBufResult {
+    let lib_path = config.lib_path.as_ref().unwrap();
+    let timestamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let backup_dir = lib_path
+        .join(&config.lib_dir)
+        .join("backup")
+        .join(timestamp);
+
+    for file in files {
+        let relative = match file.strip_prefix(lib_path) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => PathBuf::from(file.file_name().unwrap()),
+        };
+        let dest = backup_dir.join(relative);
+        if file.is_dir() {
+            fs::create_dir_all(&dest)?;
+            continue;
+        }
+        if fs::metadata(&dest).is_ok() {
+            continue;
+        }
+        fs::create_dir_all(dest.parent().unwrap())?;
+        fs::copy(file, &dest)?;
+    }
+
+    Ok(backup_dir)
+}
+
+/// Move `file` into `.tapeworm/trash/` of the library, instead of deleting it outright. When the
+/// trash already holds a file by that name, the moved file is prefixed with a timestamp.
+///
+/// # Returns
+/// - `Err`: if the file could not be moved
+/// - `PathBuf`: the new location of the file
+pub fn trash(file: &Path, config: &Config) -> types::PathBufResult {
+    let trash_dir = config
+        .lib_path
+        .as_ref()
+        .unwrap()
+        .join(&config.lib_dir)
+        .join("trash");
+    fs::create_dir_all(&trash_dir)?;
+
+    let filename = file.file_name().unwrap().to_string_lossy();
+    let mut dest = trash_dir.join(filename.as_ref());
+    if fs::metadata(&dest).is_ok() {
+        let timestamp = Local::now().format("%Y%m%d-%H%M%S");
+        dest = trash_dir.join(format!("{}-{}", timestamp, filename));
+    }
+    if fs::rename(file, &dest).is_err() {
+        // E.g. when the file is on another file system
+        fs::copy(file, &dest)?;
+        fs::remove_file(file)?;
+    }
+
+    Ok(dest)
+}
+
+/// # Returns
+/// The first term of `config.blocklist` that `s` contains (ignoring case), if any.
+pub fn blocked<'a>(config: &'a Config, s: &str) -> Option<&'a String> {
+    let s = s.to_lowercase();
+    config
+        .blocklist
+        .iter()
+        .find(|term| s.contains(term.as_str()))
+}
+
+/// Check whether files can be created in `dir`, by creating and removing a probe file.
+///
+/// # Returns
+/// - `false` if `dir` does not exist, or the probe file could not be created or removed
+/// - `true` otherwise
+pub fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".tapeworm-probe-{}", std::process::id()));
+    if fs::File::create(&probe).is_err() {
+        return false;
+    }
+    fs::remove_file(&probe).is_ok()
+}
+
+/// Whether `s` fully matches the glob `pattern`, in which `*` matches any sequence of characters
+/// and `?` matches any single character.
+pub fn glob_matches(pattern: &str, s: &str) -> bool {
+    let regex = pattern
+        .chars()
+        .map(|c| match c {
+            '*' => String::from(".*"),
+            '?' => String::from("."),
+            c => regex::escape(&c.to_string()),
+        })
+        .collect::<String>();
+    Regex::new(&format!("^{}$", regex)).is_ok_and(|r| r.is_match(s))
+}
+
+/// Split `s` into its lowercase words, ignoring punctuation.
+pub fn words(s: &str) -> HashSet<String> {
+    s.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// The share of words that `a` and `b` have in common (the Sørensen–Dice coefficient), ignoring
+/// case and punctuation, from 0.0 (no words in common) to 1.0 (the same words).
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (words(a), words(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    2.0 * a.intersection(&b).count() as f64 / (a.len() + b.len()) as f64
+}
+
 /// Parse a `Option<String>` into an `Option<F>`.
 ///
 /// # Returns
 /// - `Err` if parsing failed
 /// - `Option<F>` on success
-pub fn parse<F: FromStr>(value: Option<String>) -> Result<Option<F>, Box<dyn std::error::Error>> {
+pub fn parse<F: FromStr>(value: Option<String>) -> Result<Option<F>, TapewormError> {
     if let Some(value) = value {
         if let Ok(value) = value.parse::<F>() {
             Ok(Some(value))
@@ -158,6 +447,85 @@ pub fn parse<F: FromStr>(value: Option<String>) -> Result<Option<F>, Box<dyn std
     }
 }
 
+/// Leniently parse a track number, e.g. "05/12", "Track 4" or "4.".
+///
+/// Any trailing "/total" is dropped, after which the first run of digits is parsed.
+///
+/// # Returns
+/// - `Err` if no track number could be found
+/// - `Option<u16>` on success
+pub fn parse_track(value: Option<String>) -> Result<Option<u16>, TapewormError> {
+    if let Some(value) = value {
+        let number = value.split('/').next().unwrap();
+        let digits: String = number
+            .chars()
+            .skip_while(|c| !c.is_ascii_digit())
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        parse::<u16>(Some(digits)).map_err(|_| format!("Invalid track: {}", value).into())
+    } else {
+        Ok(None)
+    }
+}
+
+/// Leniently parse a year, extracting the first 4-digit number, e.g. from "2024-03-01" or "2024.".
+///
+/// # Returns
+/// - `Err` if no year could be found
+/// - `Option<i32>` on success
+pub fn parse_year(value: Option<String>) -> Result<Option<i32>, TapewormError> {
+    if let Some(value) = value {
+        let year = Regex::new(r"(?:^|\D)(\d{4})(?:\D|$)")
+            .unwrap()
+            .captures(&value)
+            .map(|c| c[1].to_string());
+        if let Some(year) = year {
+            parse::<i32>(Some(year))
+        } else {
+            Err(format!("Invalid year: {}", value).into())
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+/// # Returns
+/// The duration of the audio `file` in seconds, as read from its tag `ftag`, if known. For mp3
+/// files, this is the ID3 `TLEN` frame, which is in milliseconds.
+pub fn duration_of(file: &Path, ftag: &dyn AudioTag) -> Option<f64> {
+    let duration = ftag.duration()?;
+    if file
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"))
+    {
+        Some(duration / 1000.0)
+    } else {
+        Some(duration)
+    }
+}
+
+/// Check whether the `duration` (in seconds) lies within the configured duration range.
+///
+/// # Returns
+/// - `None`: if it does, or if the duration is unknown
+/// - `Some(String)`: the reason why it does not
+pub fn check_duration(config: &Config, duration: Option<f64>) -> Option<String> {
+    let duration = duration?;
+    if let Some(min) = config.min_duration.filter(|min| duration < *min) {
+        Some(format!(
+            "Duration {:.0}s is below the minimum of {}s",
+            duration, min
+        ))
+    } else if let Some(max) = config.max_duration.filter(|max| duration > *max) {
+        Some(format!(
+            "Duration {:.0}s is above the maximum of {}s",
+            duration, max
+        ))
+    } else {
+        None
+    }
+}
+
 /// Remove a string in its entirety from another string.
 pub fn remove_str_from_string(s: String, to_remove: &str) -> String {
     String::from(s.split(to_remove).fold(String::new(), |a, s| a + s).trim())
@@ -245,6 +613,128 @@ pub fn remove_duplicate_whitespace(s: String) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn reads_latin1_files() {
+        let path = std::env::temp_dir().join(format!("tapeworm-latin1-{}", std::process::id()));
+        fs::write(&path, b"ytsearch:Beyonc\xe9 - D\xe9j\xe0 Vu\n").unwrap();
+
+        assert!(read_text_file(&path, Encoding::Utf8).is_err());
+        assert_eq!(
+            read_text_file(&path, Encoding::Latin1).unwrap(),
+            "ytsearch:Beyonc\u{e9} - D\u{e9}j\u{e0} Vu\n"
+        );
+        assert!(Encoding::from("cp1252").is_err());
+
+        // Rewriting keeps the encoding
+        let contents = read_text_file(&path, Encoding::Latin1).unwrap();
+        write_text_file(&path, contents, Encoding::Latin1).unwrap();
+        append_text_file(&path, String::from("ytsearch:\u{e9}\n"), Encoding::Latin1).unwrap();
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            b"ytsearch:Beyonc\xe9 - D\xe9j\xe0 Vu\nytsearch:\xe9\n"
+        );
+        assert!(append_text_file(&path, String::from("\u{2014}"), Encoding::Latin1).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn measures_similarity() {
+        assert_eq!(similarity("Artist - Song", "artist song"), 1.0);
+        assert_eq!(similarity("Artist - Song", "Other - Tune"), 0.0);
+        assert_eq!(similarity("Artist - Song", "artist other"), 0.5);
+        assert_eq!(similarity("", "Artist - Song"), 0.0);
+    }
+
+    #[test]
+    fn parses_track_leniently() {
+        let inputs = [
+            ("4", 4),
+            ("05/12", 5),
+            ("Track 4", 4),
+            ("4.", 4),
+            (" 07 ", 7),
+        ];
+        for (input, expected) in inputs {
+            assert_eq!(
+                parse_track(Some(String::from(input))).unwrap(),
+                Some(expected)
+            );
+        }
+        assert!(parse_track(None).unwrap().is_none());
+        assert!(parse_track(Some(String::from("four"))).is_err());
+    }
+
+    #[test]
+    fn parses_year_leniently() {
+        let inputs = [
+            ("2024", 2024),
+            ("2024.", 2024),
+            ("2024-03-01", 2024),
+            ("01/03/1999", 1999),
+        ];
+        for (input, expected) in inputs {
+            assert_eq!(
+                parse_year(Some(String::from(input))).unwrap(),
+                Some(expected)
+            );
+        }
+        assert!(parse_year(None).unwrap().is_none());
+        assert!(parse_year(Some(String::from("'99"))).is_err());
+        assert!(parse_year(Some(String::from("20245"))).is_err());
+    }
+
+    #[test]
+    fn checks_writable() {
+        let dir = std::env::temp_dir();
+        assert!(is_writable(&dir));
+        assert!(is_writable(&dir)); // The probe file was removed
+        assert!(!is_writable(&dir.join("tapeworm-does-not-exist")));
+    }
+
+    #[test]
+    fn matches_glob() {
+        assert!(glob_matches("music-*", "music-rock"));
+        assert!(glob_matches("music-*", "music-"));
+        assert!(glob_matches("lib?", "lib1"));
+        assert!(glob_matches("*.lib", "a.lib"));
+        assert!(!glob_matches("*.lib", "alib"));
+        assert!(!glob_matches("music-*", "old-music-rock"));
+        assert!(!glob_matches("lib?", "lib12"));
+    }
+
+    #[test]
+    fn checks_duration() {
+        let mut config = Config::default();
+        assert!(check_duration(&config, Some(30.0)).is_none());
+
+        config.min_duration = Some(60.0);
+        config.max_duration = Some(600.0);
+        assert!(check_duration(&config, None).is_none());
+        assert!(check_duration(&config, Some(60.0)).is_none());
+        assert!(check_duration(&config, Some(600.0)).is_none());
+        assert!(check_duration(&config, Some(59.9)).is_some());
+        assert!(check_duration(&config, Some(3600.0)).is_some());
+    }
+
+    #[test]
+    fn falls_back_from_auto_beyond_threshold() {
+        let mut config = Config::default();
+        assert_eq!(auto_for(&config, true, 1000), Some(true)); // No threshold by default
+
+        config.confirm_threshold = 10;
+        assert_eq!(auto_for(&config, true, 10), Some(true));
+        assert_eq!(auto_for(&config, true, 11), Some(false));
+        assert_eq!(auto_for(&config, false, 1), Some(false));
+
+        config.interactive = false;
+        assert_eq!(auto_for(&config, true, 10), Some(true));
+        assert_eq!(auto_for(&config, true, 11), None);
+
+        config.force_auto = true;
+        assert_eq!(auto_for(&config, true, 11), Some(true));
+    }
+
     #[test]
     fn removes_brackets() {
         let inputs = [
@@ -0,0 +1,122 @@
+//! Verify that audio files have the required tags.
+
+use crate::{types, util, Config, TapewormError};
+use audiotags::{AudioTag, Tag};
+
+/// The tags that can be required, see `required_tags`.
+const TAG_NAMES: [&str; 7] = [
+    "artist",
+    "title",
+    "album",
+    "album_artist",
+    "year",
+    "genre",
+    "track",
+];
+
+/// Check the audio files in the input directories (or with `config.verify_library`, the library
+/// and its subfolders, except `.tapeworm`) for the `config.required_tags`. Files that cannot be
+/// read are reported and skipped, as `tag` does.
+///
+/// # Errors
+/// - `TapewormError::Partial`: if any file misses a required tag
+pub fn run(config: &Config) -> types::UnitResult {
+    let mut files = if config.verify_library {
+        util::filepaths_in_recursive(config.lib_path.as_ref().unwrap(), &config.lib_dir)?
+    } else {
+        util::filepaths_in_all(&config.input_dirs)?
+    };
+    files.sort();
+
+    let mut checked = 0;
+    let mut failed = 0;
+    for file in files {
+        let ftag = match Tag::new().read_from_path(&file) {
+            Ok(ftag) => ftag,
+            Err(e) => {
+                println!("! {}: {}, skipping", file.display(), e);
+                continue;
+            }
+        };
+        checked += 1;
+
+        let missing = missing_tags(ftag.as_ref(), &config.required_tags);
+        if !missing.is_empty() {
+            println!("{}: missing {}", file.display(), missing.join(", "));
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(TapewormError::Partial(format!(
+            "{} of {} files miss required tags",
+            failed, checked
+        )));
+    }
+    println!("All {} files have the required tags", checked);
+    Ok(())
+}
+
+/// # Returns
+/// The `tags` required for `required_tags` in lib.conf: a comma-separated list of `TAG_NAMES`.
+///
+/// # Errors
+/// - If a tag is not one of `TAG_NAMES`
+pub fn parse_required_tags(tags: &str) -> types::VecStringResult {
+    let mut required = Vec::new();
+    for tag in tags.split(',').map(|t| t.trim().to_lowercase()) {
+        if tag.is_empty() {
+            continue;
+        }
+        if !TAG_NAMES.contains(&tag.as_str()) {
+            return Err(format!("Invalid required tag: '{}'. See 'help'", tag).into());
+        }
+        required.push(tag);
+    }
+    Ok(required)
+}
+
+/// # Returns
+/// The `required` tags that are missing (or empty) in `ftag`.
+fn missing_tags(ftag: &dyn AudioTag, required: &[String]) -> Vec<String> {
+    let has_text = |value: Option<&str>| value.is_some_and(|v| !v.trim().is_empty());
+    required
+        .iter()
+        .filter(|tag| match tag.as_str() {
+            "artist" => !has_text(ftag.artist()),
+            "title" => !has_text(ftag.title()),
+            "album" => !has_text(ftag.album_title()),
+            "album_artist" => !has_text(ftag.album_artist()),
+            "year" => ftag.year().is_none(),
+            "genre" => !has_text(ftag.genre()),
+            "track" => ftag.track_number().is_none(),
+            _ => false,
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn finds_missing_tags() {
+        assert_eq!(
+            parse_required_tags("artist, Title,,year").unwrap(),
+            vec!["artist", "title", "year"]
+        );
+        assert!(parse_required_tags("artist,mood").is_err());
+
+        let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test");
+        let required = parse_required_tags("title,year").unwrap();
+        let ftag = Tag::new()
+            .read_from_path(resources.join("no_title.mp3"))
+            .unwrap();
+        assert_eq!(
+            missing_tags(ftag.as_ref(), &required),
+            vec!["title", "year"]
+        );
+    }
+}
@@ -1,11 +1,12 @@
 //! Integration testing helper functions.
 
 use rand::distributions::{Alphanumeric, DistString};
+use std::cell::Cell;
 use std::collections::HashSet;
 use std::io::{self, BufRead, Write};
 use std::path::PathBuf;
 use std::{env, fs};
-use tapeworm::{Config, Downloader};
+use tapeworm::{Config, Downloader, TapewormError};
 
 /// Mocks yt-dlp by simply creating a file for each input.
 pub struct MockYtDlp;
@@ -15,8 +16,13 @@ impl Downloader for MockYtDlp {
         config: &Config,
         inputs: HashSet<String>,
         _reader: R,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let dest = config.lib_path.as_ref().unwrap().join(".tapeworm").join("in");
+    ) -> Result<(), TapewormError> {
+        let dest = config
+            .lib_path
+            .as_ref()
+            .unwrap()
+            .join(&config.lib_dir)
+            .join("in");
         for (i, input) in inputs.iter().map(|s| s.to_owned()).enumerate() {
             write(&dest.join(format!("{i}.txt")), input);
         }
@@ -24,6 +30,41 @@ impl Downloader for MockYtDlp {
     }
 }
 
+/// Mocks yt-dlp failing on inputs containing "fail", e.g. private videos.
+pub struct FailingYtDlp;
+impl Downloader for FailingYtDlp {
+    fn download<R: BufRead>(
+        &self,
+        config: &Config,
+        inputs: HashSet<String>,
+        reader: R,
+    ) -> Result<(), TapewormError> {
+        if inputs.iter().any(|input| input.contains("fail")) {
+            return Err("yt-dlp exited with exit status: 1".into());
+        }
+        MockYtDlp.download(config, inputs, reader)
+    }
+}
+
+/// Mocks yt-dlp failing on the first `failures` attempts, e.g. on a flaky connection.
+pub struct FlakyYtDlp {
+    pub failures: Cell<usize>,
+}
+impl Downloader for FlakyYtDlp {
+    fn download<R: BufRead>(
+        &self,
+        config: &Config,
+        inputs: HashSet<String>,
+        reader: R,
+    ) -> Result<(), TapewormError> {
+        if self.failures.get() > 0 {
+            self.failures.set(self.failures.get() - 1);
+            return Err("yt-dlp exited with exit status: 1".into());
+        }
+        MockYtDlp.download(config, inputs, reader)
+    }
+}
+
 pub struct Library {
     /// The relative base library directory name
     pub name: String,
@@ -51,7 +92,7 @@ impl Library {
         let name = Library::generate_name();
         let base_dir = env::current_dir().unwrap().join(&name);
         assert!(fs::metadata(&base_dir).is_err());
-        let cfg_dir = base_dir.join(".tapeworm");
+        let cfg_dir = base_dir.join(tapeworm::lib_dir_name());
 
         Self {
             output_dir: cfg_dir.join("out"),
@@ -121,18 +162,18 @@ impl Library {
 ///
 /// # Returns
 /// - `Result<Config>`: the built Config or an error
-pub fn build(mut args: Vec<&str>) -> Result<Config, Box<dyn std::error::Error>> {
+pub fn build(mut args: Vec<&str>) -> Result<Config, TapewormError> {
     args.insert(0, "tapeworm");
     let args = args.into_iter().map(|s| String::from(s));
     Config::build(args)
 }
 
 /// Run the `config` and use `io::stdin` for reading any user input.
-pub fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+pub fn run(config: Config) -> Result<(), TapewormError> {
     tapeworm::run(config, io::stdin().lock(), MockYtDlp {})
 }
 
-pub fn run_with<R: BufRead>(config: Config, reader: R) -> Result<(), Box<dyn std::error::Error>> {
+pub fn run_with<R: BufRead>(config: Config, reader: R) -> Result<(), TapewormError> {
     tapeworm::run(config, reader, MockYtDlp {})
 }
 
@@ -3,7 +3,11 @@ mod common;
 use audiotags::Tag;
 use chrono::{Datelike, Utc};
 use common::*;
-use std::{fs, io::BufReader, path::PathBuf};
+use id3::TagLike;
+use std::cell::Cell;
+use std::io::{self, BufReader};
+use std::{env, fs, path::PathBuf};
+use tapeworm::TapewormError;
 
 #[test]
 fn runs_without_command_or_library() {
@@ -25,6 +29,12 @@ fn library_commands_fail_without_library() {
     }
 }
 
+fn assert_not_a_library(result: Result<tapeworm::Config, TapewormError>) {
+    let error = result.unwrap_err();
+    assert!(matches!(error, TapewormError::NotALibrary(_)));
+    assert_eq!(tapeworm::exit_code(&error), 3);
+}
+
 /// Test that tapeworm fails when:
 /// - The alias does not exist
 /// - The library path does not exist
@@ -34,15 +44,15 @@ fn fails_with_non_existing_library() {
     for cmd in ["show", "add", "download", "tag", "deposit", "process"] {
         // Non-existing alias fails
         let alias = format!("{}-not-an-alias", cmd);
-        assert!(build(vec![&alias, cmd]).is_err());
+        assert_not_a_library(build(vec![&alias, cmd]));
 
         // Just the base directory without a ".tapeworm" config folder should be an invalid library
         let lib = Library::new().create_base_folder();
-        assert!(build(vec![lib.arg(), cmd]).is_err());
+        assert_not_a_library(build(vec![lib.arg(), cmd]));
 
         // Non-existing path fails
         let lib = Library::new();
-        assert!(build(vec![lib.arg(), cmd]).is_err());
+        assert_not_a_library(build(vec![lib.arg(), cmd]));
     }
 }
 
@@ -98,6 +108,11 @@ fn clean_removes_empty_directories() {
         assert!(fs::metadata(file).is_ok());
     }
 
+    run(build(vec![lib.arg(), "clean", "--dry-run"]).unwrap()).unwrap();
+    for (folder, _) in &folders {
+        assert!(fs::metadata(folder).is_ok());
+    }
+
     run(build(vec![lib.arg(), "clean"]).unwrap()).unwrap();
     for (folder, keep) in &folders {
         if *keep {
@@ -111,6 +126,24 @@ fn clean_removes_empty_directories() {
     }
 }
 
+#[test]
+fn clean_backs_up_removed_folders() {
+    let lib = Library::new().create_cfg_folder();
+    fs::create_dir_all(lib.base_dir.join("f1").join("f2")).unwrap();
+    fs::create_dir_all(lib.base_dir.join("f3")).unwrap();
+    write(&lib.base_dir.join("f3").join("file"), String::from("test"));
+
+    run(build(vec![lib.arg(), "clean", "--backup"]).unwrap()).unwrap();
+    assert!(fs::metadata(lib.base_dir.join("f1")).is_err());
+
+    let backup_dir = lib.cfg_dir.join("backup");
+    let backups = fs::read_dir(&backup_dir).unwrap().collect::<Vec<_>>();
+    assert_eq!(1, backups.len());
+    let backup = backups[0].as_ref().unwrap().path();
+    assert!(backup.join("f1").join("f2").is_dir());
+    assert!(fs::metadata(backup.join("f3")).is_err());
+}
+
 #[test]
 fn add_fails_without_args() {
     let lib = Library::new().create_cfg_folder();
@@ -174,9 +207,22 @@ fn downloads_and_clears_input() {
 #[test]
 fn fails_tag_on_incorrect_args() {
     let lib = Library::new().create_cfg_folder();
-    assert!(build(vec![lib.arg(), "tag"]).is_err());
-    assert!(build(vec![lib.arg(), "tag", "-i"]).is_err());
-    assert!(build(vec![lib.arg(), "tag", "-i", "uy4hfaif"]).is_err());
+    assert!(matches!(
+        build(vec![lib.arg(), "tag"]),
+        Err(TapewormError::MissingInputDir(Some(_)))
+    ));
+    assert!(matches!(
+        build(vec![lib.arg(), "tag", "-i"]),
+        Err(TapewormError::MissingInputDir(None))
+    ));
+    assert!(matches!(
+        build(vec![lib.arg(), "tag", "-i", "uy4hfaif"]),
+        Err(TapewormError::MissingInputDir(Some(_)))
+    ));
+    assert!(matches!(
+        build(vec![lib.arg(), "unknown"]),
+        Err(TapewormError::UnknownCommand(_))
+    ));
 }
 
 #[test]
@@ -202,6 +248,38 @@ fn tag_skips_unsupported_files() {
     run(build(vec![lib.arg(), "tag", "-i", lib.input_arg()]).unwrap()).unwrap();
 }
 
+#[test]
+fn tag_reports_skipped_files() {
+    let lib = Library::new().create_in_out_folders();
+
+    let files = [
+        "empty_title.mp3",
+        "no_tags.mp3",
+        "no_title.mp3",
+        "not_audio.jpg",
+    ];
+    for file in files {
+        lib.copy_to_input(file);
+    }
+
+    let args = vec![lib.arg(), "tag", "-i", lib.input_arg(), "--report-skips"];
+    run(build(args).unwrap()).unwrap();
+
+    let report = read(&lib.cfg_dir.join("skipped.txt"));
+    assert!(report.contains("No 'title' tag present (2):"));
+    assert!(report.contains("Empty 'title' tag (1):"));
+    for file in files {
+        assert!(report.contains(lib.input_dir.join(file).to_str().unwrap()));
+    }
+}
+
+/// Set the ID3 `TLEN` frame of the mp3 `file`, which is in milliseconds
+fn set_duration(file: &PathBuf, seconds: u32) {
+    let mut tag = id3::Tag::read_from_path(file).unwrap_or_default();
+    tag.set_duration(seconds * 1000);
+    tag.write_to_path(file, id3::Version::Id3v24).unwrap();
+}
+
 fn test_tags(original: &PathBuf, expected: &PathBuf, title: Option<&str>, artist: Option<&str>) {
     assert!(fs::metadata(original).is_err());
     let tag = Tag::new().read_from_path(expected).unwrap();
@@ -293,7 +371,7 @@ fn fails_deposit_on_incorrect_args() {
                     // Succeed only with (not in order):
                     // -i lib_path -o any
                     // -i lib_path -o any -d A-Z
-                    if cfg.input_dir.as_ref().is_some_and(|s| s == &lib.input_dir)
+                    if cfg.input_dirs == [lib.input_dir.clone()]
                         && cfg.target_dir.as_ref().is_some()
                     {
                         run(cfg).unwrap();
@@ -308,7 +386,13 @@ fn fails_deposit_on_incorrect_args() {
     }
 }
 
-fn deposit(mode: &str, filename: &str, az_path: &PathBuf, date_path: &PathBuf) {
+fn deposit(
+    mode: &str,
+    filename: &str,
+    az_path: &PathBuf,
+    date_path: &PathBuf,
+    genre_path: &PathBuf,
+) {
     let lib = Library::new().create_in_out_folders();
     lib.copy_to_input(filename);
 
@@ -316,15 +400,18 @@ fn deposit(mode: &str, filename: &str, az_path: &PathBuf, date_path: &PathBuf) {
     let drop_path = lib.output_dir.join(filename);
     let az_path = lib.output_dir.join(az_path).join(filename);
     let date_path = lib.output_dir.join(date_path).join(filename);
+    let genre_path = lib.output_dir.join(genre_path).join(filename);
     assert!(fs::metadata(&drop_path).is_err());
     assert!(fs::metadata(&az_path).is_err());
     assert!(fs::metadata(&date_path).is_err());
+    assert!(fs::metadata(&genre_path).is_err());
 
     let i = lib.input_arg();
     let o = lib.output_arg();
     let opts = match mode {
         "A-Z" => vec![lib.arg(), "deposit", "-i", i, "-o", o, "-d", "A-Z"],
         "DATE" => vec![lib.arg(), "deposit", "-i", i, "-o", o, "-d", "DATE"],
+        "GENRE" => vec![lib.arg(), "deposit", "-i", i, "-o", o, "-d", "GENRE"],
         _ => vec![lib.arg(), "deposit", "-i", i, "-o", o],
     };
     run(build(opts).unwrap()).unwrap();
@@ -335,16 +422,25 @@ fn deposit(mode: &str, filename: &str, az_path: &PathBuf, date_path: &PathBuf) {
             assert!(fs::metadata(drop_path).is_err());
             assert!(fs::metadata(az_path).is_ok());
             assert!(fs::metadata(date_path).is_err());
+            assert!(fs::metadata(genre_path).is_err());
         }
         "DATE" => {
             assert!(fs::metadata(drop_path).is_err());
             assert!(fs::metadata(az_path).is_err());
             assert!(fs::metadata(date_path).is_ok());
+            assert!(fs::metadata(genre_path).is_err());
+        }
+        "GENRE" => {
+            assert!(fs::metadata(drop_path).is_err());
+            assert!(fs::metadata(az_path).is_err());
+            assert!(fs::metadata(date_path).is_err());
+            assert!(fs::metadata(genre_path).is_ok());
         }
         _ => {
             assert!(fs::metadata(drop_path).is_ok());
             assert!(fs::metadata(az_path).is_err());
             assert!(fs::metadata(date_path).is_err());
+            assert!(fs::metadata(genre_path).is_err());
         }
     }
 }
@@ -355,6 +451,7 @@ fn deposits() {
     let today = Utc::now();
     let today = PathBuf::from(today.year().to_string()).join(format!("{:02}", today.month()));
 
+    let unknown = PathBuf::from("Unknown");
     let files = [
         ("no_tags.mp3", PathBuf::from("N"), &today),
         ("tagged.mp3", PathBuf::from("A").join("Artist"), &today),
@@ -365,10 +462,22 @@ fn deposits() {
         ),
     ];
     for (filename, az_path, date_path) in files {
-        for drop in ["A-Z", "DATE", "x"] {
-            deposit(drop, filename, &az_path, &date_path);
+        for drop in ["A-Z", "DATE", "GENRE", "x"] {
+            deposit(drop, filename, &az_path, &date_path, &unknown);
         }
     }
+
+    // Files with a genre tag go into a folder per genre
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("tagged.mp3");
+    let file = lib.input_dir.join("tagged.mp3");
+    let mut ftag = Tag::new().read_from_path(&file).unwrap();
+    ftag.set_genre("Rock");
+    ftag.write_to_path(file.to_str().unwrap()).unwrap();
+
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    run(build(vec![lib.arg(), "deposit", "-i", i, "-o", o, "-d", "GENRE"]).unwrap()).unwrap();
+    assert!(fs::metadata(lib.output_dir.join("Rock").join("tagged.mp3")).is_ok());
 }
 
 #[test]
@@ -384,3 +493,1353 @@ fn fails_to_process_illegal_commands() {
     assert!(build(vec![lib.arg(), "process", "-s", "process"]).is_err());
     assert!(build(vec![lib.arg(), "process", "-s", "list,process"]).is_err());
 }
+
+#[test]
+fn deposits_by_date_format() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("tagged.mp3");
+
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    let args = vec![
+        lib.arg(),
+        "deposit",
+        "-i",
+        i,
+        "-o",
+        o,
+        "-d",
+        "DATE",
+        "--date-format",
+    ];
+    assert!(build([args.clone(), vec!["%Y/%Q"]].concat()).is_err());
+    run(build([args, vec!["%Y-%m-%d"]].concat()).unwrap()).unwrap();
+
+    let today = Utc::now().format("%Y-%m-%d").to_string();
+    assert!(fs::metadata(lib.output_dir.join(today).join("tagged.mp3")).is_ok());
+}
+
+#[test]
+fn deposits_unknown_artist() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("no_tags.mp3");
+    write(
+        &lib.cfg_dir.join("lib.conf"),
+        String::from("organize=A-Z\nunknown_artist=Unknown Artist\n"),
+    );
+
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    run(build(vec![lib.arg(), "deposit", "-i", i, "-o", o]).unwrap()).unwrap();
+
+    let expected = lib.output_dir.join("U").join("Unknown Artist");
+    assert!(fs::metadata(expected.join("no_tags.mp3")).is_ok());
+}
+
+#[test]
+fn deposits_by_artist() {
+    let lib = Library::new().create_in_out_folders();
+    for file in ["no_tags.mp3", "tagged.mp3", "tagged_album.mp3"] {
+        lib.copy_to_input(file);
+    }
+
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    run(build(vec![lib.arg(), "deposit", "-i", i, "-o", o, "-d", "ARTIST"]).unwrap()).unwrap();
+
+    let artist = lib.output_dir.join("Artist");
+    assert!(fs::metadata(lib.output_dir.join("no_tags.mp3")).is_ok());
+    assert!(fs::metadata(artist.join("tagged.mp3")).is_ok());
+    assert!(fs::metadata(artist.join("Album").join("tagged_album.mp3")).is_ok());
+    assert!(fs::metadata(lib.output_dir.join("A")).is_err());
+}
+
+#[test]
+fn deposits_by_quality() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.flac");
+    lib.copy_to_input("tagged.mp3");
+
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    let args = vec![
+        lib.arg(),
+        "deposit",
+        "-i",
+        i,
+        "-o",
+        o,
+        "-d",
+        "A-Z",
+        "--by-quality",
+    ];
+    run(build(args).unwrap()).unwrap();
+
+    let lossless = lib.output_dir.join("Lossless").join("T");
+    assert!(fs::metadata(lossless.join("title.flac")).is_ok());
+    let lossy = lib.output_dir.join("Lossy").join("A").join("Artist");
+    assert!(fs::metadata(lossy.join("tagged.mp3")).is_ok());
+
+    // Also from lib.conf
+    write(
+        &lib.cfg_dir.join("lib.conf"),
+        String::from("by_quality=true\n"),
+    );
+    lib.copy_to_input("no_tags.mp3");
+    run(build(vec![lib.arg(), "deposit", "-i", i, "-o", o, "-d", "A-Z"]).unwrap()).unwrap();
+    let lossy = lib.output_dir.join("Lossy").join("N");
+    assert!(fs::metadata(lossy.join("no_tags.mp3")).is_ok());
+}
+
+#[test]
+fn deposits_after_tagging() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    let args = vec![
+        lib.arg(),
+        "deposit",
+        "-i",
+        i,
+        "-o",
+        o,
+        "-d",
+        "A-Z",
+        "--tag-first",
+    ];
+    run(build(args).unwrap()).unwrap();
+
+    let expected = lib.output_dir.join("A").join("Artist");
+    let tag = Tag::new().read_from_path(expected.join("Artist - Song [Radio Edit].mp3"));
+    assert_eq!(tag.unwrap().artist(), Some("Artist"));
+}
+
+#[test]
+fn deposits_with_path_template() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("tagged.mp3");
+    lib.copy_to_input("tagged_album.mp3");
+
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    let template = "{album}/{artist} - {year}";
+    let args = vec![
+        lib.arg(),
+        "deposit",
+        "-i",
+        i,
+        "-o",
+        o,
+        "--path-template",
+        template,
+    ];
+    run(build(args).unwrap()).unwrap();
+
+    // Missing tags collapse their path segment
+    assert!(fs::metadata(lib.output_dir.join("Artist").join("tagged.mp3")).is_ok());
+    let expected = lib.output_dir.join("Album").join("Artist");
+    assert!(fs::metadata(expected.join("tagged_album.mp3")).is_ok());
+}
+
+#[test]
+fn refresh_keeps_inputs_without_sources() {
+    let lib = Library::new().create_in_out_folders();
+    run(build(vec![lib.arg(), "add", "Darude Sandstorm"]).unwrap()).unwrap();
+
+    let config = build(vec![lib.arg(), "refresh"]).unwrap();
+    let input_path = config.input_path.clone().unwrap();
+    run(config).unwrap();
+    assert_eq!("ytsearch:Darude Sandstorm\n", read(&input_path));
+}
+
+#[test]
+fn tags_single_file() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    lib.copy_to_input("tagged.mp3");
+
+    assert!(build(vec![lib.arg(), "tag", "--file", "does-not-exist.mp3"]).is_err());
+
+    let old = lib.input_dir.join("title.mp3");
+    let new = lib.input_dir.join("Artist - Song [Radio Edit].mp3");
+    let config = build(vec![
+        lib.arg(),
+        "tag",
+        "-t",
+        "--file",
+        old.to_str().unwrap(),
+    ])
+    .unwrap();
+    run(config).unwrap();
+    test_tags(&old, &new, Some("Song [Radio Edit]"), Some("Artist"));
+    assert!(fs::metadata(lib.input_dir.join("tagged.mp3")).is_ok());
+}
+
+#[test]
+fn resumes_interrupted_tag_run() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    lib.copy_to_input("title.flac");
+
+    // Pretend a previous run already handled the FLAC file before being interrupted
+    let config = build(vec![
+        lib.arg(),
+        "tag",
+        "-t",
+        "--resume",
+        "-i",
+        lib.input_arg(),
+    ])
+    .unwrap();
+    let state_path = config.state_path.clone().unwrap();
+    write(
+        &state_path,
+        String::from("{\"tag_progress\":[\"title.flac\"]}"),
+    );
+    run(config).unwrap();
+
+    let old = lib.input_dir.join("title.mp3");
+    let new = lib.input_dir.join("Artist - Song [Radio Edit].mp3");
+    test_tags(&old, &new, Some("Song [Radio Edit]"), Some("Artist"));
+    assert!(fs::metadata(lib.input_dir.join("title.flac")).is_ok());
+
+    // The run completed, so no progress is left to resume from
+    assert!(!read(&state_path).contains("tag_progress"));
+}
+
+#[test]
+fn resumes_limited_tag_run() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("tagged.mp3");
+    lib.copy_to_input("title.mp3");
+    lib.copy_to_input("title.flac");
+
+    // The handled FLAC file does not count towards the limit
+    let args = vec![
+        lib.arg(),
+        "tag",
+        "-t",
+        "--resume",
+        "--max-files",
+        "1",
+        "-i",
+        lib.input_arg(),
+    ];
+    let config = build(args.clone()).unwrap();
+    let state_path = config.state_path.clone().unwrap();
+    write(
+        &state_path,
+        String::from("{\"tag_progress\":[\"title.flac\"]}"),
+    );
+    run(config).unwrap();
+    assert!(fs::metadata(lib.input_dir.join("title.flac")).is_ok());
+    assert!(fs::metadata(lib.input_dir.join("title.mp3")).is_ok());
+
+    // Files were left out, so the progress is kept for the next run
+    assert!(read(&state_path).contains("title.flac"));
+    run(build(args).unwrap()).unwrap();
+    assert!(fs::metadata(lib.input_dir.join("title.mp3")).is_err());
+    assert!(fs::metadata(lib.input_dir.join("title.flac")).is_ok());
+    assert!(!read(&state_path).contains("tag_progress"));
+}
+
+#[test]
+fn leaves_batches_beyond_threshold_without_terminal() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    lib.copy_to_input("title.flac");
+
+    let mut config = build(vec![lib.arg(), "tag", "-t", "-i", lib.input_arg()]).unwrap();
+    config.confirm_threshold = 1;
+    config.interactive = false;
+    config.require_interactive(config.interactive).unwrap();
+    run(config).unwrap();
+    assert!(fs::metadata(lib.input_dir.join("title.mp3")).is_ok());
+    assert!(fs::metadata(lib.input_dir.join("title.flac")).is_ok());
+}
+
+#[test]
+fn skips_files_by_duration() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    let old = lib.input_dir.join("title.mp3");
+    set_duration(&old, 180);
+
+    let args = vec![
+        lib.arg(),
+        "tag",
+        "-t",
+        "--max-duration",
+        "120",
+        "-i",
+        lib.input_arg(),
+    ];
+    run(build(args).unwrap()).unwrap();
+    assert!(fs::metadata(&old).is_ok());
+
+    let args = vec![
+        lib.arg(),
+        "tag",
+        "-t",
+        "--max-duration",
+        "600",
+        "-i",
+        lib.input_arg(),
+    ];
+    run(build(args).unwrap()).unwrap();
+    assert!(fs::metadata(&old).is_err());
+}
+
+#[test]
+fn backs_up_before_tagging() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+
+    run(build(vec![
+        lib.arg(),
+        "tag",
+        "-t",
+        "--backup",
+        "-i",
+        lib.input_arg(),
+    ])
+    .unwrap())
+    .unwrap();
+    assert!(fs::metadata(lib.input_dir.join("title.mp3")).is_err());
+
+    let backup_dir = lib.cfg_dir.join("backup");
+    let backups = fs::read_dir(&backup_dir).unwrap().collect::<Vec<_>>();
+    assert_eq!(1, backups.len());
+    let backup = backups[0].as_ref().unwrap().path();
+    let relative = lib.input_dir.strip_prefix(&lib.base_dir).unwrap();
+    assert!(fs::metadata(backup.join(relative).join("title.mp3")).is_ok());
+}
+
+#[test]
+fn skips_writing_unchanged_files() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    let new = lib.input_dir.join("Artist - Song [Radio Edit].mp3");
+
+    run(build(vec![lib.arg(), "tag", "-t", "-i", lib.input_arg()]).unwrap()).unwrap();
+    let modified = fs::metadata(&new).unwrap().modified().unwrap();
+
+    // Tagging again proposes the same values, so the file should be left alone
+    run(build(vec![lib.arg(), "tag", "-t", "-i", lib.input_arg()]).unwrap()).unwrap();
+    assert_eq!(modified, fs::metadata(&new).unwrap().modified().unwrap());
+}
+
+#[test]
+fn renames_to_filename_template() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    run(build(vec![lib.arg(), "tag", "-t", "-i", lib.input_arg()]).unwrap()).unwrap();
+    let old = lib.input_dir.join("Artist - Song [Radio Edit].mp3");
+    let new = lib.input_dir.join("Song [Radio Edit].mp3");
+
+    write(
+        &lib.cfg_dir.join("lib.conf"),
+        String::from("filename_template={title}\n"),
+    );
+    run(build(vec![lib.arg(), "rename", "-o", lib.input_arg(), "--list"]).unwrap()).unwrap();
+    assert!(fs::metadata(&old).is_ok());
+
+    run(build(vec![lib.arg(), "rename", "-o", lib.input_arg()]).unwrap()).unwrap();
+    assert!(fs::metadata(&old).is_err());
+    assert!(fs::metadata(&new).is_ok());
+}
+
+#[test]
+fn sets_common_tags() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    lib.copy_to_input("title.flac");
+
+    let args = vec![lib.arg(), "tag", "-t", "-i", lib.input_arg()];
+    let args = [args, vec!["--set", "album=Common", "--set", "year=2020"]].concat();
+    run(build(args).unwrap()).unwrap();
+
+    for ext in ["mp3", "flac"] {
+        let path = lib
+            .input_dir
+            .join(format!("Artist - Song [Radio Edit].{}", ext));
+        let tag = Tag::new().read_from_path(path).unwrap();
+        assert_eq!(tag.album_title(), Some("Common"));
+        assert_eq!(tag.year(), Some(2020));
+    }
+
+    assert!(build(vec![lib.arg(), "tag", "--set", "mood=happy"]).is_err());
+}
+
+#[test]
+fn tags_from_path() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("no_title.mp3");
+    let album_dir = lib.input_dir.join("Band").join("Album");
+    fs::create_dir_all(&album_dir).unwrap();
+    fs::rename(
+        lib.input_dir.join("no_title.mp3"),
+        album_dir.join("Song.mp3"),
+    )
+    .unwrap();
+
+    let args = vec![lib.arg(), "tag", "-t", "-i", lib.input_arg()];
+    run(build([args.clone(), vec!["--from-path"]].concat()).unwrap()).unwrap();
+    assert!(fs::metadata(album_dir.join("Song.mp3")).is_ok()); // Not recursive
+
+    run(build([args, vec!["--recursive", "--from-path"]].concat()).unwrap()).unwrap();
+    let new = album_dir.join("Band - Song.mp3");
+    test_tags(
+        &album_dir.join("Song.mp3"),
+        &new,
+        Some("Song"),
+        Some("Band"),
+    );
+    let tag = Tag::new().read_from_path(&new).unwrap();
+    assert_eq!(tag.album_title(), Some("Album"));
+}
+
+#[test]
+fn uses_custom_lib_dir() {
+    let lib = Library::new().create_base_folder();
+    fs::create_dir_all(lib.base_dir.join(".tw")).unwrap();
+
+    assert!(build(vec![lib.arg(), "show"]).is_err());
+    let config = build(vec![lib.arg(), "show", "--lib-dir", ".tw"]).unwrap();
+    assert_eq!(
+        config.lib_conf_path,
+        Some(lib.base_dir.join(".tw").join("lib.conf"))
+    );
+    let error = build(vec![lib.arg(), "show", "--lib-dir"]).unwrap_err();
+    assert_eq!(tapeworm::exit_code(&error), 2);
+}
+
+#[test]
+fn overrides_verbose_from_cli() {
+    let lib = Library::new().create_in_out_folders();
+    write(
+        &lib.cfg_dir.join("lib.conf"),
+        String::from("verbose=true\n"),
+    );
+
+    let i = lib.input_arg();
+    assert!(build(vec![lib.arg(), "tag", "-i", i]).unwrap().verbose);
+    assert!(
+        !build(vec![lib.arg(), "tag", "--no-verbose", "-i", i])
+            .unwrap()
+            .verbose
+    );
+    assert!(
+        !build(vec![lib.arg(), "tag", "-V", "-i", i])
+            .unwrap()
+            .verbose
+    );
+}
+
+#[test]
+fn trashes_rejected_downloads() {
+    let lib = Library::new().create_in_out_folders();
+    let lib_conf = format!("input_dir={}\n", lib.input_arg());
+    write(&lib.cfg_dir.join("lib.conf"), lib_conf);
+    run(build(vec![lib.arg(), "add", "Darude Sandstorm"]).unwrap()).unwrap();
+
+    // Reject the download, and its query
+    let buffer = Vec::from(b"n\ny\n");
+    let reader: BufReader<&[u8]> = BufReader::new(buffer.as_ref());
+    let config = build(vec![lib.arg(), "download"]).unwrap();
+    let input_path = config.input_path.clone().unwrap();
+    run_with(config, reader).unwrap();
+
+    assert_eq!(0, fs::read_dir(&lib.input_dir).unwrap().count());
+    let trashed = lib.cfg_dir.join("trash").join("0.txt");
+    assert_eq!("ytsearch:Darude Sandstorm", read(&trashed));
+    assert!(read(&input_path).is_empty());
+}
+
+#[test]
+fn adds_structured_inputs() {
+    let lib = Library::new().create_in_out_folders();
+    let csv = lib.cfg_dir.join("queue.csv");
+    write(&csv, String::from("artist,title\nBand,Song\nmalformed\n"));
+    let json = lib.cfg_dir.join("queue.json");
+    write(
+        &json,
+        String::from(r#"[{ "artist": "Other", "title": "Tune" }]"#),
+    );
+
+    let args = vec![
+        lib.arg(),
+        "add",
+        "--input-format",
+        "csv",
+        csv.to_str().unwrap(),
+    ];
+    let config = build(args).unwrap();
+    let input_txt = config.input_path.clone().unwrap();
+    run(config).unwrap();
+    let args = vec![
+        lib.arg(),
+        "add",
+        "--input-format",
+        "json",
+        json.to_str().unwrap(),
+    ];
+    run(build(args).unwrap()).unwrap();
+
+    assert_eq!(
+        "ytsearch:Band Song\nytsearch:Other Tune\n",
+        read(&input_txt)
+    );
+    assert!(build(vec![lib.arg(), "add", "--input-format", "xml", "queue.xml"]).is_err());
+}
+
+#[test]
+fn prunes_satisfied_queries() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    run(build(vec![lib.arg(), "tag", "-t", "-i", lib.input_arg()]).unwrap()).unwrap();
+
+    let config = build(vec![lib.arg(), "add", "Artist - Song", "Other - Tune"]).unwrap();
+    let input_txt = config.input_path.clone().unwrap();
+    run(config).unwrap();
+    let expected = "ytsearch:Artist - Song\nytsearch:Other - Tune\n";
+    assert_eq!(expected, read(&input_txt));
+
+    run(build(vec![lib.arg(), "prune-inputs", "-o", lib.input_arg()]).unwrap()).unwrap();
+    assert_eq!(expected, read(&input_txt));
+
+    let args = vec![lib.arg(), "prune-inputs", "-o", lib.input_arg(), "--remove"];
+    run(build(args).unwrap()).unwrap();
+    assert_eq!("ytsearch:Other - Tune\n", read(&input_txt));
+}
+
+#[test]
+fn adds_checking_duplicates() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("tagged_album.mp3"); // "Artist - Song [Radio Edit]"
+    let args = vec![lib.arg(), "deposit", "-i", lib.input_arg(), "-o", lib.arg()];
+    run(build(args).unwrap()).unwrap();
+
+    let args = vec![
+        lib.arg(),
+        "add",
+        "--check-duplicates",
+        "artist song radio edit",
+        "Other - Tune",
+    ];
+    let config = build(args).unwrap();
+    let input_txt = config.input_path.clone().unwrap();
+    run_with(config, io::empty()).unwrap(); // Declines the likely duplicate
+    assert_eq!("ytsearch:Other - Tune\n", read(&input_txt));
+}
+
+#[test]
+fn checks_duplicates_in_relative_target_dir() {
+    let lib = Library::new().create_in_out_folders();
+    fs::create_dir_all(lib.base_dir.join("music")).unwrap();
+    write(
+        &lib.cfg_dir.join("lib.conf"),
+        String::from("target_dir=music\n"),
+    );
+    lib.copy_to_input("tagged_album.mp3"); // "Artist - Song [Radio Edit]"
+    run(build(vec![lib.arg(), "deposit", "-i", lib.input_arg()]).unwrap()).unwrap();
+
+    let args = vec![
+        lib.arg(),
+        "add",
+        "--check-duplicates",
+        "artist song radio edit",
+    ];
+    let config = build(args).unwrap();
+    assert_eq!(config.target_dir, Some(lib.base_dir.join("music")));
+    let input_txt = config.input_path.clone().unwrap();
+    run_with(config, io::empty()).unwrap(); // Declines the likely duplicate
+    assert!(fs::read_to_string(&input_txt)
+        .unwrap_or_default()
+        .is_empty());
+}
+
+#[test]
+fn deposits_from_multiple_input_dirs() {
+    let lib = Library::new().create_in_out_folders();
+    let other_dir = lib.cfg_dir.join("in2");
+    fs::create_dir_all(&other_dir).unwrap();
+    lib.copy_to_input("title.mp3");
+    fs::rename(lib.input_dir.join("title.mp3"), other_dir.join("title.mp3")).unwrap();
+    lib.copy_to_input("title.flac");
+
+    let input_dirs = env::join_paths([lib.input_dir.clone(), other_dir]).unwrap();
+    let input_dirs = input_dirs.to_str().unwrap();
+    let args = vec![
+        lib.arg(),
+        "deposit",
+        "-i",
+        input_dirs,
+        "-o",
+        lib.output_arg(),
+    ];
+    run(build(args).unwrap()).unwrap();
+
+    assert!(fs::metadata(lib.output_dir.join("title.mp3")).is_ok());
+    assert!(fs::metadata(lib.output_dir.join("title.flac")).is_ok());
+
+    let input_dirs = env::join_paths([lib.input_dir.clone(), PathBuf::from("not-a-dir")]).unwrap();
+    assert!(build(vec![
+        lib.arg(),
+        "deposit",
+        "-i",
+        input_dirs.to_str().unwrap()
+    ])
+    .is_err());
+}
+
+#[test]
+fn keeps_going_past_failed_downloads() {
+    let lib = Library::new().create_in_out_folders();
+    run(build(vec![lib.arg(), "add", "good song", "fail song"]).unwrap()).unwrap();
+
+    let config = build(vec![lib.arg(), "download", "-ac"]).unwrap();
+    let result = tapeworm::run(config, io::stdin().lock(), FailingYtDlp {});
+    assert_eq!(tapeworm::exit_code(&result.unwrap_err()), 4);
+
+    let config = build(vec![lib.arg(), "download", "-ac", "--keep-going"]).unwrap();
+    let input_path = config.input_path.clone().unwrap();
+    tapeworm::run(config, io::stdin().lock(), FailingYtDlp {}).unwrap();
+
+    // Only the failed input is kept, so it can be retried
+    assert_eq!("ytsearch:fail song\n", read(&input_path));
+    assert_eq!(1, fs::read_dir(&lib.input_dir).unwrap().count());
+}
+
+#[test]
+fn retries_failed_downloads() {
+    let lib = Library::new().create_in_out_folders();
+    write(
+        &lib.cfg_dir.join("lib.conf"),
+        String::from("retries=2\nretry_delay_ms=1\n"),
+    );
+    run(build(vec![lib.arg(), "add", "good song"]).unwrap()).unwrap();
+
+    let config = build(vec![lib.arg(), "download", "-ac"]).unwrap();
+    let input_path = config.input_path.clone().unwrap();
+    let downloader = FlakyYtDlp {
+        failures: Cell::new(2),
+    };
+    tapeworm::run(config, io::stdin().lock(), downloader).unwrap();
+    assert_eq!(1, fs::read_dir(&lib.input_dir).unwrap().count());
+    assert!(read(&input_path).is_empty());
+
+    // An input failing all retries is kept, and the pipeline continues
+    run(build(vec![lib.arg(), "add", "fail song"]).unwrap()).unwrap();
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    let args = vec![
+        lib.arg(),
+        "process",
+        "--yes",
+        "-c",
+        "-i",
+        i,
+        "-o",
+        o,
+        "-s",
+        "download,deposit",
+    ];
+    tapeworm::run(build(args).unwrap(), io::stdin().lock(), FailingYtDlp {}).unwrap();
+    assert_eq!("ytsearch:fail song\n", read(&input_path));
+    assert_eq!(0, fs::read_dir(&lib.input_dir).unwrap().count());
+    assert_eq!(1, fs::read_dir(&lib.output_dir).unwrap().count());
+}
+
+#[test]
+fn skips_archived_downloads() {
+    let lib = Library::new().create_in_out_folders();
+    let archive = lib.cfg_dir.join("archive.txt");
+    write(&archive, String::from("youtube dQw4w9WgXcQ\n"));
+    write(
+        &lib.cfg_dir.join("yt-dlp.conf"),
+        format!("--download-archive \"{}\"\n", archive.display()),
+    );
+    let inputs = "https://youtu.be/dQw4w9WgXcQ\nhttps://youtu.be/y6120QOlsfU\n";
+    write(&lib.cfg_dir.join("input.txt"), String::from(inputs));
+
+    run(build(vec![lib.arg(), "download", "-a"]).unwrap()).unwrap();
+
+    let downloaded = fs::read_dir(&lib.input_dir).unwrap().collect::<Vec<_>>();
+    assert_eq!(1, downloaded.len());
+    let contents = read(&downloaded[0].as_ref().unwrap().path());
+    assert_eq!("https://youtu.be/y6120QOlsfU", contents);
+}
+
+#[test]
+fn skips_downloads_in_configured_archive() {
+    let lib = Library::new().create_in_out_folders();
+    write(
+        &lib.cfg_dir.join("archive.txt"),
+        String::from("youtube dQw4w9WgXcQ\n"),
+    );
+    write(
+        &lib.cfg_dir.join("lib.conf"),
+        String::from("download_archive=\n"),
+    );
+    let inputs = "https://youtu.be/dQw4w9WgXcQ\nhttps://youtu.be/y6120QOlsfU\n";
+    write(&lib.cfg_dir.join("input.txt"), String::from(inputs));
+
+    let config = build(vec![lib.arg(), "download", "-a"]).unwrap();
+    assert_eq!(
+        Some(lib.cfg_dir.join("archive.txt")),
+        config.download_archive
+    );
+    run(config).unwrap();
+
+    assert_eq!(1, fs::read_dir(&lib.input_dir).unwrap().count());
+}
+
+#[test]
+fn adds_without_duplicates() {
+    let lib = Library::new().create_cfg_folder();
+    let input_path = lib.cfg_dir.join("input.txt");
+    run(build(vec![lib.arg(), "add", "Darude Sandstorm"]).unwrap()).unwrap();
+    run(build(vec![lib.arg(), "add", "darude sandstorm"]).unwrap()).unwrap();
+    assert_eq!("ytsearch:Darude Sandstorm\n", read(&input_path));
+
+    run(build(vec![
+        lib.arg(),
+        "add",
+        "--allow-duplicates",
+        "darude sandstorm",
+    ])
+    .unwrap())
+    .unwrap();
+    assert_eq!(
+        "ytsearch:Darude Sandstorm\nytsearch:darude sandstorm\n",
+        read(&input_path)
+    );
+}
+
+#[test]
+fn adds_from_file() {
+    let lib = Library::new().create_cfg_folder();
+    let file = lib.cfg_dir.join("queries.txt");
+    write(
+        &file,
+        String::from("# Migrated\nsong one\n\n  https://youtu.be/abc \n"),
+    );
+
+    assert!(build(vec![lib.arg(), "add", "-f"]).is_err());
+    let file = file.to_str().unwrap();
+    run(build(vec![lib.arg(), "add", "-f", file, "song two"]).unwrap()).unwrap();
+    assert_eq!(
+        "ytsearch:song two\nytsearch:song one\nhttps://youtu.be/abc\n",
+        read(&lib.cfg_dir.join("input.txt"))
+    );
+}
+
+#[test]
+fn exports_playlist() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    lib.copy_to_input("no_tags.mp3");
+    lib.copy_to_input("not_audio.jpg");
+    for file in ["title.mp3", "no_tags.mp3", "not_audio.jpg"] {
+        fs::rename(lib.input_dir.join(file), lib.base_dir.join(file)).unwrap();
+    }
+    set_duration(&lib.base_dir.join("no_tags.mp3"), 180);
+
+    assert!(build(vec![lib.arg(), "export"]).is_err());
+    let playlist = lib.base_dir.join("playlist.m3u");
+    let config = build(vec![lib.arg(), "export", "-o", playlist.to_str().unwrap()]).unwrap();
+    run(config).unwrap();
+
+    let contents = read(&playlist);
+    let lines = contents.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0], "#EXTM3U");
+    assert_eq!(lines[1], "#EXTINF:180,no_tags");
+    assert_eq!(lines[2], "no_tags.mp3");
+    assert_eq!(lines[4], "title.mp3");
+}
+
+#[test]
+fn skips_blocked_inputs() {
+    let lib = Library::new().create_cfg_folder();
+    write(
+        &lib.cfg_dir.join("blocklist.txt"),
+        String::from("# Never\nBand X\n"),
+    );
+
+    run(build(vec![lib.arg(), "add", "band x - song", "Other - Song"]).unwrap()).unwrap();
+    assert_eq!(
+        "ytsearch:Other - Song\n",
+        read(&lib.cfg_dir.join("input.txt"))
+    );
+}
+
+#[test]
+fn trashes_blocked_downloads() {
+    let lib = Library::new().create_in_out_folders();
+    let lib_conf = format!("input_dir={}\n", lib.input_arg());
+    write(&lib.cfg_dir.join("lib.conf"), lib_conf);
+    write(&lib.cfg_dir.join("blocklist.txt"), String::from("promo\n"));
+    write(
+        &lib.input_dir.join("Band - Song (PROMO clip).mp3"),
+        String::new(),
+    );
+    run(build(vec![lib.arg(), "add", "Darude Sandstorm"]).unwrap()).unwrap();
+
+    run(build(vec![lib.arg(), "download", "-a"]).unwrap()).unwrap();
+
+    let kept = fs::read_dir(&lib.input_dir).unwrap().collect::<Vec<_>>();
+    assert_eq!(1, kept.len());
+    assert_eq!("0.txt", kept[0].as_ref().unwrap().file_name());
+    let trashed = lib
+        .cfg_dir
+        .join("trash")
+        .join("Band - Song (PROMO clip).mp3");
+    assert!(trashed.exists());
+}
+
+#[test]
+fn removes_from_library() {
+    let lib = Library::new().create_cfg_folder();
+    run(build(vec![lib.arg(), "add", "some song", "https://youtu.be/abc"]).unwrap()).unwrap();
+    let input_path = lib.cfg_dir.join("input.txt");
+
+    assert!(build(vec![lib.arg(), "remove"]).is_err());
+    run(build(vec![lib.arg(), "remove", "some song"]).unwrap()).unwrap();
+    assert_eq!("https://youtu.be/abc\n", read(&input_path));
+
+    run(build(vec![lib.arg(), "add", "-n", "5", "other song"]).unwrap()).unwrap();
+    run(build(vec![lib.arg(), "remove", "other song"]).unwrap()).unwrap();
+    assert_eq!("https://youtu.be/abc\n", read(&input_path));
+
+    run(build(vec![lib.arg(), "remove", "--all"]).unwrap()).unwrap();
+    assert_eq!("", read(&input_path));
+}
+
+#[test]
+fn downloads_latin1_inputs() {
+    let lib = Library::new().create_in_out_folders();
+    fs::write(
+        lib.cfg_dir.join("input.txt"),
+        b"ytsearch:Beyonc\xe9 - D\xe9j\xe0 Vu\n",
+    )
+    .unwrap();
+
+    assert!(run(build(vec![lib.arg(), "download", "-a"]).unwrap()).is_err());
+
+    run(build(vec![lib.arg(), "download", "-a", "--encoding", "latin1"]).unwrap()).unwrap();
+    let downloaded = fs::read_dir(&lib.input_dir).unwrap().collect::<Vec<_>>();
+    let contents = read(&downloaded[0].as_ref().unwrap().path());
+    assert_eq!("ytsearch:Beyonc\u{e9} - D\u{e9}j\u{e0} Vu", contents);
+}
+
+#[test]
+#[cfg(not(feature = "remote-input"))]
+fn input_url_requires_remote_input_feature() {
+    let lib = Library::new().create_in_out_folders();
+    run(build(vec![lib.arg(), "add", "some song"]).unwrap()).unwrap();
+
+    assert!(build(vec![lib.arg(), "download", "--input-url", "not a url"]).is_err());
+
+    let url = "http://localhost/inputs.txt";
+    let config = build(vec![lib.arg(), "download", "-ac", "--input-url", url]).unwrap();
+    let input_path = config.input_path.clone().unwrap();
+    let result = tapeworm::run(config, io::stdin().lock(), FailingYtDlp {});
+    assert_eq!(tapeworm::exit_code(&result.unwrap_err()), 4);
+
+    // The input file is neither read nor cleared
+    assert_eq!("ytsearch:some song\n", read(&input_path));
+}
+
+#[test]
+fn tag_falls_back_to_uploader() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("no_tags.mp3");
+    let file = lib.input_dir.join("no_tags.mp3");
+    let mut ftag = Tag::new().read_from_path(&file).unwrap();
+    ftag.set_title("Some Song");
+    ftag.set_artist("Uploader");
+    ftag.write_to_path(file.to_str().unwrap()).unwrap();
+
+    let lib_conf = "override_artist=true\nuploader_as_artist=true\n";
+    write(&lib.cfg_dir.join("lib.conf"), String::from(lib_conf));
+    run(build(vec![lib.arg(), "tag", "-ti", lib.input_arg()]).unwrap()).unwrap();
+
+    let new = lib.input_dir.join("Uploader - Some Song.mp3");
+    test_tags(&file, &new, Some("Some Song"), Some("Uploader"));
+}
+
+#[test]
+fn deposits_limited_number_of_files() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.flac");
+    lib.copy_to_input("title.mp3");
+
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    assert!(build(vec![lib.arg(), "deposit", "--max-files", "0"]).is_err());
+    let args = vec![lib.arg(), "deposit", "-i", i, "-o", o, "--max-files", "1"];
+    run(build(args).unwrap()).unwrap();
+
+    // Only the first file (sorted by path) is moved
+    assert!(fs::metadata(lib.output_dir.join("title.flac")).is_ok());
+    assert!(fs::metadata(lib.input_dir.join("title.mp3")).is_ok());
+}
+
+#[test]
+fn deposit_keeps_originals() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    run(build(vec![lib.arg(), "deposit", "-i", i, "-o", o, "--keep"]).unwrap()).unwrap();
+
+    assert!(fs::metadata(lib.output_dir.join("title.mp3")).is_ok());
+    assert!(fs::metadata(lib.input_dir.join("title.mp3")).is_ok());
+}
+
+#[test]
+fn deposit_dry_run_moves_nothing() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("tagged.mp3");
+
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    let args = vec![lib.arg(), "deposit", "-i", i, "-o", o, "-d", "A-Z", "-n"];
+    run_with(build(args).unwrap(), io::empty()).unwrap();
+
+    assert!(fs::metadata(lib.input_dir.join("tagged.mp3")).is_ok());
+    assert_eq!(0, fs::read_dir(&lib.output_dir).unwrap().count());
+}
+
+#[test]
+fn deposit_renames_on_conflict() {
+    let lib = Library::new().create_in_out_folders();
+    write(
+        &lib.cfg_dir.join("lib.conf"),
+        String::from("on_conflict=rename\n"),
+    );
+    lib.copy_to_input("title.mp3");
+    write(
+        &lib.output_dir.join("title.mp3"),
+        String::from("Another song"),
+    );
+
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    run_with(
+        build(vec![lib.arg(), "deposit", "-i", i, "-o", o]).unwrap(),
+        io::empty(),
+    )
+    .unwrap();
+
+    assert_eq!(read(&lib.output_dir.join("title.mp3")), "Another song");
+    assert!(fs::metadata(lib.output_dir.join("title (2).mp3")).is_ok());
+}
+
+#[test]
+fn tag_renames_only() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("tagged_album.mp3");
+    lib.copy_to_input("no_title.mp3");
+
+    let args = vec![lib.arg(), "tag", "-i", lib.input_arg(), "--rename-only"];
+    run_with(build(args).unwrap(), io::empty()).unwrap();
+
+    let renamed = lib.input_dir.join("Artist - Song [Radio Edit].mp3");
+    test_tags(
+        &lib.input_dir.join("tagged_album.mp3"),
+        &renamed,
+        Some("Song [Radio Edit]"),
+        Some("Artist"),
+    );
+    assert!(fs::metadata(lib.input_dir.join("no_title.mp3")).is_ok());
+}
+
+#[test]
+fn tag_preserves_mtime() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    let old = lib.input_dir.join("title.mp3");
+    let mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    let file = fs::File::options().write(true).open(&old).unwrap();
+    file.set_modified(mtime).unwrap();
+
+    write(
+        &lib.cfg_dir.join("lib.conf"),
+        String::from("preserve_mtime=true\n"),
+    );
+    run(build(vec![lib.arg(), "tag", "-ti", lib.input_arg()]).unwrap()).unwrap();
+
+    let new = lib.input_dir.join("Artist - Song [Radio Edit].mp3");
+    test_tags(&old, &new, Some("Song [Radio Edit]"), Some("Artist"));
+    assert_eq!(mtime, fs::metadata(&new).unwrap().modified().unwrap());
+}
+
+#[test]
+fn tag_dry_run_writes_nothing() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    let original = lib.input_dir.join("title.mp3");
+    let before = fs::read(&original).unwrap();
+
+    let args = vec![lib.arg(), "tag", "-i", lib.input_arg(), "--dry-run"];
+    run_with(build(args).unwrap(), io::empty()).unwrap();
+
+    assert_eq!(before, fs::read(&original).unwrap());
+    assert_eq!(1, fs::read_dir(&lib.input_dir).unwrap().count());
+}
+
+#[test]
+fn tag_writes_grouping() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    lib.copy_to_input("title.flac");
+
+    let args = vec![
+        lib.arg(),
+        "tag",
+        "-t",
+        "-i",
+        lib.input_arg(),
+        "--set",
+        "grouping=Work",
+    ];
+    run(build(args).unwrap()).unwrap();
+
+    let new = lib.input_dir.join("Artist - Song [Radio Edit]");
+    let tag = id3::Tag::read_from_path(new.with_extension("mp3")).unwrap();
+    assert_eq!(
+        Some("Work"),
+        tag.get("TIT1").and_then(|f| f.content().text())
+    );
+    let tag = metaflac::Tag::read_from_path(new.with_extension("flac")).unwrap();
+    assert_eq!(Some("Work"), tag.get_vorbis("GROUPING").unwrap().next());
+}
+
+#[test]
+fn tag_embeds_lyrics() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    write(
+        &lib.input_dir.join("title.lrc"),
+        String::from("[00:01.00]Some lyrics\n"),
+    );
+
+    run(build(vec![lib.arg(), "tag", "-ti", lib.input_arg(), "--lyrics"]).unwrap()).unwrap();
+
+    let new = lib.input_dir.join("Artist - Song [Radio Edit].mp3");
+    let tag = id3::Tag::read_from_path(new).unwrap();
+    let lyrics = tag.lyrics().next().unwrap();
+    assert_eq!("[00:01.00]Some lyrics", lyrics.text);
+}
+
+#[test]
+fn tags_album_from_folder() {
+    let lib = Library::new().create_in_out_folders();
+    let album_dir = lib.input_dir.join("Some Album");
+    fs::create_dir_all(&album_dir).unwrap();
+    for name in ["b.mp3", "a.mp3"] {
+        lib.copy_to_input("no_title.mp3");
+        fs::rename(lib.input_dir.join("no_title.mp3"), album_dir.join(name)).unwrap();
+    }
+    write(
+        &lib.cfg_dir.join("lib.conf"),
+        String::from("filename_template={track} {title}\n"),
+    );
+
+    let args = vec![lib.arg(), "tag", "-t", "-i", lib.input_arg(), "--recursive"];
+    run(build([args, vec!["--from-path", "--album-from-folder"]].concat()).unwrap()).unwrap();
+
+    for (track, name) in [(1, "1 a.mp3"), (2, "2 b.mp3")] {
+        let tag = Tag::new().read_from_path(album_dir.join(name)).unwrap();
+        assert_eq!(tag.album_title(), Some("Some Album"));
+        assert_eq!(tag.track_number(), Some(track));
+    }
+}
+
+#[test]
+fn uses_custom_input_file_names() {
+    let lib = Library::new().create_cfg_folder();
+    let lib_conf = "input_file=queue.txt\nytdlp_conf=dl.conf\n";
+    write(&lib.cfg_dir.join("lib.conf"), String::from(lib_conf));
+
+    let config = build(vec![lib.arg(), "add", "some song"]).unwrap();
+    assert_eq!(config.yt_dlp_conf_path, Some(lib.cfg_dir.join("dl.conf")));
+    run(config).unwrap();
+
+    assert_eq!("ytsearch:some song\n", read(&lib.cfg_dir.join("queue.txt")));
+    assert!(fs::metadata(lib.cfg_dir.join("input.txt")).is_err());
+}
+
+#[test]
+fn process_writes_report() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    lib.copy_to_input("no_title.mp3");
+    let report = lib.cfg_dir.join("report.json");
+
+    let (i, o) = (lib.input_arg(), lib.output_arg());
+    let args = vec![
+        lib.arg(),
+        "process",
+        "-t",
+        "-i",
+        i,
+        "-o",
+        o,
+        "--report",
+        report.to_str().unwrap(),
+        "-s",
+        "tag,deposit",
+    ];
+    run(build(args).unwrap()).unwrap();
+
+    let report: serde_json::Value = serde_json::from_str(&read(&report)).unwrap();
+    let steps = report["steps"].as_array().unwrap();
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[0]["step"], "tag");
+    assert_eq!(steps[0]["total"], 2);
+    assert_eq!(steps[0]["tagged"], 1);
+    assert_eq!(steps[0]["skipped"], 1);
+    assert_eq!(steps[1]["step"], "deposit");
+    let moved = steps[1]["moved"].as_array().unwrap();
+    assert_eq!(moved.len(), 2);
+    let to = lib.output_dir.join("Artist - Song [Radio Edit].mp3");
+    assert!(moved.iter().any(|m| m["to"] == to.to_str().unwrap()));
+}
+
+#[test]
+fn verifies_required_tags() {
+    let lib = Library::new().create_in_out_folders();
+    let lib_conf = format!("input_dir={}\nrequired_tags=title\n", lib.input_arg());
+    write(&lib.cfg_dir.join("lib.conf"), lib_conf);
+    lib.copy_to_input("title.mp3");
+    lib.copy_to_input("not_audio.jpg");
+    run(build(vec![lib.arg(), "verify"]).unwrap()).unwrap();
+
+    lib.copy_to_input("no_title.mp3");
+    let result = run(build(vec![lib.arg(), "verify"]).unwrap());
+    assert!(matches!(result, Err(TapewormError::Partial(_))));
+    assert!(build(vec![lib.arg(), "verify", "--nope"]).is_err());
+}
+
+#[test]
+fn overflows_artist_folders() {
+    let lib = Library::new().create_in_out_folders();
+    let lib_conf = format!(
+        "input_dir={}\ntarget_dir={}\norganize=A-Z\nper_artist_limit=2\n",
+        lib.input_arg(),
+        lib.output_arg()
+    );
+    write(&lib.cfg_dir.join("lib.conf"), lib_conf);
+    for song in ["Band - A.txt", "Band - B.txt", "Band - C.txt"] {
+        write(&lib.input_dir.join(song), String::from(song));
+    }
+    run(build(vec![lib.arg(), "deposit"]).unwrap()).unwrap();
+
+    // The input files are deposited in no particular order
+    let band = lib.output_dir.join("B").join("Band");
+    let files = |dir: &PathBuf| {
+        let entries = fs::read_dir(dir).unwrap();
+        entries
+            .filter(|e| e.as_ref().unwrap().path().is_file())
+            .count()
+    };
+    assert_eq!(files(&band), 2);
+    assert_eq!(files(&band.join("Part 2")), 1);
+}
+
+#[test]
+fn tag_persists_edited_album() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    lib.copy_to_input("title.flac");
+
+    // Edit the album of the first file and apply it to the rest, then accept the second file
+    let buffer = Vec::from(b"e\nalbum Some Album\nq\na\ny\ny\n");
+    let reader: BufReader<&[u8]> = BufReader::new(buffer.as_ref());
+    let config = build(vec![lib.arg(), "tag", "-i", lib.input_arg()]).unwrap();
+    run_with(config, reader).unwrap();
+
+    for ext in ["mp3", "flac"] {
+        let path = lib
+            .input_dir
+            .join(format!("Artist - Song [Radio Edit].{}", ext));
+        let tag = Tag::new().read_from_path(path).unwrap();
+        assert_eq!(tag.album_title(), Some("Some Album"));
+    }
+}
+
+#[test]
+fn deposit_preserves_subdirs() {
+    let lib = Library::new().create_in_out_folders();
+    let source = lib.input_dir.join("source");
+    fs::create_dir_all(&source).unwrap();
+    write(&source.join("Band - Song.txt"), String::from("song"));
+    write(
+        &lib.input_dir.join("Band - Other.txt"),
+        String::from("other"),
+    );
+
+    let args = vec![
+        lib.arg(),
+        "deposit",
+        "-i",
+        lib.input_arg(),
+        "-o",
+        lib.output_arg(),
+    ];
+    run(build([args, vec!["-d", "A-Z", "--preserve-subdir"]].concat()).unwrap()).unwrap();
+
+    assert_eq!(
+        "song",
+        read(&lib.output_dir.join("source").join("Band - Song.txt"))
+    );
+    assert_eq!("other", read(&lib.output_dir.join("Band - Other.txt")));
+    assert!(fs::metadata(lib.output_dir.join("B")).is_err());
+}
+
+#[test]
+fn tag_accepts_all_remaining() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("title.mp3");
+    lib.copy_to_input("title.flac");
+
+    // Only the first proposal is prompted for
+    let buffer = Vec::from(b"a\n");
+    let reader: BufReader<&[u8]> = BufReader::new(buffer.as_ref());
+    let config = build(vec![lib.arg(), "tag", "-i", lib.input_arg()]).unwrap();
+    run_with(config, reader).unwrap();
+
+    for ext in ["mp3", "flac"] {
+        let path = lib
+            .input_dir
+            .join(format!("Artist - Song [Radio Edit].{}", ext));
+        assert!(fs::metadata(path).is_ok());
+    }
+}
+
+#[test]
+fn records_history() {
+    let lib = Library::new().create_in_out_folders();
+    run(build(vec![lib.arg(), "history"]).unwrap()).unwrap(); // No history yet
+
+    write(&lib.input_dir.join("Band - Song.txt"), String::from("song"));
+    let args = vec![
+        lib.arg(),
+        "deposit",
+        "-i",
+        lib.input_arg(),
+        "-o",
+        lib.output_arg(),
+    ];
+    run(build([args.clone(), vec!["--dry-run"]].concat()).unwrap()).unwrap();
+    assert!(fs::metadata(lib.cfg_dir.join("history.log")).is_err());
+    run(build(args).unwrap()).unwrap();
+
+    let history = read(&lib.cfg_dir.join("history.log"));
+    let fields = history.trim_end().split('\t').collect::<Vec<&str>>();
+    assert_eq!(fields[1], "deposit");
+    assert!(fields[2].starts_with("1 files moved to "));
+
+    run(build(vec![lib.arg(), "history", "--limit", "1"]).unwrap()).unwrap();
+    assert!(build(vec![lib.arg(), "history", "--limit", "0"]).is_err());
+}
+
+#[test]
+fn undoes_last_deposit() {
+    let lib = Library::new().create_in_out_folders();
+    run(build(vec![lib.arg(), "undo"]).unwrap()).unwrap(); // Nothing to undo
+
+    let song = lib.input_dir.join("Band - Song.txt");
+    write(&song, String::from("song"));
+    let args = vec![
+        lib.arg(),
+        "deposit",
+        "-i",
+        lib.input_arg(),
+        "-o",
+        lib.output_arg(),
+    ];
+    run(build([args, vec!["-d", "A-Z"]].concat()).unwrap()).unwrap();
+    let deposited = lib
+        .output_dir
+        .join("B")
+        .join("Band")
+        .join("Band - Song.txt");
+    assert!(fs::metadata(&song).is_err());
+
+    // Refuse when another file took the original place
+    write(&song, String::from("other"));
+    assert!(run(build(vec![lib.arg(), "undo"]).unwrap()).is_err());
+    assert_eq!("song", read(&deposited));
+
+    fs::remove_file(&song).unwrap();
+    run(build(vec![lib.arg(), "undo"]).unwrap()).unwrap();
+    assert_eq!("song", read(&song));
+    assert!(fs::metadata(&deposited).is_err());
+    assert!(fs::metadata(lib.cfg_dir.join("last_deposit.json")).is_err());
+}
+
+#[test]
+fn deposits_album_without_artist_by_letter() {
+    let lib = Library::new().create_in_out_folders();
+    lib.copy_to_input("no_tags.mp3");
+    let file = lib.input_dir.join("no_tags.mp3");
+    let mut tag = Tag::new().read_from_path(&file).unwrap();
+    tag.set_album_title("Album");
+    tag.write_to_path(file.to_str().unwrap()).unwrap();
+
+    let args = vec![
+        lib.arg(),
+        "deposit",
+        "-i",
+        lib.input_arg(),
+        "-o",
+        lib.output_arg(),
+    ];
+    run(build([args, vec!["-d", "A-Z"]].concat()).unwrap()).unwrap();
+
+    // Without an artist folder, there is no album folder either
+    assert!(fs::metadata(lib.output_dir.join("N").join("no_tags.mp3")).is_ok());
+    assert!(fs::metadata(lib.output_dir.join("N").join("Album")).is_err());
+}
+
+#[test]
+fn undo_ignores_deposits_before_a_copy() {
+    let lib = Library::new().create_in_out_folders();
+    let args = vec![
+        lib.arg(),
+        "deposit",
+        "-i",
+        lib.input_arg(),
+        "-o",
+        lib.output_arg(),
+    ];
+    write(&lib.input_dir.join("Band - Song.txt"), String::from("song"));
+    run(build(args.clone()).unwrap()).unwrap();
+
+    // The copy moves nothing, so the first deposit can no longer be undone
+    write(
+        &lib.input_dir.join("Band - Other.txt"),
+        String::from("other"),
+    );
+    run(build([args, vec!["--keep"]].concat()).unwrap()).unwrap();
+    run(build(vec![lib.arg(), "undo"]).unwrap()).unwrap();
+
+    assert_eq!("song", read(&lib.output_dir.join("Band - Song.txt")));
+    assert!(fs::metadata(lib.input_dir.join("Band - Song.txt")).is_err());
+}
+
+#[test]
+fn deposits_when_history_cannot_be_written() {
+    let lib = Library::new().create_in_out_folders();
+    fs::create_dir_all(lib.cfg_dir.join("history.log")).unwrap();
+    write(&lib.input_dir.join("Band - Song.txt"), String::from("song"));
+
+    let args = vec![
+        lib.arg(),
+        "deposit",
+        "-i",
+        lib.input_arg(),
+        "-o",
+        lib.output_arg(),
+    ];
+    run(build(args).unwrap()).unwrap();
+    run(build(vec![lib.arg(), "undo"]).unwrap()).unwrap();
+    assert_eq!("song", read(&lib.input_dir.join("Band - Song.txt")));
+}